@@ -0,0 +1,78 @@
+//! Setup shared by the examples
+use projet_long_3d_gaussian_splatting::{
+    renderer::{Background, Configuration, DepthSorting, Renderer, SplatLayout},
+    scene::Scene,
+};
+
+/// Requests a device with the limits the renderer needs for large scenes
+pub async fn request_device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        })
+        .await
+        .expect("No suitable GPU adapters found on the system!");
+    let required_features = wgpu::Features::default();
+    let required_limits = wgpu::Limits {
+        max_compute_invocations_per_workgroup: 1024,
+        max_storage_buffer_binding_size: 1024 * 1024 * 1024,
+        max_buffer_size: 1024 * 1024 * 1024,
+        ..wgpu::Limits::default()
+    };
+    let adapter_features = adapter.features();
+    assert!(
+        adapter_features.contains(required_features),
+        "Adapter does not support required features: {:?}",
+        required_features - adapter_features
+    );
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: required_features,
+                limits: required_limits,
+            },
+            None,
+        )
+        .await
+        .expect("Unable to find a suitable GPU adapter!")
+}
+
+/// Renderer configuration for offscreen images of the given size, without a window surface
+pub fn configuration(width: u32, height: u32) -> Configuration {
+    Configuration {
+        surface_configuration: wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            view_formats: vec![wgpu::TextureFormat::Rgba8Unorm],
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        },
+        depth_sorting: DepthSorting::Gpu,
+        use_covariance_for_scale: true,
+        use_unaligned_rectangles: true,
+        spherical_harmonics_order: 3,
+        max_splat_count: 1024 * 1024 * 4,
+        radix_bits_per_digit: 8,
+        frustum_culling_tolerance: 1.5,
+        ellipse_margin: 4.0,
+        splat_scale: 1.0,
+        splat_layout: SplatLayout::Full,
+        background: Background::Transparent,
+    }
+}
+
+/// Loads all splats of a file into a new scene
+pub fn load_scene(device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer, path: &str) -> Scene {
+    let (file_header, mut file) = Scene::open_file(path).expect("Invalid splat file");
+    let mut scene = Scene::new(device, renderer, file_header.splat_count).expect("Too many splats");
+    scene
+        .load_chunk(queue, &mut file, &file_header, 0..file_header.splat_count)
+        .expect("Failed to load the splats");
+    scene
+}
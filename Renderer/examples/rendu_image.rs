@@ -1,10 +1,12 @@
-mod read_cam;
 
-use projetLong3DGaussianSplatting::{
-    renderer::{Configuration, DepthSorting, Renderer},
-    scene::Scene,
+mod common;
+
+use projet_long_3d_gaussian_splatting::{
+    camera::Camera,
+    colmap,
+    offscreen::OffscreenTarget,
+    renderer::{Background, Configuration, Renderer},
 };
-use std::fs::File;
 
 /*
 Function which, given the path of the binary files, 
@@ -12,156 +14,47 @@ the ply file and a directory, generates the images corresponding to
 those used to generate the ply file.
 */
 async fn run(path_bin:&str,path_ply :&str, output_path : &str) {
-    //open the ply file
-    let file = File::open(path_ply).unwrap();
-
-    //handle to the gpu
-    let instance = wgpu::Instance::default();
-    let adapter = instance
-    .request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: None,
-    })
-    .await
-    .expect("No suitable GPU adapters found on the system!");
-    let required_features = wgpu::Features::default();
-    let required_limits = wgpu::Limits {
-        max_compute_invocations_per_workgroup: 1024,
-        max_storage_buffer_binding_size: 1024 * 1024 * 1024,
-        max_buffer_size: 1024 * 1024 * 1024,
-        ..wgpu::Limits::default()
-    };
-    let adapter_features = adapter.features();
-    assert!(
-        adapter_features.contains(required_features),
-        "Adapter does not support required features: {:?}",
-        required_features - adapter_features
-    );
-    let (device, mut queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: required_features,
-                limits: required_limits,
-            },
-            None,
-        )
-        .await
-        .expect("Unable to find a suitable GPU adapter!");
+    let (device, queue) = common::request_device().await;
 
     // read the intrinsics and extrinsics parameters
-    let (cameras_intrinsics,cameras_extrinsic) = read_cam::read_colmap_scene_info(path_bin);
-    let camera = cameras_intrinsics.get(&(1 as u64)).unwrap();
-    let width = if camera.width % 64 == 0{ camera.width as u32}else {64 * (camera.width as f32/64.0).round() as u32};
+    let (cameras_intrinsics,cameras_extrinsic) = colmap::read_scene_info(path_bin).expect("Failed to read the COLMAP files");
+    let camera = cameras_intrinsics.get(&1).unwrap();
+    let width = camera.width as u32;
     let height = camera.height as u32;
 
-    //initiate the surface
-    let texture = init_output_texture(&device, width,height);  //size.width
-    let surface_configuration = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: wgpu::TextureFormat::Rgba8Unorm, 
-        view_formats: vec![wgpu::TextureFormat::Rgba8Unorm],
-        width: width,
-        height: height,
-        present_mode: wgpu::PresentMode::Fifo,
-        alpha_mode: wgpu::CompositeAlphaMode::Opaque, 
-    };
-
     //intiat the renderer
     let renderer = Renderer::new(
         &device,
         Configuration {
-            surface_configuration: surface_configuration.clone(),
-            depth_sorting: DepthSorting::Gpu,
-            use_covariance_for_scale: true,
-            use_unaligned_rectangles: true,
             spherical_harmonics_order: 2,
-            max_splat_count: 1024 * 1024 * 4,
-            radix_bits_per_digit: 8,
-            frustum_culling_tolerance: 1.5,
-            ellipse_margin: 4.0,
-            splat_scale: 1.0,
+            background: Background::Color(wgpu::Color::BLACK),
+            ..common::configuration(width, height)
         },
     );
 
-    // lecture du fichier 
-    let (file_header_size, splat_count, mut file) = Scene::parse_file_header(file);
-    //creation de la scene
-    let mut scene = Scene::new(&device, &renderer, splat_count);
-    
     // load the spalt
-    scene.load_chunk(&queue, &mut file, file_header_size, 0..splat_count);
+    let scene = common::load_scene(&device, &queue, &renderer, path_ply);
 
-    let viewport_size = wgpu::Extent3d {
-        width: width,
-        height: height,
-        depth_or_array_layers: 1,};
+    // texture rendered into and read back
+    let target = OffscreenTarget::new(&device, &renderer, width, height);
 
     // render the images
     println!("rendering");
     for i in cameras_extrinsic.keys() {
         println!("Image {}",i);
-        let (world_view_transform,projection_matrix,camera_center,FoVy,FoVx) = read_cam::compute_matrix(&cameras_intrinsics,&cameras_extrinsic ,i );
-        let output_buffer = renderer.render_frame( &device, &mut queue, &texture, viewport_size,&scene, world_view_transform,projection_matrix,camera_center,FoVy,FoVx);
+        let image = &cameras_extrinsic[i];
+        let camera = Camera::from_colmap(&cameras_intrinsics[&(image.camera_id as u64)], image).expect("Unsupported camera");
+        let rendered_image = target
+            .render_to_image(&device, &queue, &renderer, &scene, &camera)
+            .expect("Failed to read the frame back");
 
-        let img = to_image(&device, &output_buffer, texture.width(), texture.height()).await;
-
-        img.save(format!("{}/image{}.jpg",output_path, i)).unwrap();
+        rendered_image.color.save(format!("{}/image{}.jpg",output_path, i)).unwrap();
     }
     print!("ok");
 
 }
 
 
-fn init_output_texture(device: &wgpu::Device, width : u32, height : u32 ) -> wgpu::Texture {
-    let texture_desc = wgpu::TextureDescriptor {
-        label: Some("output_texture"),
-        // The texture size. (layers is set to 1)
-        size: wgpu::Extent3d {
-            width: width,
-            height: height,
-            depth_or_array_layers: 1,
-        },
-        dimension: wgpu::TextureDimension::D2,
-        mip_level_count: 1, // the number of mip levels the texture will contain
-        sample_count: 1,    // sample_count > 1 would indicate a multisampled texture
-        // Use RGBA format for the output
-        format: wgpu::TextureFormat::Rgba8Unorm,
-        // RENDER_ATTACHMENT -> so that the GPU can render to the texture
-        // COPY_SRC -> so that we can pull data out of the texture
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        // Specify the allowed formats when calling "texture.create_view()"
-        view_formats: &[], //&[wgpu::TextureFormat::Rgba8Unorm],
-    };
-    device.create_texture(&texture_desc)
-}
-
-async fn to_image(
-    device: &wgpu::Device,
-    texture_buffer: &wgpu::Buffer,
-    width: u32,
-    height: u32,
-) -> image::RgbaImage {
-    let buffer_slice = texture_buffer.slice(..);
-
-    // NOTE: We have to create the mapping THEN device.poll() before await the future.
-    // Otherwise the application will freeze.
-    let (tx, rx) = oneshot::channel();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-        tx.send(result).unwrap();
-    });
-    device.poll(wgpu::Maintain::Wait);
-    rx.await.unwrap().unwrap();
-
-    // Synchronously and immediately map a buffer for reading.
-    // Will panic if buffer_slice.map_async() did not finish yet.
-    let data = buffer_slice.get_mapped_range();
-
-    image::RgbaImage::from_raw(width, height, Vec::from(&data as &[u8])).unwrap()
-}
-
-
 fn main() {
     pollster::block_on(run("C:\\3DGS\\gaussian-splatting\\tandt_db\\lefaucheux_7mm","point_cloud_gun.ply","test2\\"));
     //pollster::block_on(run("C:\\3DGS\\gaussian-splatting\\tandt_db\\tandt\\train"));
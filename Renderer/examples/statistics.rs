@@ -0,0 +1,20 @@
+mod common;
+
+use projet_long_3d_gaussian_splatting::renderer::Renderer;
+
+/*
+Prints the statistics of a splat file, given as the first argument.
+*/
+async fn run(path_ply: &str) {
+    let (device, queue) = common::request_device().await;
+    // nothing is rendered, the surface configuration only sets up the renderer
+    let renderer = Renderer::new(&device, common::configuration(64, 64));
+    let scene = common::load_scene(&device, &queue, &renderer, path_ply);
+
+    println!("{}", scene.statistics());
+}
+
+fn main() {
+    let path_ply = std::env::args().nth(1).expect("Usage: statistics <splat file>");
+    pollster::block_on(run(&path_ply));
+}
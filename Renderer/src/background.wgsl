@@ -0,0 +1,33 @@
+// Blends the background behind the splats, like a splat at infinity which covers the whole frame
+struct BackgroundUniforms {
+    color: vec4<f32>,
+    use_texture: u32,
+}
+
+@group(0) @binding(0) var<uniform> background: BackgroundUniforms;
+@group(0) @binding(1) var background_texture: texture_2d<f32>;
+@group(0) @binding(2) var background_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) gl_Position: vec4<f32>,
+    @location(0) gl_TexCoord: vec2<f32>,
+}
+
+// A triangle covering the frame, texture coordinates start at the top left corner
+@vertex
+fn backgroundVertex(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var stage_out: VertexOutput;
+    let clip_space_position = vec2<f32>(f32(vertex_index & 1u) * 4.0 - 1.0, f32(vertex_index >> 1u) * 4.0 - 1.0);
+    stage_out.gl_Position = vec4<f32>(clip_space_position, 0.0, 1.0);
+    stage_out.gl_TexCoord = vec2<f32>(0.5, -0.5) * clip_space_position + vec2<f32>(0.5);
+    return stage_out;
+}
+
+@fragment
+fn backgroundFragment(stage_in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = background.color;
+    if(background.use_texture != 0u) {
+        color = textureSample(background_texture, background_sampler, stage_in.gl_TexCoord);
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
@@ -0,0 +1,411 @@
+//! Pinhole camera model deriving the matrices the renderer needs
+use crate::{colmap, transform::rotation_matrix_f64};
+use geometric_algebra::ppga3d::Point;
+
+/// Near plane distance used by the reference implementation of 3DGS
+pub const DEFAULT_NEAR: f64 = 0.001;
+/// Far plane distance used by the reference implementation of 3DGS
+pub const DEFAULT_FAR: f64 = 100.0;
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(vector: [f64; 3]) -> [f64; 3] {
+    let length = dot(vector, vector).sqrt();
+    vector.map(|component| component / length)
+}
+
+fn transpose(matrix: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| matrix[column][row]))
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|index| a[row][index] * b[index][column]).sum()))
+}
+
+fn transform(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|row| dot(matrix[row], vector))
+}
+
+/// Row major rotation by `angle` radians around the unit vector `axis`, by Rodrigues' formula
+fn axis_angle_rotation(axis: [f64; 3], angle: f64) -> [[f64; 3]; 3] {
+    let (sin, cos) = angle.sin_cos();
+    let [x, y, z] = axis;
+    let c = 1.0 - cos;
+    [
+        [cos + x * x * c, x * y * c - z * sin, x * z * c + y * sin],
+        [y * x * c + z * sin, cos + y * y * c, y * z * c - x * sin],
+        [z * x * c - y * sin, z * y * c + x * sin, cos + z * z * c],
+    ]
+}
+
+/// Column major 4x4 matrix of a rotation followed by a translation
+fn affine_matrix(rotation: &[[f64; 3]; 3], translation: [f64; 3]) -> [Point; 4] {
+    let column = |index: usize| Point::new(rotation[0][index] as f32, rotation[1][index] as f32, rotation[2][index] as f32, 0.0);
+    [
+        column(0),
+        column(1),
+        column(2),
+        Point::new(translation[0] as f32, translation[1] as f32, translation[2] as f32, 1.0),
+    ]
+}
+
+/// Intrinsics and pose of a pinhole camera, in the conventions of COLMAP
+///
+/// The view space has x pointing right, y pointing down and the camera looking along z.
+/// Focal lengths and the principal point are in pixels, with the origin in the top left corner of the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    pub width: u32,
+    pub height: u32,
+    pub near: f64,
+    pub far: f64,
+    /// World to view rotation, row major, so the rows are the view axes in world space
+    pub rotation: [[f64; 3]; 3],
+    /// World to view translation
+    pub translation: [f64; 3],
+}
+
+impl Camera {
+    /// Camera of a registered COLMAP image
+    ///
+    /// The SIMPLE_PINHOLE and PINHOLE models are supported, the only ones [colmap::read_intrinsics_binary] reads.
+    /// Fails with [colmap::ColmapError::MissingParameters] if the camera has fewer than three parameters.
+    pub fn from_colmap(camera: &colmap::Camera, image: &colmap::Image) -> Result<Self, colmap::ColmapError> {
+        let (fx, fy, cx, cy) = match camera.params[..] {
+            [f, cx, cy] => (f, f, cx, cy),
+            [fx, fy, cx, cy, ..] => (fx, fy, cx, cy),
+            _ => {
+                return Err(colmap::ColmapError::MissingParameters {
+                    camera_id: camera.id,
+                    model: camera.model.clone(),
+                    count: camera.params.len(),
+                })
+            }
+        };
+        Ok(Self {
+            fx,
+            fy,
+            cx,
+            cy,
+            width: camera.width as u32,
+            height: camera.height as u32,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            rotation: rotation_matrix_f64(image.qvec),
+            translation: image.tvec,
+        })
+    }
+
+    /// Camera at the origin looking along z, with square pixels and the principal point in the center of the image
+    pub fn from_field_of_view(field_of_view_y: f64, width: u32, height: u32) -> Self {
+        let focal_length = height as f64 / (2.0 * (field_of_view_y * 0.5).tan());
+        Self {
+            fx: focal_length,
+            fy: focal_length,
+            cx: width as f64 * 0.5,
+            cy: height as f64 * 0.5,
+            width,
+            height,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            translation: [0.0; 3],
+        }
+    }
+
+    /// Like [Camera::from_field_of_view], placed at `eye` and looking at `target`, see [Camera::look_at]
+    pub fn from_look_at(eye: [f64; 3], target: [f64; 3], up: [f64; 3], field_of_view_y: f64, width: u32, height: u32) -> Self {
+        let mut camera = Self::from_field_of_view(field_of_view_y, width, height);
+        camera.look_at(eye, target, up);
+        camera
+    }
+
+    /// Places the camera at `eye` looking at `target`, with the top of the image towards `up`
+    pub fn look_at(&mut self, eye: [f64; 3], target: [f64; 3], up: [f64; 3]) {
+        let forward = normalize([0, 1, 2].map(|axis| target[axis] - eye[axis]));
+        let right = normalize(cross(forward, up));
+        let down = cross(forward, right);
+        self.set_pose([right, down, forward], eye);
+    }
+
+    /// Rotates the camera around `pivot`, by `yaw` radians around its up axis and then by `pitch` radians around its right axis
+    ///
+    /// Both rotations are right-handed. A pivot in the center of the image stays there.
+    pub fn orbit(&mut self, pivot: [f64; 3], yaw: f64, pitch: f64) {
+        let up = self.rotation[1].map(|component| -component);
+        let rotation = multiply(&axis_angle_rotation(self.rotation[0], pitch), &axis_angle_rotation(up, yaw));
+        let offset = transform(&rotation, [0, 1, 2].map(|axis| self.position()[axis] - pivot[axis]));
+        // The view axes turn along with the position
+        let view_axes = multiply(&self.rotation, &transpose(&rotation));
+        self.set_pose(view_axes, [0, 1, 2].map(|axis| pivot[axis] + offset[axis]));
+    }
+
+    /// Turns the camera in place like [Camera::orbit], then moves it by `offset` given in view space
+    pub fn fly(&mut self, offset: [f64; 3], yaw: f64, pitch: f64) {
+        let position = self.position();
+        self.orbit(position, yaw, pitch);
+        let offset = transform(&transpose(&self.rotation), offset);
+        self.set_pose(self.rotation, [0, 1, 2].map(|axis| position[axis] + offset[axis]));
+    }
+
+    fn set_pose(&mut self, rotation: [[f64; 3]; 3], position: [f64; 3]) {
+        self.rotation = rotation;
+        self.translation = transform(&rotation, position).map(|component| -component);
+    }
+
+    /// Center of projection in world space
+    pub fn position(&self) -> [f64; 3] {
+        transform(&transpose(&self.rotation), self.translation).map(|component| -component)
+    }
+
+    /// Size of the rendered image
+    pub fn viewport_size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Horizontal field of view in radians
+    pub fn field_of_view_x(&self) -> f64 {
+        2.0 * (self.width as f64 / (2.0 * self.fx)).atan()
+    }
+
+    /// Vertical field of view in radians
+    pub fn field_of_view_y(&self) -> f64 {
+        2.0 * (self.height as f64 / (2.0 * self.fy)).atan()
+    }
+
+    /// Tangents of the half fields of view, the half extents of the image plane at distance 1
+    pub fn view_size(&self) -> [f32; 2] {
+        [
+            (self.width as f64 / (2.0 * self.fx)) as f32,
+            (self.height as f64 / (2.0 * self.fy)) as f32,
+        ]
+    }
+
+    /// World to view matrix
+    pub fn view_matrix(&self) -> [Point; 4] {
+        affine_matrix(&self.rotation, self.translation)
+    }
+
+    /// View to world matrix, the inverse of [Camera::view_matrix]
+    pub fn camera_matrix(&self) -> [Point; 4] {
+        affine_matrix(&transpose(&self.rotation), self.position())
+    }
+
+    /// Position of the image center on the image plane at distance 1, zero if the principal point is centered
+    pub fn view_offset(&self) -> [f32; 2] {
+        [
+            ((self.width as f64 * 0.5 - self.cx) / self.fx) as f32,
+            ((self.height as f64 * 0.5 - self.cy) / self.fy) as f32,
+        ]
+    }
+
+    /// View to clip space matrix, mapping depths between the near and far planes to [0, 1]
+    ///
+    /// The frustum is asymmetric if the principal point is not centered.
+    /// As clip space y points up while image rows go down, view space y is flipped.
+    pub fn projection_matrix(&self) -> [Point; 4] {
+        let depth_scale = self.far / (self.far - self.near);
+        [
+            Point::new((2.0 * self.fx / self.width as f64) as f32, 0.0, 0.0, 0.0),
+            Point::new(0.0, (-2.0 * self.fy / self.height as f64) as f32, 0.0, 0.0),
+            Point::new(
+                (2.0 * self.cx / self.width as f64 - 1.0) as f32,
+                (1.0 - 2.0 * self.cy / self.height as f64) as f32,
+                depth_scale as f32,
+                1.0,
+            ),
+            Point::new(0.0, 0.0, (-depth_scale * self.near) as f32, 0.0),
+        ]
+    }
+
+    /// World to clip space matrix
+    pub fn view_projection_matrix(&self) -> [Point; 4] {
+        crate::utils::mat4_multiplication(&self.projection_matrix(), &self.view_matrix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// The matrices of `compute_matrix` for the only image of the test scene
+    fn reference_matrices(camera: &colmap::Camera, image: &colmap::Image) -> ([Point; 4], [Point; 4], [Point; 4], f64, f64) {
+        let cameras_intrinsics = HashMap::from([(camera.id, camera.clone())]);
+        let cameras_extrinsic = HashMap::from([(image.id, image.clone())]);
+        crate::read_cam::compute_matrix(&cameras_intrinsics, &cameras_extrinsic, &image.id)
+    }
+
+    fn assert_matrix_eq(matrix: [Point; 4], expected: [Point; 4]) {
+        for column in 0..4 {
+            for row in 0..4 {
+                let (value, expected) = (matrix[column][row], expected[column][row]);
+                assert!(
+                    (value - expected).abs() <= 1.0e-5 * expected.abs().max(1.0),
+                    "{} != {} at column {} row {}",
+                    value,
+                    expected,
+                    column,
+                    row
+                );
+            }
+        }
+    }
+
+    fn test_image() -> (colmap::Camera, colmap::Image) {
+        let norm = (0.9f64 * 0.9 + 0.2 * 0.2 + 0.3 * 0.3 + 0.25 * 0.25).sqrt();
+        (
+            colmap::Camera {
+                id: 1,
+                model: "PINHOLE".to_string(),
+                width: 1959,
+                height: 1090,
+                params: vec![1159.5, 1164.7, 979.5, 545.0],
+            },
+            colmap::Image {
+                id: 3,
+                camera_id: 1,
+                qvec: [0.9, 0.2, -0.3, 0.25].map(|component| component / norm),
+                tvec: [0.5, -1.2, 3.4],
+                name: "00003.jpg".to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn colmap_matrices_match_the_reference() {
+        let (colmap_camera, image) = test_image();
+        let camera = Camera::from_colmap(&colmap_camera, &image).unwrap();
+        let (view_matrix, mut projection_matrix, camera_matrix, fo_vy, fo_vx) = reference_matrices(&colmap_camera, &image);
+        // The principal point of the test camera is centered, only the flip of y differs
+        projection_matrix[1][1] = -projection_matrix[1][1];
+        assert_matrix_eq(camera.view_matrix(), view_matrix);
+        assert_matrix_eq(camera.projection_matrix(), projection_matrix);
+        assert_matrix_eq(camera.camera_matrix(), camera_matrix);
+        assert!((camera.field_of_view_y() - fo_vy).abs() < 1.0e-12);
+        assert!((camera.field_of_view_x() - fo_vx).abs() < 1.0e-12);
+        let view_size = camera.view_size();
+        assert!((view_size[0] - (fo_vx * 0.5).tan() as f32).abs() < 1.0e-6);
+        assert!((view_size[1] - (fo_vy * 0.5).tan() as f32).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn colmap_cameras_need_three_parameters() {
+        let (mut colmap_camera, image) = test_image();
+        colmap_camera.params.truncate(2);
+        assert!(matches!(
+            Camera::from_colmap(&colmap_camera, &image),
+            Err(colmap::ColmapError::MissingParameters { camera_id: 1, count: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn off_center_principal_point() {
+        let (mut colmap_camera, image) = test_image();
+        colmap_camera.params[2] = 1100.0;
+        colmap_camera.params[3] = 500.0;
+        let camera = Camera::from_colmap(&colmap_camera, &image).unwrap();
+        let view_projection_matrix = camera.view_projection_matrix();
+        let view_offset = camera.view_offset();
+        let view_size = camera.view_size();
+        for point in [[0.3, -0.2, 1.0], [-1.5, 0.7, 2.0], [0.0, 0.0, -4.0]] {
+            let view_position = to_view_space(&camera, point);
+            let view_plane_position = [view_position[0] / view_position[2], view_position[1] / view_position[2]];
+            // Projected like COLMAP does, rows go down like view space y
+            let pixel = [
+                camera.fx * view_plane_position[0] + camera.cx,
+                camera.fy * view_plane_position[1] + camera.cy,
+            ];
+            let clip_space_position = to_clip_space(&view_projection_matrix, point);
+            let rendered_pixel = to_pixel(&camera, clip_space_position);
+            assert!((rendered_pixel[0] - pixel[0]).abs() < 1.0e-2 && (rendered_pixel[1] - pixel[1]).abs() < 1.0e-2);
+            // Mirrors `viewPlaneToClipSpace` in the shaders
+            for axis in 0..2 {
+                let sign = if axis == 0 { 1.0 } else { -1.0 };
+                let clip = sign * (view_plane_position[axis] as f32 - view_offset[axis]) / view_size[axis];
+                assert!((clip - clip_space_position[axis]).abs() < 1.0e-4);
+            }
+        }
+    }
+
+    /// Position of a world space point in clip space, without the depth
+    fn to_clip_space(view_projection_matrix: &[Point; 4], point: [f64; 3]) -> [f32; 2] {
+        let homogenous_position = [0, 1, 3].map(|row| {
+            view_projection_matrix[0][row] * point[0] as f32
+                + view_projection_matrix[1][row] * point[1] as f32
+                + view_projection_matrix[2][row] * point[2] as f32
+                + view_projection_matrix[3][row]
+        });
+        [
+            homogenous_position[0] / homogenous_position[2],
+            homogenous_position[1] / homogenous_position[2],
+        ]
+    }
+
+    /// Pixel a clip space position is rasterized at, rows go down from the top of the image
+    fn to_pixel(camera: &Camera, clip_space_position: [f32; 2]) -> [f64; 2] {
+        [
+            (clip_space_position[0] as f64 + 1.0) * 0.5 * camera.width as f64,
+            (1.0 - clip_space_position[1] as f64) * 0.5 * camera.height as f64,
+        ]
+    }
+
+    /// Position of a world space point in view space
+    fn to_view_space(camera: &Camera, point: [f64; 3]) -> [f64; 3] {
+        let rotated = transform(&camera.rotation, point);
+        [0, 1, 2].map(|axis| rotated[axis] + camera.translation[axis])
+    }
+
+    #[test]
+    fn look_at_orbit_and_fly() {
+        let (eye, target) = ([1.0, 2.0, -4.0], [0.5, 0.0, 1.0]);
+        let mut camera = Camera::from_look_at(eye, target, [0.0, 1.0, 0.0], 0.8, 640, 480);
+        let position = camera.position();
+        assert!((0..3).all(|axis| (position[axis] - eye[axis]).abs() < 1.0e-12));
+        let distance = dot(
+            [0, 1, 2].map(|axis| target[axis] - eye[axis]),
+            [0, 1, 2].map(|axis| target[axis] - eye[axis]),
+        )
+        .sqrt();
+        let view_target = to_view_space(&camera, target);
+        assert!(view_target[0].abs() < 1.0e-12 && view_target[1].abs() < 1.0e-12);
+        assert!((view_target[2] - distance).abs() < 1.0e-12);
+        // The world up direction is towards the top of the image, where view space y is negative
+        assert!(to_view_space(&camera, [eye[0], eye[1] + 1.0, eye[2]])[1] < 0.0);
+        let pixel = |camera: &Camera, point: [f64; 3]| to_pixel(camera, to_clip_space(&camera.view_projection_matrix(), point));
+        let [center, above] = [target, [target[0], target[1] + 0.5, target[2]]].map(|point| pixel(&camera, point));
+        assert!((center[0] - 320.0).abs() < 1.0e-3 && (center[1] - 240.0).abs() < 1.0e-3);
+        assert!(above[1] < center[1] - 10.0);
+
+        camera.orbit(target, 0.7, -0.3);
+        let view_target = to_view_space(&camera, target);
+        assert!(view_target[0].abs() < 1.0e-12 && view_target[1].abs() < 1.0e-12);
+        assert!((view_target[2] - distance).abs() < 1.0e-12);
+        // Orbiting only turns the up direction a little, it stays towards the top of the image
+        assert!(pixel(&camera, [target[0], target[1] + 0.5, target[2]])[1] < pixel(&camera, target)[1] - 10.0);
+        let rotation = multiply(&camera.rotation, &transpose(&camera.rotation));
+        for (row, identity_row) in rotation.iter().zip([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]) {
+            assert!((0..3).all(|column| (row[column] - identity_row[column]).abs() < 1.0e-12));
+        }
+
+        let position = camera.position();
+        camera.fly([0.0, 0.0, 1.0], 0.0, 0.0);
+        let forward = camera.rotation[2];
+        let moved = camera.position();
+        assert!((0..3).all(|axis| (moved[axis] - position[axis] - forward[axis]).abs() < 1.0e-12));
+    }
+}
@@ -0,0 +1,156 @@
+//! Removal of splats which cost sorting and fill rate without contributing to the image
+use crate::{
+    renderer::Renderer,
+    scene::{GpuSplat, Scene, SceneError},
+};
+
+/// Thresholds of [Scene::cleanup], each rule is disabled by an infinite threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CleanupThresholds {
+    /// Splats with a lower opacity are removed
+    pub min_opacity: f32,
+    /// Splats with a larger scale along any axis are removed
+    pub max_scale: f32,
+    /// Splats whose largest scale exceeds their smallest scale by a larger factor are removed
+    pub max_anisotropy: f32,
+    /// Removes splats with NaN or infinite attributes
+    pub remove_non_finite: bool,
+}
+
+impl Default for CleanupThresholds {
+    fn default() -> Self {
+        Self {
+            min_opacity: 0.005,
+            max_scale: f32::INFINITY,
+            max_anisotropy: f32::INFINITY,
+            remove_non_finite: true,
+        }
+    }
+}
+
+/// The rule a splat is removed by, checked in this order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CleanupRule {
+    NonFinite,
+    Transparent,
+    Oversized,
+    Anisotropic,
+}
+
+impl CleanupThresholds {
+    /// The first rule which removes the splat, if any
+    pub fn rule_removing(&self, splat: &GpuSplat) -> Option<CleanupRule> {
+        let is_finite = splat
+            .rotation
+            .iter()
+            .chain(splat.center.iter())
+            .chain(splat.scale.iter())
+            .chain(std::iter::once(&splat.alpha))
+            .chain(splat.color.iter())
+            .all(|value| value.is_finite());
+        let largest_scale = splat.scale.iter().fold(0.0f32, |largest, scale| largest.max(*scale));
+        let smallest_scale = splat.scale.iter().fold(f32::INFINITY, |smallest, scale| smallest.min(*scale));
+        if self.remove_non_finite && !is_finite {
+            Some(CleanupRule::NonFinite)
+        } else if splat.alpha < self.min_opacity {
+            Some(CleanupRule::Transparent)
+        } else if largest_scale > self.max_scale {
+            Some(CleanupRule::Oversized)
+        } else if largest_scale > smallest_scale * self.max_anisotropy {
+            Some(CleanupRule::Anisotropic)
+        } else {
+            None
+        }
+    }
+}
+
+/// How many splats each rule of [Scene::cleanup] removed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub non_finite: usize,
+    pub transparent: usize,
+    pub oversized: usize,
+    pub anisotropic: usize,
+    /// Number of splats left in the scene
+    pub kept: usize,
+}
+
+impl CleanupReport {
+    pub fn removed(&self) -> usize {
+        self.non_finite + self.transparent + self.oversized + self.anisotropic
+    }
+
+    fn count(&mut self, rule: CleanupRule) {
+        match rule {
+            CleanupRule::NonFinite => self.non_finite += 1,
+            CleanupRule::Transparent => self.transparent += 1,
+            CleanupRule::Oversized => self.oversized += 1,
+            CleanupRule::Anisotropic => self.anisotropic += 1,
+        }
+    }
+}
+
+impl Scene {
+    /// Removes the loaded splats which are nearly transparent, too large, too anisotropic or not finite
+    ///
+    /// Each removed splat is counted by the first rule removing it.
+    pub fn cleanup(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        thresholds: &CleanupThresholds,
+    ) -> Result<CleanupReport, SceneError> {
+        let splat_data = self.download_splats(device, queue, 0..self.loaded_splat_count)?;
+        let mut report = CleanupReport::default();
+        let mut removed_splat_indices = Vec::new();
+        for (splat_index, splat) in splat_data.iter().enumerate() {
+            if let Some(rule) = thresholds.rule_removing(splat) {
+                report.count(rule);
+                removed_splat_indices.push(splat_index);
+            }
+        }
+        self.remove_splats(device, queue, renderer, &removed_splat_indices)?;
+        report.kept = self.loaded_splat_count;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_remove_splats() {
+        let thresholds = CleanupThresholds {
+            max_scale: 10.0,
+            max_anisotropy: 100.0,
+            ..CleanupThresholds::default()
+        };
+        let splat = GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            scale: [0.1, 0.2, 0.3],
+            alpha: 0.5,
+            ..GpuSplat::default()
+        };
+        assert_eq!(thresholds.rule_removing(&splat), None);
+        let mut non_finite = splat;
+        non_finite.color[20] = f32::NAN;
+        // Also transparent, but the first rule counts
+        non_finite.alpha = 0.0;
+        assert_eq!(thresholds.rule_removing(&non_finite), Some(CleanupRule::NonFinite));
+        let transparent = GpuSplat { alpha: 0.001, ..splat };
+        assert_eq!(thresholds.rule_removing(&transparent), Some(CleanupRule::Transparent));
+        let oversized = GpuSplat {
+            scale: [20.0, 20.0, 20.0],
+            ..splat
+        };
+        assert_eq!(thresholds.rule_removing(&oversized), Some(CleanupRule::Oversized));
+        let anisotropic = GpuSplat {
+            scale: [0.001, 1.0, 1.0],
+            ..splat
+        };
+        assert_eq!(thresholds.rule_removing(&anisotropic), Some(CleanupRule::Anisotropic));
+        assert_eq!(CleanupThresholds::default().rule_removing(&anisotropic), None);
+    }
+}
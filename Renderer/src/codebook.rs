@@ -0,0 +1,348 @@
+//! Vector quantization of the higher order spherical harmonics
+//!
+//! The 45 higher order coefficients of every splat are replaced by the nearest entry of a
+//! [SphericalHarmonicsCodebook], trained with k-means. Each splat then only needs the index
+//! of its entry in [GpuSplat::codebook_index], and the shaders look the coefficients up in the codebook.
+//!
+//! A codebook file, with the extension `.shcb`, stores the quantized scene in little endian:
+//! - the magic `SHCB`, followed by the splat count, the spherical harmonics degree and the codebook size as u32
+//! - the codebook entries, each holding the coefficients used by the degree as f16
+//! - for every splat its center, scale, rotation, opacity and base color as f32 followed by its codebook index as u16
+use crate::{
+    scene::{GpuSplat, SceneError},
+    utils::{f16_bits_to_f32, f32_to_f16_bits},
+};
+use std::{
+    convert::TryInto,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+/// Number of higher order spherical harmonics coefficients, 15 for each color channel
+pub const CODEBOOK_ENTRY_SIZE: usize = 3 * 15;
+/// Largest supported codebook, so that indices fit into 16 bits
+pub const MAX_CODEBOOK_SIZE: usize = 1 << 16;
+/// Magic bytes at the start of a codebook file
+pub(crate) const MAGIC: &[u8; 4] = b"SHCB";
+const HEADER_SIZE: usize = 16;
+/// Floats of a splat record, see [read_record]
+const RECORD_FLOAT_COUNT: usize = 3 + 3 + 4 + 1 + 3;
+const RECORD_SIZE: usize = RECORD_FLOAT_COUNT * 4 + 2;
+/// Number of training samples per codebook entry, bounds the cost of training on large scenes
+const TRAINING_SAMPLES_PER_ENTRY: usize = 64;
+
+/// Number of higher order coefficients used by a spherical harmonics degree
+pub(crate) fn coefficient_count(spherical_harmonics_degree: usize) -> usize {
+    3 * ((spherical_harmonics_degree + 1) * (spherical_harmonics_degree + 1) - 1)
+}
+
+/// The higher order coefficients of a splat, in the interleaved layout of the entries
+fn higher_order_coefficients(splat: &GpuSplat) -> &[f32] {
+    &splat.color[3..]
+}
+
+fn squared_distance(a: &[f32], b: &[f32], upper_bound: f32) -> f32 {
+    let mut distance = 0.0;
+    for (a, b) in a.iter().zip(b.iter()) {
+        distance += (a - b) * (a - b);
+        // Most entries are far away, so stop as soon as this one can not be the nearest anymore
+        if distance >= upper_bound {
+            break;
+        }
+    }
+    distance
+}
+
+/// Applies `function` to every element of `data` in parallel, with the index of the element
+fn for_each_parallel<T: Send, F: Fn(usize, &mut T) + Sync>(data: &mut [T], function: F) {
+    let thread_count = std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1);
+    let chunk_size = data.len().div_ceil(thread_count).max(1);
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in data.chunks_mut(chunk_size).enumerate() {
+            let function = &function;
+            scope.spawn(move || {
+                for (index, element) in chunk.iter_mut().enumerate() {
+                    function(chunk_index * chunk_size + index, element);
+                }
+            });
+        }
+    });
+}
+
+/// Codebook of higher order spherical harmonics coefficients
+#[derive(Clone, Debug, PartialEq)]
+pub struct SphericalHarmonicsCodebook {
+    /// Coefficients in the layout of [GpuSplat::color] without the base color
+    pub entries: Vec<[f32; CODEBOOK_ENTRY_SIZE]>,
+    /// Degree of the spherical harmonics, coefficients beyond it are zero
+    pub spherical_harmonics_degree: usize,
+}
+
+impl SphericalHarmonicsCodebook {
+    /// Clusters the higher order coefficients of the splats into at most `codebook_size` entries
+    ///
+    /// Runs `iterations` steps of k-means, on a strided subset of the splats for large scenes.
+    pub fn train(splat_data: &[GpuSplat], spherical_harmonics_degree: usize, codebook_size: usize, iterations: usize) -> Self {
+        let coefficient_count = coefficient_count(spherical_harmonics_degree);
+        let codebook_size = codebook_size.clamp(1, MAX_CODEBOOK_SIZE).min(splat_data.len().max(1));
+        let sample_count = splat_data.len().min(codebook_size * TRAINING_SAMPLES_PER_ENTRY);
+        let samples: Vec<&[f32]> = (0..sample_count)
+            .map(|index| &higher_order_coefficients(&splat_data[index * splat_data.len() / sample_count])[0..coefficient_count])
+            .collect();
+        let mut codebook = Self {
+            entries: vec![[0.0; CODEBOOK_ENTRY_SIZE]; codebook_size],
+            spherical_harmonics_degree,
+        };
+        if samples.is_empty() {
+            return codebook;
+        }
+        for (entry_index, entry) in codebook.entries.iter_mut().enumerate() {
+            entry[0..coefficient_count].copy_from_slice(samples[entry_index * samples.len() / codebook_size]);
+        }
+        let mut assignments = vec![0; samples.len()];
+        for _ in 0..iterations {
+            for_each_parallel(&mut assignments, |sample_index, assignment| {
+                *assignment = codebook.nearest_entry(samples[sample_index]);
+            });
+            let mut sums = vec![[0.0f64; CODEBOOK_ENTRY_SIZE]; codebook_size];
+            let mut counts = vec![0usize; codebook_size];
+            for (sample, assignment) in samples.iter().zip(assignments.iter()) {
+                counts[*assignment as usize] += 1;
+                for (sum, coefficient) in sums[*assignment as usize].iter_mut().zip(sample.iter()) {
+                    *sum += *coefficient as f64;
+                }
+            }
+            // Entries without samples keep their previous coefficients
+            for ((entry, sum), count) in codebook.entries.iter_mut().zip(sums.iter()).zip(counts.iter()) {
+                if *count > 0 {
+                    for (coefficient, sum) in entry.iter_mut().zip(sum.iter()) {
+                        *coefficient = (*sum / *count as f64) as f32;
+                    }
+                }
+            }
+        }
+        codebook
+    }
+
+    /// Index of the entry closest to the given higher order coefficients
+    pub fn nearest_entry(&self, coefficients: &[f32]) -> u32 {
+        let coefficient_count = coefficient_count(self.spherical_harmonics_degree).min(coefficients.len());
+        let mut nearest_index = 0;
+        let mut nearest_distance = f32::INFINITY;
+        for (entry_index, entry) in self.entries.iter().enumerate() {
+            let distance = squared_distance(&entry[0..coefficient_count], &coefficients[0..coefficient_count], nearest_distance);
+            if distance < nearest_distance {
+                nearest_index = entry_index;
+                nearest_distance = distance;
+            }
+        }
+        nearest_index as u32
+    }
+
+    /// Sets the [GpuSplat::codebook_index] of every splat to its nearest entry
+    ///
+    /// Keeps the coefficients of the splats, use [SphericalHarmonicsCodebook::decode] to replace them by their entries.
+    pub fn quantize(&self, splat_data: &mut [GpuSplat]) {
+        for_each_parallel(splat_data, |_, splat| {
+            splat.codebook_index = self.nearest_entry(higher_order_coefficients(splat));
+        });
+    }
+
+    /// Sets the higher order coefficients of the splat to the entry at its [GpuSplat::codebook_index]
+    pub fn decode(&self, splat: &mut GpuSplat) {
+        splat.color[3..].copy_from_slice(&self.entries[splat.codebook_index as usize]);
+    }
+}
+
+/// Where the splats are stored in a codebook file
+pub(crate) struct CodebookFileLayout {
+    pub(crate) splat_count: usize,
+    pub(crate) codebook: SphericalHarmonicsCodebook,
+    records_offset: u64,
+}
+
+impl CodebookFileLayout {
+    /// Parses the header and the codebook, the reader has to be at the start of the file
+    pub(crate) fn parse<R: Read>(reader: &mut R, file_size: u64) -> Result<Self, SceneError> {
+        let mut header = [0; HEADER_SIZE];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(SceneError::MalformedHeader("Codebook file does not start with SHCB".into()));
+        }
+        let field = |index: usize| u32::from_le_bytes(header[index * 4..index * 4 + 4].try_into().unwrap()) as usize;
+        let (splat_count, spherical_harmonics_degree, codebook_size) = (field(1), field(2), field(3));
+        if spherical_harmonics_degree > 3 {
+            return Err(SceneError::MalformedHeader(format!(
+                "Spherical harmonics of degree {} are not supported",
+                spherical_harmonics_degree
+            )));
+        }
+        if codebook_size == 0 || codebook_size > MAX_CODEBOOK_SIZE {
+            return Err(SceneError::MalformedHeader(format!("Codebook size {} is out of range", codebook_size)));
+        }
+        let coefficient_count = coefficient_count(spherical_harmonics_degree);
+        let records_offset = (HEADER_SIZE + codebook_size * coefficient_count * 2) as u64;
+        if records_offset + (splat_count * RECORD_SIZE) as u64 > file_size {
+            return Err(SceneError::Truncated);
+        }
+        let mut buffer = vec![0; codebook_size * coefficient_count * 2];
+        reader.read_exact(&mut buffer)?;
+        let entries = buffer
+            .chunks_exact(coefficient_count * 2)
+            .map(|entry_bytes| {
+                let mut entry = [0.0; CODEBOOK_ENTRY_SIZE];
+                for (coefficient, bytes) in entry.iter_mut().zip(entry_bytes.chunks_exact(2)) {
+                    *coefficient = f16_bits_to_f32(u16::from_le_bytes([bytes[0], bytes[1]]));
+                }
+                entry
+            })
+            .collect();
+        Ok(Self {
+            splat_count,
+            codebook: SphericalHarmonicsCodebook {
+                entries,
+                spherical_harmonics_degree,
+            },
+            records_offset,
+        })
+    }
+
+    pub(crate) fn read_splats<R: Read + Seek>(&self, reader: &mut R, splat_index_range: std::ops::Range<usize>) -> Result<Vec<GpuSplat>, SceneError> {
+        reader.seek(SeekFrom::Start(self.records_offset + (splat_index_range.start * RECORD_SIZE) as u64))?;
+        let mut buffer = vec![0; splat_index_range.len() * RECORD_SIZE];
+        reader.read_exact(&mut buffer)?;
+        buffer
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| {
+                let mut splat = read_record(record);
+                if splat.codebook_index as usize >= self.codebook.entries.len() {
+                    return Err(SceneError::MalformedHeader(format!(
+                        "Codebook index {} is out of range",
+                        splat.codebook_index
+                    )));
+                }
+                self.codebook.decode(&mut splat);
+                Ok(splat)
+            })
+            .collect()
+    }
+}
+
+/// Parses the attributes and the codebook index of a splat, leaving its higher order coefficients zero
+fn read_record(record: &[u8]) -> GpuSplat {
+    let mut floats = record[0..RECORD_FLOAT_COUNT * 4]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()));
+    let mut splat = GpuSplat::default();
+    for value in splat
+        .center
+        .iter_mut()
+        .chain(splat.scale.iter_mut())
+        .chain(splat.rotation.iter_mut())
+        .chain(std::iter::once(&mut splat.alpha))
+        .chain(splat.color[0..3].iter_mut())
+    {
+        *value = floats.next().unwrap();
+    }
+    splat.codebook_index = u16::from_le_bytes([record[RECORD_FLOAT_COUNT * 4], record[RECORD_FLOAT_COUNT * 4 + 1]]) as u32;
+    splat
+}
+
+fn write_record<W: Write>(writer: &mut W, splat: &GpuSplat) -> std::io::Result<()> {
+    for value in splat
+        .center
+        .iter()
+        .chain(splat.scale.iter())
+        .chain(splat.rotation.iter())
+        .chain(std::iter::once(&splat.alpha))
+        .chain(splat.color[0..3].iter())
+    {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&(splat.codebook_index as u16).to_le_bytes())
+}
+
+/// Writes quantized splats and their codebook to a codebook file
+pub(crate) fn write<W: Write>(writer: &mut W, splat_data: &[GpuSplat], codebook: &SphericalHarmonicsCodebook) -> std::io::Result<()> {
+    let coefficient_count = coefficient_count(codebook.spherical_harmonics_degree);
+    writer.write_all(MAGIC)?;
+    for value in [splat_data.len(), codebook.spherical_harmonics_degree, codebook.entries.len()] {
+        writer.write_all(&(value as u32).to_le_bytes())?;
+    }
+    for entry in &codebook.entries {
+        for coefficient in &entry[0..coefficient_count] {
+            writer.write_all(&f32_to_f16_bits(*coefficient).to_le_bytes())?;
+        }
+    }
+    for splat in splat_data {
+        write_record(writer, splat)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_splats() -> Vec<GpuSplat> {
+        (0..200)
+            .map(|index| {
+                let mut splat = GpuSplat {
+                    rotation: [1.0, 0.0, 0.0, 0.0],
+                    center: [index as f32, 0.0, 0.0],
+                    scale: [0.1, 0.2, 0.3],
+                    alpha: 0.5,
+                    ..GpuSplat::default()
+                };
+                // Two clusters of coefficients
+                for (coefficient_index, coefficient) in splat.color[3..].iter_mut().enumerate() {
+                    *coefficient = if index % 2 == 0 { 0.25 } else { -0.5 } + coefficient_index as f32 * 0.001;
+                }
+                splat
+            })
+            .collect()
+    }
+
+    #[test]
+    fn train_and_quantize() {
+        let mut splat_data = test_splats();
+        let codebook = SphericalHarmonicsCodebook::train(&splat_data, 3, 2, 4);
+        assert_eq!(codebook.entries.len(), 2);
+        let original_splat_data = splat_data.clone();
+        codebook.quantize(&mut splat_data);
+        let cluster_indices = [splat_data[0].codebook_index, splat_data[1].codebook_index];
+        assert_ne!(cluster_indices[0], cluster_indices[1]);
+        for (splat, original_splat) in splat_data.iter_mut().zip(original_splat_data.iter()) {
+            assert_eq!(splat.codebook_index, cluster_indices[splat.center[0] as usize % 2]);
+            assert_eq!(splat.color, original_splat.color);
+            codebook.decode(splat);
+            for (coefficient, original_coefficient) in splat.color.iter().zip(original_splat.color.iter()) {
+                assert!((coefficient - original_coefficient).abs() < 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn write_and_read() {
+        let mut splat_data = test_splats();
+        let codebook = SphericalHarmonicsCodebook::train(&splat_data, 3, 2, 4);
+        codebook.quantize(&mut splat_data);
+        let mut file = Vec::new();
+        write(&mut file, &splat_data, &codebook).unwrap();
+        assert_eq!(file.len(), HEADER_SIZE + 2 * CODEBOOK_ENTRY_SIZE * 2 + splat_data.len() * RECORD_SIZE);
+        let mut reader = std::io::Cursor::new(&file);
+        let layout = CodebookFileLayout::parse(&mut reader, file.len() as u64).unwrap();
+        assert_eq!(layout.splat_count, splat_data.len());
+        let read_splat_data = layout.read_splats(&mut reader, 10..20).unwrap();
+        for (splat, read_splat) in splat_data[10..20].iter().zip(read_splat_data.iter()) {
+            assert_eq!(splat.codebook_index, read_splat.codebook_index);
+            assert_eq!(splat.center, read_splat.center);
+            assert_eq!(splat.scale, read_splat.scale);
+            assert_eq!(splat.rotation, read_splat.rotation);
+            assert_eq!(splat.alpha, read_splat.alpha);
+            assert_eq!(splat.color[0..3], read_splat.color[0..3]);
+            for (coefficient, read_coefficient) in splat.color[3..].iter().zip(read_splat.color[3..].iter()) {
+                assert!((coefficient - read_coefficient).abs() < 1.0e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// Codebook splat layout, replaces the splat layout section of shaders.wgsl
+struct Splat {
+    rotation: vec4<f32>,
+    center: vec3<f32>,
+    codebookIndex: u32,
+    scale: vec3<f32>,
+    alpha: f32,
+    colorDC: vec3<f32>,
+}
+fn splatRotation(splat_index: u32) -> vec4<f32> {
+    return splats[splat_index].rotation;
+}
+fn splatScale(splat_index: u32) -> vec3<f32> {
+    return splats[splat_index].scale;
+}
+// Only the base color is stored, the higher order coefficients are read from the codebook
+fn splatColorSH(splat_index: u32, coefficient: u32) -> vec3<f32> {
+    return select(vec3<f32>(0.0), splats[splat_index].colorDC, coefficient == 0u);
+}
+fn splatCodebookIndex(splat_index: u32) -> u32 {
+    return splats[splat_index].codebookIndex;
+}
@@ -0,0 +1,198 @@
+//! Reading of the binary camera files written by COLMAP
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// Errors which can occur while reading COLMAP files
+#[derive(Debug)]
+pub enum ColmapError {
+    /// Reading the file failed
+    Io(std::io::Error),
+    /// The file ends in the middle of a record
+    Truncated,
+    /// The camera uses a model other than SIMPLE_PINHOLE or PINHOLE
+    UnsupportedCameraModel(u32),
+    /// The camera has fewer parameters than its model needs
+    MissingParameters { camera_id: u64, model: String, count: usize },
+}
+
+impl std::fmt::Display for ColmapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Truncated => write!(f, "the COLMAP file is truncated"),
+            Self::UnsupportedCameraModel(model_id) => write!(f, "unsupported camera model {}", model_id),
+            Self::MissingParameters { camera_id, model, count } => {
+                write!(f, "camera {} has only {} parameters for its model {}", camera_id, count, model)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ColmapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ColmapError {
+    fn from(error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            Self::Truncated
+        } else {
+            Self::Io(error)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CameraModel<'a> {
+    model_id: u32,
+    model_name: &'a str,
+    num_params: usize,
+}
+
+const CAMERA_MODELS: [CameraModel; 2] = [
+    CameraModel {
+        model_id: 0,
+        model_name: "SIMPLE_PINHOLE",
+        num_params: 3,
+    },
+    CameraModel {
+        model_id: 1,
+        model_name: "PINHOLE",
+        num_params: 4,
+    },
+];
+
+/// Intrinsic parameters of a camera
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub id: u64,
+    pub model: String,
+    pub width: u64,
+    pub height: u64,
+    /// Focal lengths and principal point, in the order defined by the camera model
+    pub params: Vec<f64>,
+}
+
+/// Extrinsic parameters of a registered image
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub id: u64,
+    pub camera_id: u32,
+    /// World to camera rotation as quaternion (w, x, y, z)
+    pub qvec: [f64; 4],
+    /// World to camera translation
+    pub tvec: [f64; 3],
+    pub name: String,
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, ColmapError> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, ColmapError> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64, ColmapError> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+/// Reads an `images.bin` file
+pub fn read_extrinsics_binary<P: AsRef<Path>>(path_to_model_file: P) -> Result<HashMap<u64, Image>, ColmapError> {
+    let mut fid = BufReader::new(File::open(path_to_model_file)?);
+    let num_reg_images = read_u64(&mut fid)?;
+    let mut images: HashMap<u64, Image> = HashMap::new();
+    for _ in 0..num_reg_images {
+        let image_id = read_u32(&mut fid)?;
+        let mut qvec = [0.0; 4];
+        for value in qvec.iter_mut() {
+            *value = read_f64(&mut fid)?;
+        }
+        let mut tvec = [0.0; 3];
+        for value in tvec.iter_mut() {
+            *value = read_f64(&mut fid)?;
+        }
+        let camera_id = read_u32(&mut fid)?;
+        let mut name = Vec::new();
+        let mut current_char = [0; 1];
+        loop {
+            fid.read_exact(&mut current_char)?;
+            if current_char[0] == 0 {
+                break;
+            }
+            name.push(current_char[0]);
+        }
+        // Skip the 2D points: x, y as f64 and the point 3D id as i64
+        let points_size = read_u64(&mut fid)?.checked_mul(24).ok_or(ColmapError::Truncated)?;
+        if std::io::copy(&mut (&mut fid).take(points_size), &mut std::io::sink())? != points_size {
+            return Err(ColmapError::Truncated);
+        }
+        images.insert(
+            image_id as u64,
+            Image {
+                id: image_id as u64,
+                camera_id,
+                qvec,
+                tvec,
+                name: String::from_utf8_lossy(&name).into_owned(),
+            },
+        );
+    }
+    Ok(images)
+}
+
+/// Reads a `cameras.bin` file
+pub fn read_intrinsics_binary<P: AsRef<Path>>(path_to_model_file: P) -> Result<HashMap<u64, Camera>, ColmapError> {
+    let mut fid = BufReader::new(File::open(path_to_model_file)?);
+    let num_cameras = read_u64(&mut fid)?;
+    let mut cameras: HashMap<u64, Camera> = HashMap::new();
+    for _ in 0..num_cameras {
+        let camera_id = read_u32(&mut fid)?;
+        let model_id = read_u32(&mut fid)?;
+        let width = read_u64(&mut fid)?;
+        let height = read_u64(&mut fid)?;
+        let model = CAMERA_MODELS
+            .iter()
+            .find(|model| model.model_id == model_id)
+            .ok_or(ColmapError::UnsupportedCameraModel(model_id))?;
+        let mut params = vec![0.0; model.num_params];
+        for param in params.iter_mut() {
+            *param = read_f64(&mut fid)?;
+        }
+        cameras.insert(
+            camera_id as u64,
+            Camera {
+                id: camera_id as u64,
+                model: model.model_name.to_string(),
+                width,
+                height,
+                params,
+            },
+        );
+    }
+    Ok(cameras)
+}
+
+/// Cameras and images of a COLMAP reconstruction, indexed by their ids
+pub type SceneInfo = (HashMap<u64, Camera>, HashMap<u64, Image>);
+
+/// Reads the cameras and images of the COLMAP reconstruction in `path/sparse/0`
+pub fn read_scene_info<P: AsRef<Path>>(path: P) -> Result<SceneInfo, ColmapError> {
+    let sparse_path = path.as_ref().join("sparse/0");
+    let cameras_intrinsics = read_intrinsics_binary(sparse_path.join("cameras.bin"))?;
+    let cameras_extrinsic = read_extrinsics_binary(sparse_path.join("images.bin"))?;
+    Ok((cameras_intrinsics, cameras_extrinsic))
+}
@@ -0,0 +1,216 @@
+//! Composition of several splat files into one scene
+//!
+//! Splats of different files only blend correctly if they are sorted together,
+//! so the files are loaded into the splat buffer of a single [Scene].
+//! Each object keeps its range of splats, so that it can be moved or faded later with [Scene::place_object].
+use crate::{
+    renderer::Renderer,
+    scene::{GpuSplat, Scene, SceneError, SplatFileHeader, SplatFileReader},
+    transform::Similarity,
+};
+use std::{ops::Range, path::PathBuf};
+
+/// Number of splats read and uploaded at once
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// A splat file placed in a composed scene
+#[derive(Clone, Debug)]
+pub struct SceneObject {
+    pub path: PathBuf,
+    /// Moves the splats from the frame of the file into the frame of the scene
+    pub transform: Similarity,
+    /// Factor the opacity of every splat is multiplied with, in [0, 1]
+    pub opacity: f32,
+}
+
+impl SceneObject {
+    /// Places the file at `path` with the identity transform and full opacity
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            transform: Similarity::identity(),
+            opacity: 1.0,
+        }
+    }
+
+    /// Applies the transform and the opacity multiplier
+    fn place_splats(&self, splat_data: &mut [GpuSplat]) {
+        self.transform.transform_splats(splat_data);
+        for splat in splat_data {
+            splat.alpha = (splat.alpha * self.opacity).clamp(0.0, 1.0);
+        }
+    }
+}
+
+/// A [SceneObject] loaded into a composed scene
+#[derive(Clone, Debug)]
+pub struct ComposedObject {
+    /// The file and the current transform and opacity of the object
+    pub object: SceneObject,
+    /// Indices of the splats of the object in the scene
+    pub splat_range: Range<usize>,
+}
+
+impl Scene {
+    /// Loads several splat files into one scene, so that their splats are depth sorted together
+    ///
+    /// Returns the scene and the objects with their ranges of splat indices.
+    /// The spherical harmonics degree of the scene is the highest of all files,
+    /// objects of lower degree have zero higher order coefficients.
+    pub fn compose(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        objects: &[SceneObject],
+    ) -> Result<(Self, Vec<ComposedObject>), SceneError> {
+        let mut headers = Vec::with_capacity(objects.len());
+        for object in objects {
+            headers.push(Scene::open_file(&object.path)?);
+        }
+        let splat_count = headers.iter().map(|(header, _)| header.splat_count).sum();
+        let mut scene = Scene::new(device, renderer, splat_count)?;
+        scene.spherical_harmonics_degree = headers.iter().map(|(header, _)| header.spherical_harmonics_degree).max().unwrap_or(0);
+        let mut composed_objects = Vec::with_capacity(objects.len());
+        let mut splat_index_offset = 0;
+        for (object, (header, mut file)) in objects.iter().zip(headers) {
+            scene.load_object(queue, object, &header, &mut file, splat_index_offset)?;
+            composed_objects.push(ComposedObject {
+                object: object.clone(),
+                splat_range: splat_index_offset..splat_index_offset + header.splat_count,
+            });
+            splat_index_offset += header.splat_count;
+        }
+        Ok((scene, composed_objects))
+    }
+
+    /// Moves and fades an object of [Scene::compose] by reloading its splats from its file with the new transform and opacity
+    ///
+    /// Placing the splats from the file again keeps repeated updates exact, but discards edits of the splats of the object.
+    /// Fails with [SceneError::MalformedHeader] if the file no longer has as many splats as the object.
+    pub fn place_object(
+        &mut self,
+        queue: &wgpu::Queue,
+        composed_object: &mut ComposedObject,
+        transform: Similarity,
+        opacity: f32,
+    ) -> Result<(), SceneError> {
+        let (header, mut file) = Scene::open_file(&composed_object.object.path)?;
+        if header.splat_count != composed_object.splat_range.len() {
+            return Err(SceneError::MalformedHeader(format!(
+                "{} has {} splats instead of the {} it was composed with",
+                composed_object.object.path.display(),
+                header.splat_count,
+                composed_object.splat_range.len()
+            )));
+        }
+        let object = SceneObject {
+            transform,
+            opacity,
+            ..composed_object.object.clone()
+        };
+        self.load_object(queue, &object, &header, &mut file, composed_object.splat_range.start)?;
+        composed_object.object = object;
+        Ok(())
+    }
+
+    /// Reads the splats of an object in chunks, places them and uploads them starting at `splat_index_offset`
+    fn load_object(
+        &mut self,
+        queue: &wgpu::Queue,
+        object: &SceneObject,
+        header: &SplatFileHeader,
+        file: &mut SplatFileReader,
+        splat_index_offset: usize,
+    ) -> Result<(), SceneError> {
+        for chunk_start in (0..header.splat_count).step_by(CHUNK_SIZE) {
+            let mut splat_data = header.read_splats(file, chunk_start..(chunk_start + CHUNK_SIZE).min(header.splat_count))?;
+            object.place_splats(&mut splat_data);
+            self.upload_splats(queue, splat_index_offset + chunk_start, &splat_data);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{renderer::DepthSorting, scene::SerializedSplat, utils::test_renderer};
+
+    /// Writes a `.splat` file of `splat_count` splats along the x axis
+    fn write_splat_file(path: &std::path::Path, splat_count: usize) {
+        let records: Vec<u8> = (0..splat_count)
+            .flat_map(|splat_index| {
+                SerializedSplat::compact_record(&GpuSplat {
+                    rotation: [1.0, 0.0, 0.0, 0.0],
+                    center: [splat_index as f32, 1.0, 2.0],
+                    scale: [0.1, 0.2, 0.3],
+                    alpha: 0.8,
+                    ..GpuSplat::default()
+                })
+            })
+            .collect();
+        std::fs::write(path, records).unwrap();
+    }
+
+    fn read_file_splats(path: &std::path::Path) -> Vec<GpuSplat> {
+        let (header, mut file) = Scene::open_file(path).unwrap();
+        header.read_splats(&mut file, 0..header.splat_count).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn compose_and_place_objects() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let directory = std::env::temp_dir().join(format!("composition_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (path_a, path_b) = (directory.join("a.splat"), directory.join("b.splat"));
+        write_splat_file(&path_a, 3);
+        write_splat_file(&path_b, 2);
+        let moved = SceneObject {
+            transform: Similarity {
+                rotation: [1.0, 0.0, 0.0, 0.0],
+                translation: [10.0, 0.0, 0.0],
+                scale: 2.0,
+            },
+            opacity: 0.5,
+            ..SceneObject::new(&path_b)
+        };
+        let (mut scene, mut objects) = Scene::compose(&device, &queue, &renderer, &[SceneObject::new(&path_a), moved]).unwrap();
+        assert_eq!((scene.splat_count, scene.loaded_splat_count), (5, 5));
+        assert_eq!(objects.iter().map(|object| object.splat_range.clone()).collect::<Vec<_>>(), [0..3, 3..5]);
+
+        let assert_object_splats = |scene: &Scene, object: &ComposedObject| {
+            let splat_data = scene.download_splats(&device, &queue, object.splat_range.clone()).unwrap();
+            let file_splat_data = read_file_splats(&object.object.path);
+            for (splat, file_splat) in splat_data.iter().zip(file_splat_data.iter()) {
+                let center = object.object.transform.transform_point(file_splat.center);
+                for (value, expected) in splat.center.iter().zip(center.iter()) {
+                    assert!((value - expected).abs() < 1.0e-5);
+                }
+                for (scale, file_scale) in splat.scale.iter().zip(file_splat.scale.iter()) {
+                    assert!((scale - file_scale * object.object.transform.scale).abs() < 1.0e-5);
+                }
+                assert!((splat.alpha - file_splat.alpha * object.object.opacity).abs() < 1.0e-5);
+            }
+        };
+        assert_object_splats(&scene, &objects[0]);
+        assert_object_splats(&scene, &objects[1]);
+        let splat_data = scene.download_splats(&device, &queue, 3..5).unwrap();
+        assert_eq!([splat_data[0].center[0], splat_data[1].center[0]], [10.0, 12.0]);
+
+        // Placing an object again starts from its file, not from the splats placed before
+        scene.place_object(&queue, &mut objects[1], Similarity::identity(), 0.25).unwrap();
+        assert_object_splats(&scene, &objects[1]);
+        assert_eq!(objects[1].object.opacity, 0.25);
+        let splat_data = scene.download_splats(&device, &queue, 3..5).unwrap();
+        assert_eq!([splat_data[0].center[0], splat_data[1].center[0]], [0.0, 1.0]);
+        assert_object_splats(&scene, &objects[0]);
+
+        write_splat_file(&path_b, 4);
+        assert!(matches!(
+            scene.place_object(&queue, &mut objects[1], Similarity::identity(), 1.0),
+            Err(SceneError::MalformedHeader(_))
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,471 @@
+//! The compressed PLY format of PlayCanvas and SuperSplat
+//!
+//! Splats are grouped in chunks of [CHUNK_SIZE], each storing the bounds of the positions,
+//! logarithmic scales and colors of its splats in a `chunk` element.
+//! A `vertex` element packs each splat into four u32:
+//! - the position and the scale as 11, 10 and 11 bit integers relative to the bounds of the chunk
+//! - the rotation as the index of the largest quaternion component (in `rot_0..rot_3` order)
+//!   followed by the other three components in 10 bits each
+//! - the color and the opacity in 8 bits each
+//!
+//! The higher order spherical harmonics are optionally stored as u8 in an `sh` element.
+use crate::{
+    ply::{self, PlyElement, PlyFormat, PlyHeader, PlyScalarType},
+    scene::{color_rest_count, inverse_sigmoid, sigmoid, spherical_harmonics_degree, GpuSplat, SceneError, SerializedSplat, SH_C0},
+};
+use std::io::{BufRead, Seek, SeekFrom, Write};
+
+/// Number of splats sharing the same bounds
+pub(crate) const CHUNK_SIZE: usize = 256;
+
+// Bounds of the logarithmic scales, which keeps degenerate splats from stretching the bounds of a chunk
+const MAX_LOG_SCALE: f32 = 20.0;
+
+const CHUNK_PROPERTY_NAMES: [&str; 18] = [
+    "min_x",
+    "min_y",
+    "min_z",
+    "max_x",
+    "max_y",
+    "max_z",
+    "min_scale_x",
+    "min_scale_y",
+    "min_scale_z",
+    "max_scale_x",
+    "max_scale_y",
+    "max_scale_z",
+    "min_r",
+    "min_g",
+    "min_b",
+    "max_r",
+    "max_g",
+    "max_b",
+];
+
+const PACKED_PROPERTY_NAMES: [&str; 4] = ["packed_position", "packed_rotation", "packed_scale", "packed_color"];
+
+fn unpack_unorm(value: u32, bits: u32) -> f32 {
+    let max = (1 << bits) - 1;
+    (value & max) as f32 / max as f32
+}
+
+fn pack_unorm(value: f32, bits: u32) -> u32 {
+    let max = (1 << bits) - 1;
+    (value * max as f32 + 0.5).floor().clamp(0.0, max as f32) as u32
+}
+
+fn unpack_111011(value: u32) -> [f32; 3] {
+    [unpack_unorm(value >> 21, 11), unpack_unorm(value >> 11, 10), unpack_unorm(value, 11)]
+}
+
+fn pack_111011(value: [f32; 3]) -> u32 {
+    (pack_unorm(value[0], 11) << 21) | (pack_unorm(value[1], 10) << 11) | pack_unorm(value[2], 11)
+}
+
+fn unpack_8888(value: u32) -> [f32; 4] {
+    [
+        unpack_unorm(value >> 24, 8),
+        unpack_unorm(value >> 16, 8),
+        unpack_unorm(value >> 8, 8),
+        unpack_unorm(value, 8),
+    ]
+}
+
+fn pack_8888(value: [f32; 4]) -> u32 {
+    (pack_unorm(value[0], 8) << 24) | (pack_unorm(value[1], 8) << 16) | (pack_unorm(value[2], 8) << 8) | pack_unorm(value[3], 8)
+}
+
+fn unpack_rotation(value: u32) -> [f32; 4] {
+    let largest = (value >> 30) as usize;
+    let mut rotation = [0.0; 4];
+    let mut sum_of_squares = 0.0;
+    let mut shift = 20;
+    for (component, rotation_component) in rotation.iter_mut().enumerate() {
+        if component != largest {
+            *rotation_component = (unpack_unorm(value >> shift, 10) - 0.5) * std::f32::consts::SQRT_2;
+            sum_of_squares += *rotation_component * *rotation_component;
+            shift -= 10;
+        }
+    }
+    rotation[largest] = (1.0 - sum_of_squares).max(0.0).sqrt();
+    rotation
+}
+
+fn pack_rotation(rotation: [f32; 4]) -> u32 {
+    let length = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+    let largest = (0..4).fold(0, |largest, component| {
+        if rotation[component].abs() > rotation[largest].abs() {
+            component
+        } else {
+            largest
+        }
+    });
+    // q and -q are the same rotation, so the largest component can always be made positive
+    let factor = if rotation[largest] < 0.0 { -1.0 } else { 1.0 } / length;
+    let mut packed = largest as u32;
+    for (component, rotation_component) in rotation.iter().enumerate() {
+        if component != largest {
+            packed = (packed << 10) | pack_unorm(rotation_component * factor * std::f32::consts::FRAC_1_SQRT_2 + 0.5, 10);
+        }
+    }
+    packed
+}
+
+fn unpack_spherical_harmonic(value: f64) -> f32 {
+    let normalized = if value == 0.0 { 0.0 } else { (value as f32 + 0.5) / 256.0 };
+    (normalized - 0.5) * 8.0
+}
+
+fn pack_spherical_harmonic(value: f32) -> f64 {
+    ((value / 8.0 + 0.5) * 256.0).trunc().clamp(0.0, 255.0) as f64
+}
+
+fn lerp(min: f32, max: f32, factor: f32) -> f32 {
+    min + (max - min) * factor
+}
+
+fn normalize(value: f32, min: f32, max: f32) -> f32 {
+    if max - min < 1.0e-5 {
+        0.0
+    } else {
+        (value - min) / (max - min)
+    }
+}
+
+/// Bounds of a chunk, in the order of [CHUNK_PROPERTY_NAMES]
+struct ChunkBounds([f32; 18]);
+
+impl ChunkBounds {
+    fn min_position(&self, axis: usize) -> f32 {
+        self.0[axis]
+    }
+
+    fn max_position(&self, axis: usize) -> f32 {
+        self.0[3 + axis]
+    }
+
+    fn min_scale(&self, axis: usize) -> f32 {
+        self.0[6 + axis]
+    }
+
+    fn max_scale(&self, axis: usize) -> f32 {
+        self.0[9 + axis]
+    }
+
+    fn min_color(&self, channel: usize) -> f32 {
+        self.0[12 + channel]
+    }
+
+    fn max_color(&self, channel: usize) -> f32 {
+        self.0[15 + channel]
+    }
+
+    /// Gathers the bounds from a chunk record, older files have no color bounds and store colors in [0, 1]
+    fn from_values(property_indices: &[usize], values: &[f64]) -> Self {
+        let mut bounds = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        for (bound, property_index) in bounds.iter_mut().zip(property_indices) {
+            *bound = values[*property_index] as f32;
+        }
+        Self(bounds)
+    }
+
+    /// Computes the bounds enclosing `serialized_splats`
+    fn enclosing(serialized_splats: &[SerializedSplat]) -> Self {
+        let mut bounds = [[
+            f32::INFINITY,
+            f32::INFINITY,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+            f32::NEG_INFINITY,
+        ]; 3];
+        for serialized_splat in serialized_splats {
+            for axis in 0..3 {
+                let values = [
+                    serialized_splat.center[axis],
+                    serialized_splat.scale[axis].clamp(-MAX_LOG_SCALE, MAX_LOG_SCALE),
+                    0.5 + SH_C0 * serialized_splat.color[axis],
+                ];
+                for (bounds, value) in bounds.iter_mut().zip(values.iter()) {
+                    bounds[axis] = bounds[axis].min(*value);
+                    bounds[3 + axis] = bounds[3 + axis].max(*value);
+                }
+            }
+        }
+        let mut chunk_bounds = [0.0; 18];
+        for (attribute, bounds) in bounds.iter().enumerate() {
+            chunk_bounds[attribute * 6..attribute * 6 + 6].copy_from_slice(bounds);
+        }
+        Self(chunk_bounds)
+    }
+
+    /// Dequantizes everything but the higher order spherical harmonics of a splat
+    fn unpack(&self, packed: [u32; 4], serialized_splat: &mut SerializedSplat) {
+        let position = unpack_111011(packed[0]);
+        let scale = unpack_111011(packed[2]);
+        let color = unpack_8888(packed[3]);
+        for axis in 0..3 {
+            serialized_splat.center[axis] = lerp(self.min_position(axis), self.max_position(axis), position[axis]);
+            serialized_splat.scale[axis] = lerp(self.min_scale(axis), self.max_scale(axis), scale[axis]);
+            serialized_splat.color[axis] = (lerp(self.min_color(axis), self.max_color(axis), color[axis]) - 0.5) / SH_C0;
+        }
+        serialized_splat.alpha = inverse_sigmoid(color[3]);
+        serialized_splat.rotation = unpack_rotation(packed[1]);
+    }
+
+    /// Quantizes everything but the higher order spherical harmonics of a splat
+    fn pack(&self, serialized_splat: &SerializedSplat) -> [u32; 4] {
+        let mut position = [0.0; 3];
+        let mut scale = [0.0; 3];
+        let mut color = [0.0; 4];
+        for axis in 0..3 {
+            position[axis] = normalize(serialized_splat.center[axis], self.min_position(axis), self.max_position(axis));
+            scale[axis] = normalize(
+                serialized_splat.scale[axis].clamp(-MAX_LOG_SCALE, MAX_LOG_SCALE),
+                self.min_scale(axis),
+                self.max_scale(axis),
+            );
+            color[axis] = normalize(0.5 + SH_C0 * serialized_splat.color[axis], self.min_color(axis), self.max_color(axis));
+        }
+        color[3] = sigmoid(serialized_splat.alpha);
+        [
+            pack_111011(position),
+            pack_rotation(serialized_splat.rotation),
+            pack_111011(scale),
+            pack_8888(color),
+        ]
+    }
+}
+
+/// Where the elements and properties of a compressed PLY file are
+pub(crate) struct CompressedPlyLayout {
+    chunk_element_index: usize,
+    vertex_element_index: usize,
+    sh_element_index: Option<usize>,
+    chunk_properties: Vec<usize>,
+    packed_properties: [usize; 4],
+    sh_properties: Vec<usize>,
+    pub(crate) spherical_harmonics_degree: usize,
+}
+
+impl CompressedPlyLayout {
+    /// Locates the packed properties, returns `None` if the file is not a compressed PLY file
+    pub(crate) fn from_header(ply: &PlyHeader, vertex_element_index: usize) -> Result<Option<Self>, SceneError> {
+        let vertex_element = &ply.elements[vertex_element_index];
+        if vertex_element.property_index(PACKED_PROPERTY_NAMES[0]).is_none() {
+            return Ok(None);
+        }
+        let malformed = |message: String| SceneError::MalformedHeader(message);
+        if ply.format == PlyFormat::Ascii {
+            return Err(malformed("Compressed PLY files must be binary".into()));
+        }
+        let property = |element: &PlyElement, name: &str| {
+            element
+                .property_index(name)
+                .ok_or_else(|| malformed(format!("Compressed PLY file is missing the {} property {}", element.name, name)))
+        };
+        let chunk_element_index = ply
+            .element_index("chunk")
+            .ok_or_else(|| malformed("Compressed PLY file has no chunk element".into()))?;
+        let chunk_element = &ply.elements[chunk_element_index];
+        if chunk_element.count < vertex_element.count.div_ceil(CHUNK_SIZE) {
+            return Err(malformed("Compressed PLY file has fewer chunks than its splats need".into()));
+        }
+        let chunk_property_count = if chunk_element.property_index("min_r").is_some() { 18 } else { 12 };
+        let chunk_properties = CHUNK_PROPERTY_NAMES[0..chunk_property_count]
+            .iter()
+            .map(|name| property(chunk_element, name))
+            .collect::<Result<_, _>>()?;
+        let mut packed_properties = [0; 4];
+        for (packed_property, name) in packed_properties.iter_mut().zip(PACKED_PROPERTY_NAMES.iter()) {
+            *packed_property = property(vertex_element, name)?;
+        }
+        let sh_element_index = ply.element_index("sh");
+        let sh_properties: Vec<usize> = if let Some(sh_element_index) = sh_element_index {
+            let sh_element = &ply.elements[sh_element_index];
+            if sh_element.count != vertex_element.count {
+                return Err(malformed("Compressed PLY file has a different number of sh and vertex records".into()));
+            }
+            (0..color_rest_count(sh_element))
+                .map(|index| property(sh_element, &format!("f_rest_{}", index)))
+                .collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(Some(Self {
+            chunk_element_index,
+            vertex_element_index,
+            sh_element_index,
+            chunk_properties,
+            packed_properties,
+            spherical_harmonics_degree: spherical_harmonics_degree(sh_properties.len())?,
+            sh_properties,
+        }))
+    }
+
+    /// Reads and dequantizes the splats in `splat_index_range`
+    pub(crate) fn read_splats<R: BufRead + Seek>(
+        &self,
+        ply: &PlyHeader,
+        reader: &mut R,
+        splat_index_range: std::ops::Range<usize>,
+    ) -> Result<Vec<GpuSplat>, SceneError> {
+        let mut values = Vec::new();
+        let mut buffer = Vec::new();
+        let seek_record = |reader: &mut R, element_index: usize, record_index: usize| {
+            let offset = ply.element_offset(element_index) + (record_index * ply.elements[element_index].stride) as u64;
+            reader.seek(SeekFrom::Start(offset))
+        };
+        let chunk_element = &ply.elements[self.chunk_element_index];
+        let chunk_index_range = splat_index_range.start / CHUNK_SIZE..splat_index_range.end.div_ceil(CHUNK_SIZE);
+        seek_record(reader, self.chunk_element_index, chunk_index_range.start)?;
+        let chunks = chunk_index_range
+            .clone()
+            .map(|chunk_index| {
+                let line = ply.record_line(self.chunk_element_index, chunk_index);
+                ply::read_record(reader, ply.format, chunk_element, line, &mut values, &mut buffer)?;
+                Ok(ChunkBounds::from_values(&self.chunk_properties, &values))
+            })
+            .collect::<Result<Vec<_>, SceneError>>()?;
+        let mut serialized_splats = vec![SerializedSplat::default(); splat_index_range.len()];
+        let vertex_element = &ply.elements[self.vertex_element_index];
+        seek_record(reader, self.vertex_element_index, splat_index_range.start)?;
+        for (splat_index, serialized_splat) in splat_index_range.clone().zip(serialized_splats.iter_mut()) {
+            let line = ply.record_line(self.vertex_element_index, splat_index);
+            ply::read_record(reader, ply.format, vertex_element, line, &mut values, &mut buffer)?;
+            let mut packed = [0; 4];
+            for (packed, property_index) in packed.iter_mut().zip(self.packed_properties.iter()) {
+                *packed = values[*property_index] as u32;
+            }
+            chunks[splat_index / CHUNK_SIZE - chunk_index_range.start].unpack(packed, serialized_splat);
+        }
+        if let Some(sh_element_index) = self.sh_element_index {
+            let sh_element = &ply.elements[sh_element_index];
+            let coefficients_per_channel = self.sh_properties.len() / 3;
+            seek_record(reader, sh_element_index, splat_index_range.start)?;
+            for (splat_index, serialized_splat) in splat_index_range.clone().zip(serialized_splats.iter_mut()) {
+                let line = ply.record_line(sh_element_index, splat_index);
+                ply::read_record(reader, ply.format, sh_element, line, &mut values, &mut buffer)?;
+                for (index, property_index) in self.sh_properties.iter().enumerate() {
+                    let channel = index / coefficients_per_channel;
+                    let coefficient = index % coefficients_per_channel;
+                    serialized_splat.color[3 + channel * 15 + coefficient] = unpack_spherical_harmonic(values[*property_index]);
+                }
+            }
+        }
+        Ok(serialized_splats.iter().map(GpuSplat::from).collect())
+    }
+}
+
+/// Quantizes splats into a compressed PLY file
+pub(crate) fn write<W: Write>(writer: &mut W, splat_data: &[GpuSplat], spherical_harmonics_degree: usize) -> std::io::Result<()> {
+    let coefficients_per_channel = (spherical_harmonics_degree + 1) * (spherical_harmonics_degree + 1) - 1;
+    let serialized_splats: Vec<SerializedSplat> = splat_data.iter().map(SerializedSplat::from).collect();
+    let chunks: Vec<ChunkBounds> = serialized_splats.chunks(CHUNK_SIZE).map(ChunkBounds::enclosing).collect();
+    let chunk_properties: Vec<(&str, PlyScalarType)> = CHUNK_PROPERTY_NAMES.iter().map(|name| (*name, PlyScalarType::Float32)).collect();
+    let packed_properties: Vec<(&str, PlyScalarType)> = PACKED_PROPERTY_NAMES.iter().map(|name| (*name, PlyScalarType::UInt32)).collect();
+    let mut elements = vec![
+        PlyElement::new("chunk", chunks.len(), &chunk_properties),
+        PlyElement::new("vertex", serialized_splats.len(), &packed_properties),
+    ];
+    if coefficients_per_channel > 0 {
+        let sh_properties: Vec<(String, PlyScalarType)> = (0..3 * coefficients_per_channel)
+            .map(|index| (format!("f_rest_{}", index), PlyScalarType::UInt8))
+            .collect();
+        elements.push(PlyElement::new("sh", serialized_splats.len(), &sh_properties));
+    }
+    let header = PlyHeader {
+        format: PlyFormat::BinaryLittleEndian,
+        elements,
+        header_size: 0,
+        header_line_count: 0,
+    };
+    header.write(writer)?;
+    let mut values = Vec::new();
+    let mut buffer = Vec::new();
+    for chunk in &chunks {
+        values.clear();
+        values.extend(chunk.0.iter().map(|bound| *bound as f64));
+        ply::write_record(writer, header.format, &header.elements[0], &values, &mut buffer)?;
+    }
+    for (splat_index, serialized_splat) in serialized_splats.iter().enumerate() {
+        values.clear();
+        values.extend(
+            chunks[splat_index / CHUNK_SIZE]
+                .pack(serialized_splat)
+                .iter()
+                .map(|packed| *packed as f64),
+        );
+        ply::write_record(writer, header.format, &header.elements[1], &values, &mut buffer)?;
+    }
+    if coefficients_per_channel > 0 {
+        for serialized_splat in &serialized_splats {
+            values.clear();
+            for channel in 0..3 {
+                let coefficients = &serialized_splat.color[3 + channel * 15..3 + channel * 15 + coefficients_per_channel];
+                values.extend(coefficients.iter().map(|coefficient| pack_spherical_harmonic(*coefficient)));
+            }
+            ply::write_record(writer, header.format, &header.elements[2], &values, &mut buffer)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_and_unpack_rotation() {
+        let rotation = [0.1_f32, -0.7, 0.2, 0.4];
+        let length = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+        let unpacked = unpack_rotation(pack_rotation(rotation));
+        // The largest component is negative, so the quaternion comes back negated
+        for (component, unpacked_component) in rotation.iter().zip(unpacked.iter()) {
+            assert!((-component / length - unpacked_component).abs() < 2.0e-3);
+        }
+    }
+
+    #[test]
+    fn write_and_read() {
+        let splat_data: Vec<GpuSplat> = (0..CHUNK_SIZE + 3)
+            .map(|index| {
+                let t = index as f32 / CHUNK_SIZE as f32;
+                let mut splat = GpuSplat {
+                    rotation: [0.6, 0.0, 0.8, 0.0],
+                    center: [t, -t, 2.0 * t],
+                    scale: [0.01 + t, 0.02, 0.03],
+                    alpha: 0.5,
+                    ..GpuSplat::default()
+                };
+                for (coefficient, value) in splat.color[0..3 * 4].iter_mut().enumerate() {
+                    *value = t - coefficient as f32 * 0.1;
+                }
+                splat
+            })
+            .collect();
+        let mut file = Vec::new();
+        write(&mut file, &splat_data, 1).unwrap();
+        let mut reader = std::io::Cursor::new(file);
+        let ply = PlyHeader::parse(&mut reader).unwrap();
+        let layout = CompressedPlyLayout::from_header(&ply, ply.element_index("vertex").unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(layout.spherical_harmonics_degree, 1);
+        let read_splat_data = layout.read_splats(&ply, &mut reader, 2..CHUNK_SIZE + 3).unwrap();
+        for (splat, read_splat) in splat_data[2..].iter().zip(read_splat_data.iter()) {
+            let values = bytemuck::cast_slice::<GpuSplat, f32>(std::slice::from_ref(splat));
+            let read_values = bytemuck::cast_slice::<GpuSplat, f32>(std::slice::from_ref(read_splat));
+            for (index, (value, read_value)) in values.iter().zip(read_values.iter()).enumerate() {
+                let tolerance = if index < 12 { 0.01 } else { 0.04 };
+                assert!(
+                    (value - read_value).abs() <= tolerance,
+                    "value {} is {} instead of {}",
+                    index,
+                    read_value,
+                    value
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Depth maps of the rendered frames, by rendering the splats again into float targets
+//!
+//! Every fragment has the view space depth of the center of its splat.
+//! The median depth is bisected per pixel, every step renders the transmittance of the splats up to the middle of the bounds of each pixel.
+//! The expected depth is blended front to back like the colors, relative to the depth the bisection ended at.
+use crate::{
+    offscreen::{read_buffer, FloatImage},
+    renderer::{Renderer, FLOAT_FRAME_FORMAT, FRONT_TO_BACK_BLENDING},
+    scene::{Scene, SceneError},
+    utils::transmute_slice,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Bounds of the bisection of the median depth, or the reference depth of the expected depth, the bits of zero and of infinity
+///
+/// Positive floats are ordered like their bits, so 31 steps narrow the bounds down to the depth of a single splat.
+const BISECTION_BOUNDS: [u32; 2] = [0, 0x7F80_0000];
+const BISECTION_STEPS: usize = 31;
+
+/// Depth maps of a frame rendered with [Renderer::render_frame], in the units of the scene along the optical axis
+///
+/// Pixels which no splat covers are infinite.
+/// The median depths are exact. The expected depths are accumulated in half precision relative to the median,
+/// so their error grows with the spread of the depths blended into a pixel rather than with the distance to the camera,
+/// about a thousandth of the spread.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthImage {
+    /// Average depth of the splats blended into each pixel, weighted by their contributions
+    pub expected: FloatImage,
+    /// Depth of the splat at which the transmittance of each pixel first falls below 0.5
+    ///
+    /// Infinite where the accumulated opacity stays below 0.5.
+    pub median: FloatImage,
+}
+
+/// Pipelines of the depth passes, which share the vertex shader of the splats
+pub(crate) struct DepthPipelines {
+    bisection_bind_group_layout: wgpu::BindGroupLayout,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    transmittance_pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+    bisection_pipeline: wgpu::RenderPipeline,
+    bisect_pipeline: wgpu::ComputePipeline,
+    resolve_pipeline: wgpu::ComputePipeline,
+}
+
+impl DepthPipelines {
+    pub(crate) fn new(device: &wgpu::Device, shader_module: &wgpu::ShaderModule, render_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let bisection_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth bisection"),
+            entries: &[storage_buffer_layout(0, wgpu::ShaderStages::FRAGMENT, true)],
+        });
+        let depth_texture_layout = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let compute_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth"),
+            entries: &[
+                depth_texture_layout(10),
+                depth_texture_layout(11),
+                storage_buffer_layout(12, wgpu::ShaderStages::COMPUTE, false),
+                storage_buffer_layout(13, wgpu::ShaderStages::COMPUTE, false),
+            ],
+        });
+        let create_render_pipeline = |bind_group_layouts: &[&wgpu::BindGroupLayout], entry_point: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(entry_point),
+                    bind_group_layouts,
+                    push_constant_ranges: &[],
+                })),
+                vertex: wgpu::VertexState {
+                    module: shader_module,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader_module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FLOAT_FRAME_FORMAT,
+                        blend: Some(FRONT_TO_BACK_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let compute_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth"),
+            bind_group_layouts: &[&compute_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_compute_pipeline = |entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&compute_pipeline_layout),
+                module: shader_module,
+                entry_point,
+            })
+        };
+        Self {
+            transmittance_pipeline: create_render_pipeline(&[render_bind_group_layout], "transmittanceFragment"),
+            depth_pipeline: create_render_pipeline(&[render_bind_group_layout, &bisection_bind_group_layout], "depthFragment"),
+            bisection_pipeline: create_render_pipeline(&[render_bind_group_layout, &bisection_bind_group_layout], "depthBisectionFragment"),
+            bisect_pipeline: create_compute_pipeline("bisectDepth"),
+            resolve_pipeline: create_compute_pipeline("resolveDepth"),
+            bisection_bind_group_layout,
+            compute_bind_group_layout,
+        }
+    }
+}
+
+/// Begins a pass of the splats into a cleared float texture
+fn begin_render_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("depth pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                // Zero with a transmittance of one
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
+
+fn storage_buffer_layout(binding: u32, visibility: wgpu::ShaderStages, read_only: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Textures and buffers the depth maps of an [OffscreenTarget](crate::offscreen::OffscreenTarget) are rendered into
+pub(crate) struct DepthTarget {
+    width: u32,
+    height: u32,
+    /// The weighted depth relative to the upper bisection bound in the red channel and the transmittance in the alpha channel
+    accumulation_view: wgpu::TextureView,
+    /// The transmittance of the splats up to the middle of the bisection bounds in the alpha channel
+    transmittance_view: wgpu::TextureView,
+    /// Lower and upper bisection bound of each pixel, as the bits of the depths
+    bisection_buffer: wgpu::Buffer,
+    /// The expected depth map followed by the median depth map
+    depth_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    bisection_bind_group: wgpu::BindGroup,
+    compute_bind_group: wgpu::BindGroup,
+}
+
+impl DepthTarget {
+    pub(crate) fn new(device: &wgpu::Device, renderer: &Renderer, width: u32, height: u32) -> Self {
+        let create_view = |label: &str| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: FLOAT_FRAME_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let accumulation_view = create_view("depth accumulation");
+        let transmittance_view = create_view("depth transmittance");
+        let pixel_count = (width * height) as u64;
+        let bisection_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth bisection"),
+            size: pixel_count * std::mem::size_of::<[u32; 2]>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let depth_size = 2 * pixel_count * std::mem::size_of::<f32>() as u64;
+        let depth_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth maps"),
+            size: depth_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth readback"),
+            size: depth_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let depth_pipelines = &renderer.depth_pipelines;
+        let bisection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("depth bisection"),
+            layout: &depth_pipelines.bisection_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: bisection_buffer.as_entire_binding(),
+            }],
+        });
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("depth"),
+            layout: &depth_pipelines.compute_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::TextureView(&accumulation_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: wgpu::BindingResource::TextureView(&transmittance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: bisection_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: depth_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        Self {
+            width,
+            height,
+            accumulation_view,
+            transmittance_view,
+            bisection_buffer,
+            depth_buffer,
+            readback_buffer,
+            bisection_bind_group,
+            compute_bind_group,
+        }
+    }
+
+    /// Encodes the depth passes after the splats of a frame have been sorted, and the copy of the depth maps into the readback buffer
+    ///
+    /// Renders the splats once for the final transmittance, once per bisection step and once more for the expected depth.
+    pub(crate) fn encode(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, renderer: &Renderer, scene: &Scene, splat_count: usize) {
+        let depth_pipelines = &renderer.depth_pipelines;
+        let bounds = vec![BISECTION_BOUNDS; (self.width * self.height) as usize];
+        queue.write_buffer(&self.bisection_buffer, 0, transmute_slice::<_, u8>(&bounds));
+        let workgroups = [self.width.div_ceil(8), self.height.div_ceil(8)];
+        // The final transmittance, which the bisection threshold depends on
+        {
+            let mut render_pass = begin_render_pass(encoder, &self.accumulation_view);
+            render_pass.set_pipeline(&depth_pipelines.transmittance_pipeline);
+            renderer.draw_splats(&mut render_pass, scene, splat_count);
+        }
+        for _ in 0..BISECTION_STEPS {
+            {
+                let mut render_pass = begin_render_pass(encoder, &self.transmittance_view);
+                render_pass.set_pipeline(&depth_pipelines.bisection_pipeline);
+                render_pass.set_bind_group(1, &self.bisection_bind_group, &[]);
+                renderer.draw_splats(&mut render_pass, scene, splat_count);
+            }
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("depth bisection"),
+            });
+            compute_pass.set_pipeline(&depth_pipelines.bisect_pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+        }
+        {
+            let mut render_pass = begin_render_pass(encoder, &self.accumulation_view);
+            render_pass.set_pipeline(&depth_pipelines.depth_pipeline);
+            render_pass.set_bind_group(1, &self.bisection_bind_group, &[]);
+            renderer.draw_splats(&mut render_pass, scene, splat_count);
+        }
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("depth resolve"),
+            });
+            compute_pass.set_pipeline(&depth_pipelines.resolve_pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.depth_buffer, 0, &self.readback_buffer, 0, self.depth_buffer.size());
+    }
+
+    /// Reads the depth maps back once the commands of [DepthTarget::encode] are submitted
+    pub(crate) fn read(&self, device: &wgpu::Device) -> Result<DepthImage, SceneError> {
+        let depths: Vec<f32> = read_buffer(device, &self.readback_buffer, |bytes| transmute_slice::<u8, f32>(bytes).to_vec())?;
+        let (expected, median) = depths.split_at((self.width * self.height) as usize);
+        Ok(DepthImage {
+            expected: FloatImage::from_raw(self.width, self.height, expected.to_vec()).unwrap(),
+            median: FloatImage::from_raw(self.width, self.height, median.to_vec()).unwrap(),
+        })
+    }
+}
+
+/// Writes a depth map to a 16 bit grayscale PNG file, as `depth * depth_scale` rounded to integers
+///
+/// Pixels which no splat covers become 0, depths beyond the range are clamped to 65535.
+pub fn save_png16<P: AsRef<Path>>(depth: &FloatImage, path: P, depth_scale: f32) -> Result<(), SceneError> {
+    let pixels = depth
+        .iter()
+        .map(|depth| {
+            if depth.is_finite() {
+                (depth * depth_scale).round().clamp(0.0, u16::MAX as f32) as u16
+            } else {
+                0
+            }
+        })
+        .collect();
+    image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(depth.width(), depth.height(), pixels)
+        .unwrap()
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|error| SceneError::Io(std::io::Error::other(error)))
+}
+
+/// Writes a depth map to a grayscale PFM file, which keeps the floats including the infinite depths
+pub fn save_pfm<P: AsRef<Path>>(depth: &FloatImage, path: P) -> Result<(), SceneError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_pfm(&mut writer, depth)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The negative scale marks little endian floats, the rows are stored from the bottom to the top
+fn write_pfm<W: Write>(writer: &mut W, depth: &FloatImage) -> std::io::Result<()> {
+    write!(writer, "Pf\n{} {}\n-1.0\n", depth.width(), depth.height())?;
+    for row in depth.as_raw().chunks_exact(depth.width().max(1) as usize).rev() {
+        for depth in row {
+            writer.write_all(&depth.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, offscreen::OffscreenTarget, renderer::DepthSorting, scene::GpuSplat, utils::test_renderer};
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn expected_and_median_depth() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 64);
+        // Two splats in front of each other at depths 2 and 4, and a faint one at depth 6 covering the whole frame,
+        // all a little off the optical axis, where the projected ellipses degenerate
+        let splat = |depth: f32, scale: f32, alpha: f32| GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center: [0.01, 0.02, depth - 5.0],
+            scale: [scale, 0.9 * scale, 0.8 * scale],
+            alpha,
+            ..GpuSplat::default()
+        };
+        let mut scene = Scene::new(&device, &renderer, 0).unwrap();
+        scene
+            .append_splats(
+                &device,
+                &queue,
+                &renderer,
+                &[splat(4.0, 0.5, 0.6), splat(6.0, 3.0, 0.3), splat(2.0, 0.5, 0.45)],
+            )
+            .unwrap();
+        let mut target = OffscreenTarget::new(&device, &renderer, 64, 64);
+        target.output_depth = true;
+        let depth = target
+            .render_to_image(&device, &queue, &renderer, &scene, &camera)
+            .unwrap()
+            .depth
+            .unwrap();
+
+        // The transmittance falls below 0.5 at the second splat
+        let expected_depth = (0.45 * 2.0 + 0.55 * 0.6 * 4.0 + 0.55 * 0.4 * 0.3 * 6.0) / (1.0 - 0.55 * 0.4 * 0.7);
+        assert!((depth.expected.get_pixel(31, 31)[0] - expected_depth).abs() < 0.02);
+        assert!((depth.median.get_pixel(31, 31)[0] - 4.0).abs() < 1.0e-5);
+        // Only the faint splat reaches the corners
+        assert!((depth.expected.get_pixel(0, 0)[0] - 6.0).abs() < 1.0e-5);
+        assert_eq!(depth.median.get_pixel(0, 0)[0], f32::INFINITY);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn expected_depth_keeps_its_precision_far_away() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let mut camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 64);
+        // The splats are sorted by their depths in clip space, which the near plane has to leave apart
+        camera.near = 1.0;
+        camera.far = 10000.0;
+        // Half floats resolve depths around 1000 only to 0.5
+        let splat = |depth: f32, alpha: f32| GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center: [2.0, 4.0, depth - 5.0],
+            scale: [100.0, 90.0, 80.0],
+            alpha,
+            ..GpuSplat::default()
+        };
+        let mut scene = Scene::new(&device, &renderer, 0).unwrap();
+        scene
+            .append_splats(&device, &queue, &renderer, &[splat(1000.6, 0.25), splat(1000.2, 0.25)])
+            .unwrap();
+        let mut target = OffscreenTarget::new(&device, &renderer, 64, 64);
+        target.output_depth = true;
+        let depth = target
+            .render_to_image(&device, &queue, &renderer, &scene, &camera)
+            .unwrap()
+            .depth
+            .unwrap();
+
+        // The opacity stays below 0.5, the reference depth is the one where it reaches half of its final value
+        let expected_depth = (0.25 * 1000.2 + 0.75 * 0.25 * 1000.6) / (1.0 - 0.75 * 0.75);
+        assert!((depth.expected.get_pixel(31, 31)[0] - expected_depth).abs() < 0.01);
+        assert_eq!(depth.median.get_pixel(31, 31)[0], f32::INFINITY);
+    }
+
+    #[test]
+    fn pfm_rows_are_stored_bottom_up() {
+        let depth = FloatImage::from_raw(2, 2, vec![1.0, 2.0, 3.0, f32::INFINITY]).unwrap();
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, &depth).unwrap();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let values: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(values, vec![3.0, f32::INFINITY, 1.0, 2.0]);
+    }
+}
@@ -0,0 +1,292 @@
+//! Interactive editing of the splats of a scene, with an undo history
+use crate::{
+    picking::PickResult,
+    region::Region,
+    renderer::Renderer,
+    scene::{index_runs, GpuSplat, Scene, SceneError},
+};
+
+/// How a new selection is combined with the current one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+}
+
+/// An operation in the undo or redo history
+enum Edit {
+    /// The splats at these indices were overwritten
+    Modify {
+        splat_indices: Vec<usize>,
+        before: Vec<GpuSplat>,
+        after: Vec<GpuSplat>,
+    },
+    /// The splats at these indices were removed, the indices are those before the removal
+    Delete { splat_indices: Vec<usize>, splat_data: Vec<GpuSplat> },
+}
+
+/// Selects splats of a [Scene] and edits them
+///
+/// Every edit is recorded, so that it can be undone and redone.
+/// Only the edited splats are uploaded to the GPU again, except for deletions and their undo which move the following splats.
+pub struct SceneEditor {
+    scene: Scene,
+    /// Indices of the selected splats, ascending
+    selection: Vec<usize>,
+    undo_history: Vec<Edit>,
+    redo_history: Vec<Edit>,
+}
+
+impl SceneEditor {
+    pub fn new(scene: Scene) -> Self {
+        Self {
+            scene,
+            selection: Vec::new(),
+            undo_history: Vec::new(),
+            redo_history: Vec::new(),
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Ends editing, returning the edited scene
+    pub fn into_scene(self) -> Scene {
+        self.scene
+    }
+
+    /// Indices of the selected splats, ascending
+    pub fn selection(&self) -> &[usize] {
+        &self.selection
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    /// Combines the given splats with the selection, ignoring indices of splats which are not loaded
+    pub fn select_splats(&mut self, splat_indices: &[usize], mode: SelectionMode) {
+        let mut splat_indices: Vec<usize> = splat_indices
+            .iter()
+            .copied()
+            .filter(|splat_index| *splat_index < self.scene.loaded_splat_count)
+            .collect();
+        splat_indices.sort_unstable();
+        splat_indices.dedup();
+        match mode {
+            SelectionMode::Replace => self.selection = splat_indices,
+            SelectionMode::Add => {
+                self.selection.extend(splat_indices);
+                self.selection.sort_unstable();
+                self.selection.dedup();
+            }
+            SelectionMode::Subtract => self.selection.retain(|splat_index| splat_indices.binary_search(splat_index).is_err()),
+        }
+    }
+
+    /// Selects the splats whose centers lie inside the region
+    pub fn select_region(&mut self, region: &Region, mode: SelectionMode) {
+        let splat_indices: Vec<usize> = self
+            .scene
+            .spatial_index()
+            .query_box(&region.bounding_box())
+            .into_iter()
+            .filter(|splat_index| region.contains(self.scene.splat_summaries[*splat_index].center))
+            .collect();
+        self.select_splats(&splat_indices, mode);
+    }
+
+    /// Selects the dominant splat found by [Renderer::pick]
+    pub fn select_picked(&mut self, pick_result: &PickResult, mode: SelectionMode) {
+        self.select_splats(&[pick_result.splat_index], mode);
+    }
+
+    /// Selects the splats for which the predicate holds, for example on their [GpuSplat::base_color] or opacity
+    pub fn select_where<F: Fn(&GpuSplat) -> bool>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        predicate: F,
+        mode: SelectionMode,
+    ) -> Result<(), SceneError> {
+        let splat_data = self.scene.download_splats(device, queue, 0..self.scene.loaded_splat_count)?;
+        let splat_indices: Vec<usize> = (0..splat_data.len()).filter(|splat_index| predicate(&splat_data[*splat_index])).collect();
+        self.select_splats(&splat_indices, mode);
+        Ok(())
+    }
+
+    /// Removes the selected splats from the scene and clears the selection
+    pub fn delete_selection(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer) -> Result<(), SceneError> {
+        if self.selection.is_empty() {
+            return Ok(());
+        }
+        let splat_indices = std::mem::take(&mut self.selection);
+        let splat_data = self.scene.download_splat_indices(device, queue, &splat_indices)?;
+        self.scene.remove_splats(device, queue, renderer, &splat_indices)?;
+        self.record(Edit::Delete { splat_indices, splat_data });
+        Ok(())
+    }
+
+    /// Overrides the view independent color of the selected splats, see [GpuSplat::set_base_color]
+    pub fn recolor_selection(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, color: [f32; 3]) -> Result<(), SceneError> {
+        self.modify_selection(device, queue, |splat| splat.set_base_color(color))
+    }
+
+    /// Sets the opacity of the selected splats, in [0, 1]
+    pub fn set_selection_opacity(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, opacity: f32) -> Result<(), SceneError> {
+        self.modify_selection(device, queue, |splat| splat.alpha = opacity.clamp(0.0, 1.0))
+    }
+
+    /// Moves the selected splats by `offset`
+    pub fn translate_selection(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, offset: [f32; 3]) -> Result<(), SceneError> {
+        self.modify_selection(device, queue, |splat| {
+            for (component, offset) in splat.center.iter_mut().zip(offset) {
+                *component += offset;
+            }
+        })
+    }
+
+    fn modify_selection<F: Fn(&mut GpuSplat)>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, modify: F) -> Result<(), SceneError> {
+        if self.selection.is_empty() {
+            return Ok(());
+        }
+        let before = self.scene.download_splat_indices(device, queue, &self.selection)?;
+        let mut after = before.clone();
+        after.iter_mut().for_each(modify);
+        let splat_indices = self.selection.clone();
+        self.upload_runs(queue, &splat_indices, &after);
+        self.record(Edit::Modify {
+            splat_indices,
+            before,
+            after,
+        });
+        Ok(())
+    }
+
+    fn record(&mut self, edit: Edit) {
+        self.undo_history.push(edit);
+        self.redo_history.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_history.is_empty()
+    }
+
+    /// Reverts the last edit, returns false if there is none
+    ///
+    /// Undoing a deletion reinserts the splats at their old indices and clears the selection.
+    pub fn undo(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer) -> Result<bool, SceneError> {
+        let edit = match self.undo_history.pop() {
+            Some(edit) => edit,
+            None => return Ok(false),
+        };
+        match &edit {
+            Edit::Modify { splat_indices, before, .. } => self.upload_runs(queue, splat_indices, before),
+            Edit::Delete { splat_indices, splat_data } => {
+                self.selection.clear();
+                self.scene.insert_splats(device, queue, renderer, splat_indices, splat_data)?;
+            }
+        }
+        self.redo_history.push(edit);
+        Ok(true)
+    }
+
+    /// Applies the last undone edit again, returns false if there is none
+    pub fn redo(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer) -> Result<bool, SceneError> {
+        let edit = match self.redo_history.pop() {
+            Some(edit) => edit,
+            None => return Ok(false),
+        };
+        match &edit {
+            Edit::Modify { splat_indices, after, .. } => self.upload_runs(queue, splat_indices, after),
+            Edit::Delete { splat_indices, .. } => {
+                self.selection.clear();
+                self.scene.remove_splats(device, queue, renderer, splat_indices)?;
+            }
+        }
+        self.undo_history.push(edit);
+        Ok(true)
+    }
+
+    /// Uploads the splats at the given ascending indices, one run of consecutive indices at a time
+    fn upload_runs(&mut self, queue: &wgpu::Queue, splat_indices: &[usize], splat_data: &[GpuSplat]) {
+        for (splat_index, positions) in index_runs(splat_indices) {
+            self.scene.upload_splats(queue, splat_index, &splat_data[positions]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        renderer::DepthSorting,
+        utils::{numbered_splats, splat_numbers, test_renderer},
+    };
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn selection_runs_are_merged() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let mut scene = Scene::new(&device, &renderer, 0).unwrap();
+        scene.append_splats(&device, &queue, &renderer, &numbered_splats(0..8)).unwrap();
+        let mut editor = SceneEditor::new(scene);
+        editor.select_splats(&[5, 1, 2, 1, 9], SelectionMode::Replace);
+        assert_eq!(editor.selection(), [1, 2, 5]);
+        editor.select_splats(&[3, 2], SelectionMode::Add);
+        assert_eq!(editor.selection(), [1, 2, 3, 5]);
+        editor.select_splats(&[2, 6], SelectionMode::Subtract);
+        assert_eq!(editor.selection(), [1, 3, 5]);
+        editor.select_splats(&[2], SelectionMode::Add);
+        assert_eq!(editor.selection(), [1, 2, 3, 5]);
+
+        // The runs 1..4 and 5..6 are uploaded
+        editor.translate_selection(&device, &queue, [10.0, 0.0, 0.0]).unwrap();
+        let translated = [0.0, 11.0, 12.0, 13.0, 4.0, 15.0, 6.0, 7.0];
+        assert_eq!(splat_numbers(&device, &queue, editor.scene()), translated);
+        assert!(editor.undo(&device, &queue, &renderer).unwrap());
+        assert_eq!(splat_numbers(&device, &queue, editor.scene()), [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert!(editor.redo(&device, &queue, &renderer).unwrap());
+        assert_eq!(splat_numbers(&device, &queue, editor.scene()), translated);
+        assert!(!editor.redo(&device, &queue, &renderer).unwrap());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn undo_deletion_with_unloaded_splats() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        // The last two splats are not loaded yet
+        let mut scene = Scene::new(&device, &renderer, 8).unwrap();
+        scene.upload_splats(&queue, 0, &numbered_splats(0..8));
+        scene.loaded_splat_count = 6;
+        let mut editor = SceneEditor::new(scene);
+        editor.select_splats(&[1, 2, 4], SelectionMode::Replace);
+        editor.delete_selection(&device, &queue, &renderer).unwrap();
+        let scene = editor.scene();
+        assert_eq!((scene.splat_count, scene.loaded_splat_count), (5, 3));
+        assert_eq!(splat_numbers(&device, &queue, scene), [0.0, 3.0, 5.0, 6.0, 7.0]);
+        assert!(editor.selection().is_empty());
+
+        assert!(editor.undo(&device, &queue, &renderer).unwrap());
+        let scene = editor.scene();
+        assert_eq!((scene.splat_count, scene.loaded_splat_count), (8, 6));
+        assert_eq!(splat_numbers(&device, &queue, scene), [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        for splat_index in 0..6 {
+            assert_eq!(scene.splat_summaries[splat_index].center, [splat_index as f32, 0.0, 0.0]);
+            assert_eq!(scene.splat_positions.as_ref().unwrap()[splat_index * 3], splat_index as f32);
+        }
+        let mut indexed_splats = scene.spatial_index().query_box(&[[-0.5, -1.0, -1.0], [5.5, 1.0, 1.0]]);
+        indexed_splats.sort_unstable();
+        assert_eq!(indexed_splats, [0, 1, 2, 3, 4, 5]);
+        assert!(!editor.undo(&device, &queue, &renderer).unwrap());
+
+        assert!(editor.redo(&device, &queue, &renderer).unwrap());
+        assert_eq!(splat_numbers(&device, &queue, editor.scene()), [0.0, 3.0, 5.0, 6.0, 7.0]);
+    }
+}
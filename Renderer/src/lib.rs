@@ -1,3 +1,22 @@
+pub mod camera;
+pub mod cleanup;
+pub mod codebook;
+pub mod colmap;
+pub mod composition;
+mod compressed_ply;
+pub mod depth;
+pub mod editor;
+pub mod loader;
+pub mod offscreen;
+pub mod picking;
+pub mod ply;
+pub mod procedural;
+#[cfg(test)]
+mod read_cam;
+pub mod region;
 pub mod renderer;
 pub mod scene;
+pub mod spatial_index;
+pub mod statistics;
+pub mod transform;
 mod utils;
@@ -0,0 +1,211 @@
+//! Progressive loading of splat files on a worker thread
+use crate::scene::{GpuSplat, Scene, SceneError, SplatFileHeader, SplatFileReader};
+use std::{
+    ops::Range,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread::JoinHandle,
+};
+
+/// How many splats of the file have been uploaded or skipped so far
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub loaded_splat_count: usize,
+    pub splat_count: usize,
+}
+
+impl LoadProgress {
+    /// Loaded fraction of the file, in [0, 1]
+    pub fn fraction(&self) -> f32 {
+        if self.splat_count == 0 {
+            1.0
+        } else {
+            self.loaded_splat_count as f32 / self.splat_count as f32
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.loaded_splat_count >= self.splat_count
+    }
+}
+
+/// A chunk of splats read by the worker thread
+struct LoadedChunk {
+    splat_index_range: Range<usize>,
+    /// The splats in the range which pass the filters of the header
+    splat_data: Vec<GpuSplat>,
+}
+
+/// Reads a splat file in fixed-size chunks on a worker thread
+///
+/// The worker thread parses and converts the splats, while the owner of the [wgpu::Queue]
+/// uploads the finished chunks with [SceneLoader::upload_ready_chunks] between frames.
+/// As chunks are uploaded in order, [Renderer::render_frame](crate::renderer::Renderer::render_frame)
+/// renders all splats uploaded so far.
+pub struct SceneLoader {
+    receiver: Receiver<Result<LoadedChunk, SceneError>>,
+    worker: Option<JoinHandle<()>>,
+    spherical_harmonics_degree: usize,
+    filtered: bool,
+    progress: LoadProgress,
+}
+
+impl SceneLoader {
+    /// Starts reading the splats described by `header` from `file`, `chunk_size` splats at a time
+    ///
+    /// Reads at most `splat_count` splats, which should be the `splat_count` of the [Scene] they are loaded into.
+    pub fn new(mut file: SplatFileReader, header: SplatFileHeader, splat_count: usize, chunk_size: usize) -> Self {
+        let splat_count = splat_count.min(header.splat_count);
+        let chunk_size = chunk_size.max(1);
+        let spherical_harmonics_degree = header.spherical_harmonics_degree;
+        let filtered = !header.filters.is_empty();
+        // The channel is bounded to keep the worker thread from reading the entire file into memory ahead of the uploads
+        let (sender, receiver) = mpsc::sync_channel(4);
+        let worker = std::thread::spawn(move || {
+            for splat_index_offset in (0..splat_count).step_by(chunk_size) {
+                let splat_index_range = splat_index_offset..(splat_index_offset + chunk_size).min(splat_count);
+                let chunk = header.read_splats(&mut file, splat_index_range.clone()).map(|splat_data| LoadedChunk {
+                    splat_index_range,
+                    splat_data,
+                });
+                let failed = chunk.is_err();
+                // Stop when the loader was dropped or the file could not be read
+                if sender.send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self {
+            receiver,
+            worker: Some(worker),
+            spherical_harmonics_degree,
+            filtered,
+            progress: LoadProgress {
+                loaded_splat_count: 0,
+                splat_count,
+            },
+        }
+    }
+
+    /// How many splats have been uploaded so far
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    /// Uploads the chunks which the worker thread has finished reading, without blocking
+    pub fn upload_ready_chunks(&mut self, queue: &wgpu::Queue, scene: &mut Scene) -> Result<LoadProgress, SceneError> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(chunk) => self.upload_chunk(queue, scene, chunk?)?,
+                Err(TryRecvError::Empty) => return Ok(self.progress),
+                Err(TryRecvError::Disconnected) => return self.finish_worker(),
+            }
+        }
+    }
+
+    /// Uploads all remaining chunks, blocking until the whole file is loaded
+    ///
+    /// Calls `progress` after every uploaded chunk.
+    pub fn finish<F: FnMut(LoadProgress)>(mut self, queue: &wgpu::Queue, scene: &mut Scene, mut progress: F) -> Result<(), SceneError> {
+        while let Ok(chunk) = self.receiver.recv() {
+            self.upload_chunk(queue, scene, chunk?)?;
+            progress(self.progress);
+        }
+        self.finish_worker().map(|_| ())
+    }
+
+    /// Fails if the chunk reaches beyond the `splat_count` of the scene
+    fn upload_chunk(&mut self, queue: &wgpu::Queue, scene: &mut Scene, chunk: LoadedChunk) -> Result<(), SceneError> {
+        scene.check_file_chunk(&chunk.splat_index_range, self.filtered)?;
+        self.progress.loaded_splat_count = chunk.splat_index_range.end;
+        scene.upload_file_chunk(
+            queue,
+            chunk.splat_index_range,
+            &chunk.splat_data,
+            self.filtered,
+            self.spherical_harmonics_degree,
+        );
+        Ok(())
+    }
+
+    fn finish_worker(&mut self) -> Result<LoadProgress, SceneError> {
+        if let Some(worker) = self.worker.take() {
+            worker
+                .join()
+                .map_err(|_| SceneError::Io(std::io::Error::other("the loader thread panicked")))?;
+        }
+        Ok(self.progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ply::{self, PlyElement, PlyFormat, PlyHeader, PlyScalarType};
+
+    const PROPERTIES: [&str; 14] = [
+        "x", "y", "z", "f_dc_0", "f_dc_1", "f_dc_2", "opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3",
+    ];
+
+    /// Writes a 3DGS PLY file without higher order spherical harmonics and returns its path
+    fn write_ply_file(format: PlyFormat, splat_count: usize) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("loader_{:?}_{}.ply", format, std::process::id()));
+        let properties: Vec<(&str, PlyScalarType)> = PROPERTIES.iter().map(|name| (*name, PlyScalarType::Float32)).collect();
+        let header = PlyHeader {
+            format,
+            elements: vec![PlyElement::new("vertex", splat_count, &properties)],
+            header_size: 0,
+            header_line_count: 0,
+        };
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        let mut buffer = Vec::new();
+        for splat_index in 0..splat_count {
+            let values: Vec<f64> = (0..PROPERTIES.len())
+                .map(|property_index| (splat_index * PROPERTIES.len() + property_index) as f64 * 0.125)
+                .collect();
+            ply::write_record(&mut bytes, format, &header.elements[0], &values, &mut buffer).unwrap();
+        }
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn chunked_load_matches_one_shot_load() {
+        for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian] {
+            let path = write_ply_file(format, 7);
+            let (header, mut file) = Scene::open_file(&path).unwrap();
+            let one_shot = header.read_splats(&mut file, 0..7).unwrap();
+
+            let (header, file) = Scene::open_file(&path).unwrap();
+            let loader = SceneLoader::new(file, header, 7, 3);
+            let chunks: Vec<LoadedChunk> = loader.receiver.iter().map(Result::unwrap).collect();
+            assert_eq!(
+                chunks.iter().map(|chunk| chunk.splat_index_range.clone()).collect::<Vec<_>>(),
+                vec![0..3, 3..6, 6..7]
+            );
+            let chunked: Vec<GpuSplat> = chunks.into_iter().flat_map(|chunk| chunk.splat_data).collect();
+            assert_eq!(
+                bytemuck::cast_slice::<GpuSplat, u8>(&chunked),
+                bytemuck::cast_slice::<GpuSplat, u8>(&one_shot)
+            );
+
+            // Going back to an earlier chunk seeks to it
+            let earlier = header_and_read(&path, &[5..7, 1..3]);
+            assert_eq!(
+                bytemuck::cast_slice::<GpuSplat, u8>(&earlier),
+                bytemuck::cast_slice::<GpuSplat, u8>(&one_shot[1..3])
+            );
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    /// Reads the chunks in order with one reader and returns the splats of the last one
+    fn header_and_read(path: &std::path::Path, chunks: &[Range<usize>]) -> Vec<GpuSplat> {
+        let (header, mut file) = Scene::open_file(path).unwrap();
+        let mut splat_data = Vec::new();
+        for chunk in chunks {
+            splat_data = header.read_splats(&mut file, chunk.clone()).unwrap();
+        }
+        splat_data
+    }
+}
@@ -0,0 +1,338 @@
+//! Headless rendering into a texture which is read back into an image
+use crate::{
+    camera::Camera,
+    depth::{DepthImage, DepthTarget},
+    renderer::{Renderer, FLOAT_FRAME_FORMAT},
+    scene::{Scene, SceneError},
+    utils::f16_bits_to_f32,
+};
+
+/// Single channel image of floats
+pub type FloatImage = image::ImageBuffer<image::Luma<f32>, Vec<f32>>;
+
+/// How the colors of a [RenderedImage] relate to its alpha channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Colors multiplied by the alpha channel, as they are blended
+    Premultiplied,
+    /// Colors divided by the alpha channel, as image files like PNG expect them
+    Straight,
+}
+
+/// A frame read back by [OffscreenTarget::render_to_image]
+pub struct RenderedImage {
+    /// Colors in the [OffscreenTarget::alpha_mode], the alpha channel holds the accumulated opacity including the background
+    pub color: image::RgbaImage,
+    /// Accumulated opacity in [0, 1] as read from the float frame, if [OffscreenTarget::output_alpha] is set
+    pub alpha: Option<FloatImage>,
+    /// Depth maps of the frame, if [OffscreenTarget::output_depth] is set
+    pub depth: Option<DepthImage>,
+}
+
+/// Texture the [Renderer] renders into without a window, with a readback buffer reused for every frame
+///
+/// The texture has the [FLOAT_FRAME_FORMAT], the colors are only quantized to 8 bits when they are read back.
+pub struct OffscreenTarget {
+    texture: wgpu::Texture,
+    /// Whether the colors are encoded to sRGB when they are read back, like the frame buffer format of the renderer does
+    encode_srgb: bool,
+    readback_buffer: wgpu::Buffer,
+    /// Bytes per row of the readback buffer, padded to [wgpu::COPY_BYTES_PER_ROW_ALIGNMENT]
+    padded_bytes_per_row: u32,
+    /// Created with the first frame whose depth maps are rendered
+    depth_target: std::cell::OnceCell<DepthTarget>,
+    /// How the colors of the read back frames relate to their alpha channels
+    pub alpha_mode: AlphaMode,
+    /// Also returns the accumulated opacity as floats
+    pub output_alpha: bool,
+    /// Also renders the depth maps, which is much slower than rendering the frame
+    pub output_depth: bool,
+}
+
+impl OffscreenTarget {
+    /// Creates a target of the given size, whose images are encoded like the frame buffer format of the renderer
+    pub fn new(device: &wgpu::Device, renderer: &Renderer, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FLOAT_FRAME_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let padded_bytes_per_row = (PIXEL_SIZE * width).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            texture,
+            encode_srgb: renderer.config.surface_configuration.format.is_srgb(),
+            readback_buffer,
+            padded_bytes_per_row,
+            depth_target: std::cell::OnceCell::new(),
+            alpha_mode: AlphaMode::Premultiplied,
+            output_alpha: false,
+            output_depth: false,
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn size(&self) -> wgpu::Extent3d {
+        self.texture.size()
+    }
+
+    /// Renders the scene with [Renderer::render_frame] and reads the frame back
+    pub fn render_to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        scene: &Scene,
+        camera: &Camera,
+    ) -> Result<RenderedImage, SceneError> {
+        let size = self.size();
+        if camera.viewport_size() != size {
+            return Err(SceneError::ViewportSizeMismatch {
+                camera_size: [camera.width, camera.height],
+                target_size: [size.width, size.height],
+            });
+        }
+        let depth_target = if self.output_depth {
+            let depth_target = self
+                .depth_target
+                .get_or_init(|| DepthTarget::new(device, renderer, size.width, size.height));
+            renderer.render_frame_with_depth(device, queue, &self.texture, depth_target, scene, camera);
+            Some(depth_target)
+        } else {
+            renderer.render_frame(device, queue, &self.texture, scene, camera);
+            None
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let mut float_pixels = read_buffer(device, &self.readback_buffer, |bytes| {
+            unpad_rows(bytes, self.padded_bytes_per_row as usize, size.width as usize)
+        })?;
+        if self.alpha_mode == AlphaMode::Straight {
+            unpremultiply(&mut float_pixels);
+        }
+        let pixels = quantize(&float_pixels, self.encode_srgb);
+
+        let alpha = if self.output_alpha {
+            let alpha = float_pixels.iter().map(|pixel| pixel[3]).collect();
+            Some(FloatImage::from_raw(size.width, size.height, alpha).unwrap())
+        } else {
+            None
+        };
+        let depth = depth_target.map(|depth_target| depth_target.read(device)).transpose()?;
+        Ok(RenderedImage {
+            color: image::RgbaImage::from_raw(size.width, size.height, pixels).unwrap(),
+            alpha,
+            depth,
+        })
+    }
+}
+
+/// Maps a buffer whose copies have been submitted, reads it and unmaps it again
+pub(crate) fn read_buffer<T>(device: &wgpu::Device, buffer: &wgpu::Buffer, read: impl FnOnce(&[u8]) -> T) -> Result<T, SceneError> {
+    let buffer_slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv().unwrap().map_err(|error| SceneError::Io(std::io::Error::other(error)))?;
+    let result = read(&buffer_slice.get_mapped_range());
+    buffer.unmap();
+    Ok(result)
+}
+
+/// Bytes per pixel of the [FLOAT_FRAME_FORMAT]
+const PIXEL_SIZE: u32 = 8;
+
+/// Removes the row padding of a readback buffer and converts its pixels to float RGBA
+///
+/// The blend state of the renderer leaves the transmittance in the alpha channel, it becomes the opacity.
+fn unpad_rows(padded: &[u8], padded_bytes_per_row: usize, width: usize) -> Vec<[f32; 4]> {
+    let mut pixels = Vec::with_capacity(padded.len() / padded_bytes_per_row * width);
+    for row in padded.chunks_exact(padded_bytes_per_row) {
+        for pixel in row[..width * PIXEL_SIZE as usize].chunks_exact(PIXEL_SIZE as usize) {
+            let [red, green, blue, transmittance] =
+                [0, 1, 2, 3].map(|channel| f16_bits_to_f32(u16::from_le_bytes([pixel[2 * channel], pixel[2 * channel + 1]])));
+            pixels.push([red, green, blue, 1.0 - transmittance]);
+        }
+    }
+    pixels
+}
+
+/// Encodes linear float colors like an 8 bit frame buffer, sRGB encoded or not, leaving the alpha channel linear
+fn quantize(pixels: &[[f32; 4]], encode_srgb: bool) -> Vec<u8> {
+    let mut quantized = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        for (channel, value) in pixel.iter().enumerate() {
+            let value = if encode_srgb && channel < 3 { linear_to_srgb(*value) } else { *value };
+            quantized.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    quantized
+}
+
+/// The sRGB transfer function, which 8 bit sRGB frame buffers apply when they are written
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Divides the linear colors of RGBA pixels by their alpha, fully transparent pixels become black
+fn unpremultiply(pixels: &mut [[f32; 4]]) {
+    for pixel in pixels {
+        let alpha = pixel[3];
+        for channel in pixel[..3].iter_mut() {
+            *channel = if alpha > 0.0 { *channel / alpha } else { 0.0 };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        renderer::{Background, DepthSorting},
+        scene::GpuSplat,
+        utils::{f32_to_f16_bits, test_renderer},
+    };
+
+    #[test]
+    fn rows_are_unpadded() {
+        // Two rows of two pixels, padded to 20 bytes
+        let row = |first: [f32; 4], second: [f32; 4]| {
+            let mut row: Vec<u8> = first
+                .iter()
+                .chain(second.iter())
+                .flat_map(|value| f32_to_f16_bits(*value).to_le_bytes())
+                .collect();
+            row.extend_from_slice(&[9; 4]);
+            row
+        };
+        let padded = [
+            row([0.5, 0.25, 1.0, 1.0], [2.0, 0.0, 0.0, 0.0]),
+            row([0.0, 0.0, 0.0, 0.75], [0.125, 0.0, 0.0, 0.5]),
+        ]
+        .concat();
+        assert_eq!(
+            unpad_rows(&padded, 20, 2),
+            vec![[0.5, 0.25, 1.0, 0.0], [2.0, 0.0, 0.0, 1.0], [0.0, 0.0, 0.0, 0.25], [0.125, 0.0, 0.0, 0.5]]
+        );
+    }
+
+    #[test]
+    fn colors_are_quantized_like_the_frame_buffer() {
+        let pixels = [[0.5, 2.0, -1.0, 0.25], [0.0031308, 0.214, 1.0, 1.0]];
+        assert_eq!(quantize(&pixels, false), [128, 255, 0, 64, 1, 55, 255, 255]);
+        assert_eq!(quantize(&pixels, true), [188, 255, 0, 64, 10, 127, 255, 255]);
+    }
+
+    #[test]
+    fn straight_alpha_divides_by_opacity() {
+        let mut pixels = [[0.2, 0.4, 0.0, 0.4], [0.1, 0.1, 0.1, 0.0], [0.3, 0.5, 0.6, 1.0], [0.125, 0.0, 0.0, 0.5]];
+        unpremultiply(&mut pixels);
+        assert_eq!(pixels[..3], [[0.5, 1.0, 0.0, 0.4], [0.0; 4], [0.3, 0.5, 0.6, 1.0]]);
+        // Divided before the colors are encoded to sRGB
+        assert_eq!(quantize(&pixels[3..], true), [137, 0, 0, 128]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn alpha_is_read_back_as_floats() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 64);
+        let mut scene = Scene::new(&device, &renderer, 0).unwrap();
+        // A little off the optical axis, where the projected ellipse degenerates
+        let splat = GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center: [0.01, 0.02, 0.0],
+            scale: [0.5, 0.4, 0.3],
+            alpha: 0.6,
+            ..GpuSplat::default()
+        };
+        scene.append_splats(&device, &queue, &renderer, &[splat]).unwrap();
+        let mut target = OffscreenTarget::new(&device, &renderer, 64, 64);
+        target.output_alpha = true;
+        let rendered_image = target.render_to_image(&device, &queue, &renderer, &scene, &camera).unwrap();
+        let alpha = rendered_image.alpha.unwrap();
+        // The splat is a little up and left of the image center, still off the center of the pixel
+        assert!((alpha.get_pixel(31, 31)[0] - 0.6).abs() < 0.01);
+        assert_eq!(alpha.get_pixel(0, 0)[0], 0.0);
+        assert_eq!(
+            rendered_image.color.get_pixel(31, 31)[3],
+            (alpha.get_pixel(31, 31)[0] * 255.0).round() as u8
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn background_is_replaced_between_frames() {
+        let (device, queue, mut renderer) = test_renderer(DepthSorting::Cpu);
+        let camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 64);
+        let scene = Scene::new(&device, &renderer, 0).unwrap();
+        let target = OffscreenTarget::new(&device, &renderer, 64, 64);
+        let background = wgpu::Color {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+            a: 0.5,
+        };
+        renderer.set_background(&device, &queue, Background::Color(background));
+        let rendered_image = target.render_to_image(&device, &queue, &renderer, &scene, &camera).unwrap();
+        assert_eq!(rendered_image.color.get_pixel(0, 0).0, [128, 0, 0, 128]);
+        renderer.set_background(&device, &queue, Background::Transparent);
+        let rendered_image = target.render_to_image(&device, &queue, &renderer, &scene, &camera).unwrap();
+        assert_eq!(rendered_image.color.get_pixel(0, 0).0, [0; 4]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn camera_size_must_match_the_target() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 48);
+        let scene = Scene::new(&device, &renderer, 0).unwrap();
+        let target = OffscreenTarget::new(&device, &renderer, 64, 64);
+        assert!(matches!(
+            target.render_to_image(&device, &queue, &renderer, &scene, &camera),
+            Err(SceneError::ViewportSizeMismatch {
+                camera_size: [64, 48],
+                target_size: [64, 64]
+            })
+        ));
+    }
+}
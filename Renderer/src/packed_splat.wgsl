@@ -0,0 +1,28 @@
+// Half precision splat layout, replaces the splat layout section of shaders.wgsl
+struct Splat {
+    center: vec3<f32>,
+    alpha: f32,
+    rotation: vec2<u32>,
+    scale: vec2<u32>,
+    colorSH: array<u32, 24>,
+}
+fn splatRotation(splat_index: u32) -> vec4<f32> {
+    let rotation = splats[splat_index].rotation;
+    return vec4<f32>(unpack2x16float(rotation.x), unpack2x16float(rotation.y));
+}
+// The scales are stored as their natural logarithms
+fn splatScale(splat_index: u32) -> vec3<f32> {
+    let scale = splats[splat_index].scale;
+    return exp(vec3<f32>(unpack2x16float(scale.x), unpack2x16float(scale.y).x));
+}
+fn splatColorSHComponent(splat_index: u32, index: u32) -> f32 {
+    let pair = unpack2x16float(splats[splat_index].colorSH[index >> 1u]);
+    return select(pair.x, pair.y, (index & 1u) == 1u);
+}
+fn splatColorSH(splat_index: u32, coefficient: u32) -> vec3<f32> {
+    let index = coefficient * 3u;
+    return vec3<f32>(splatColorSHComponent(splat_index, index), splatColorSHComponent(splat_index, index + 1u), splatColorSHComponent(splat_index, index + 2u));
+}
+fn splatCodebookIndex(splat_index: u32) -> u32 {
+    return splats[splat_index].scale.y >> 16u;
+}
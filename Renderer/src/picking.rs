@@ -0,0 +1,417 @@
+//! Finding the splats which produce a pixel, by evaluating the shaders on the CPU
+//!
+//! The vertex and fragment shaders are mirrored for the splats whose enlarged bounding boxes the pixel ray hits,
+//! and their contributions are blended front to back in the depth order of [DepthSorting::Cpu](crate::renderer::DepthSorting::Cpu).
+use crate::{
+    camera::Camera,
+    renderer::Renderer,
+    scene::{GpuSplat, Scene, SceneError},
+};
+use geometric_algebra::ppga3d::Point;
+
+/// Column major 3x3 matrix, like `mat3x3<f32>` in WGSL
+type Matrix3 = [[f32; 3]; 3];
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    [0, 1, 2].map(|column| [0, 1, 2].map(|row| (0..3).map(|k| a[k][row] * b[column][k]).sum()))
+}
+
+fn transpose(a: &Matrix3) -> Matrix3 {
+    [0, 1, 2].map(|column| [0, 1, 2].map(|row| a[row][column]))
+}
+
+/// `matrix * vector` in WGSL
+fn transform(matrix: &Matrix3, vector: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|row| (0..3).map(|k| matrix[k][row] * vector[k]).sum())
+}
+
+/// `vector * matrix` in WGSL
+fn transform_transposed(vector: [f32; 3], matrix: &Matrix3) -> [f32; 3] {
+    [0, 1, 2].map(|column| (0..3).map(|k| vector[k] * matrix[column][k]).sum())
+}
+
+/// `sign()` in WGSL, which is zero for zero
+fn sign(value: f32) -> f32 {
+    if value == 0.0 {
+        0.0
+    } else {
+        value.signum()
+    }
+}
+
+/// Mirrors `quatToMat` in the shaders
+fn quaternion_to_matrix(rotation: [f32; 4]) -> Matrix3 {
+    let [x, y, z, w] = rotation.map(|component| component * std::f32::consts::SQRT_2);
+    [
+        [1.0 - z * z - w * w, y * z + w * x, y * w - z * x],
+        [y * z - w * x, 1.0 - y * y - w * w, z * w + y * x],
+        [y * w + z * x, z * w - y * x, 1.0 - y * y - z * z],
+    ]
+}
+
+/// The uniforms of a frame which the splat shapes depend on
+struct View {
+    camera_matrix: Matrix3,
+    camera_position: [f32; 3],
+    view_matrix: [Point; 4],
+    view_projection_matrix: [Point; 4],
+    view_size: [f32; 2],
+    image_size: [f32; 2],
+    view_offset: [f32; 2],
+    frustum_culling_tolerance: f32,
+    ellipse_size_bias: f32,
+    ellipse_margin: f32,
+    splat_scale: f32,
+    use_covariance_for_scale: bool,
+    use_unaligned_rectangles: bool,
+}
+
+impl View {
+    /// Collects the uniforms [Renderer::render_frame] would use for the camera
+    fn new(renderer: &Renderer, camera: &Camera) -> Self {
+        let camera_matrix = camera.camera_matrix();
+        let view_size = camera.view_size();
+        Self {
+            camera_matrix: [0, 1, 2].map(|column| [0, 1, 2].map(|row| camera_matrix[column][row])),
+            camera_position: [0, 1, 2].map(|row| camera_matrix[3][row]),
+            view_matrix: camera.view_matrix(),
+            view_projection_matrix: camera.view_projection_matrix(),
+            view_size,
+            image_size: [camera.width as f32, camera.height as f32],
+            view_offset: camera.view_offset(),
+            frustum_culling_tolerance: renderer.config.frustum_culling_tolerance,
+            ellipse_size_bias: 0.2 * view_size[0] / camera.width as f32,
+            ellipse_margin: renderer.config.ellipse_margin,
+            splat_scale: renderer.config.splat_scale,
+            use_covariance_for_scale: renderer.config.use_covariance_for_scale,
+            use_unaligned_rectangles: renderer.config.use_unaligned_rectangles,
+        }
+    }
+
+    /// Maps a position in pixels, with the origin in the top left corner of the image, into the view plane
+    fn pixel_to_view_plane(&self, pixel_position: [f32; 2]) -> [f32; 2] {
+        // Mirrors `screenToClipSpace`
+        let clip_space_position = [
+            2.0 * (pixel_position[0] / self.image_size[0] - 0.5),
+            -2.0 * (pixel_position[1] / self.image_size[1] - 0.5),
+        ];
+        // Inverts `viewPlaneToClipSpace`
+        [
+            clip_space_position[0] * self.view_size[0] + self.view_offset[0],
+            -clip_space_position[1] * self.view_size[1] + self.view_offset[1],
+        ]
+    }
+
+    /// Mirrors `worldToClipSpace` in the shaders
+    fn world_to_clip_space(&self, position: [f32; 3]) -> [f32; 3] {
+        let m = &self.view_projection_matrix;
+        let homogenous_position = [0, 1, 2, 3].map(|row| m[0][row] * position[0] + m[1][row] * position[1] + m[2][row] * position[2] + m[3][row]);
+        [0, 1, 2].map(|row| homogenous_position[row] / homogenous_position[3])
+    }
+
+    /// Mirrors `isInFrustum` in the shaders
+    fn is_in_frustum(&self, clip_space_position: [f32; 3]) -> bool {
+        clip_space_position[0].abs() < self.frustum_culling_tolerance
+            && clip_space_position[1].abs() < self.frustum_culling_tolerance
+            && (clip_space_position[2] - 0.5).abs() < 0.5
+    }
+
+    /// Mirrors `projectedCovarianceOfEllipsoid` in the shaders
+    fn projected_covariance(&self, scale: [f32; 3], rotation: [f32; 4], translation: [f32; 3]) -> Matrix3 {
+        let mut transform = quaternion_to_matrix(rotation);
+        for (column, scale) in transform.iter_mut().zip(scale) {
+            *column = column.map(|value| value * scale);
+        }
+        let v = &self.view_matrix;
+        let mut view_position = [0, 1, 2].map(|row| v[0][row] * translation[0] + v[1][row] * translation[1] + v[2][row] * translation[2] + v[3][row]);
+        for axis in 0..2 {
+            let view_plane_position = (view_position[axis] / view_position[2] - self.view_offset[axis]).clamp(-1.0, 1.0) + self.view_offset[axis];
+            view_position[axis] = view_plane_position * view_position[2];
+        }
+        let [x, y, z] = view_position;
+        let jacobian = [[1.0 / z, 0.0, -x / (z * z)], [0.0, 1.0 / z, -y / (z * z)], [0.0; 3]];
+        let t = multiply(&multiply(&transpose(&transform), &self.camera_matrix), &jacobian);
+        multiply(&transpose(&t), &t)
+    }
+
+    /// Mirrors `projectedContourOfEllipsoid` in the shaders
+    fn projected_contour(&self, scale: [f32; 3], rotation: [f32; 4], translation: [f32; 3]) -> Matrix3 {
+        let mut transform = quaternion_to_matrix(rotation);
+        for (column, scale) in transform.iter_mut().zip(scale) {
+            *column = column.map(|value| value / scale);
+        }
+        let ray_origin = [0, 1, 2].map(|axis| self.camera_position[axis] - translation[axis]);
+        let l = transform_transposed(ray_origin, &transform);
+        let diagonal = [
+            1.0 - l[1] * l[1] - l[2] * l[2],
+            1.0 - l[0] * l[0] - l[2] * l[2],
+            1.0 - l[0] * l[0] - l[1] * l[1],
+        ];
+        let triangle = [l[1] * l[2], l[0] * l[2], l[0] * l[1]];
+        let a = [
+            [diagonal[0], triangle[2], triangle[1]],
+            [triangle[2], diagonal[1], triangle[0]],
+            [triangle[1], triangle[0], diagonal[2]],
+        ];
+        let transform = multiply(&transpose(&self.camera_matrix), &transform);
+        multiply(&multiply(&transform, &a), &transpose(&transform))
+    }
+
+    /// Mirrors `vertex` in the shaders, which emits the quad of the splat
+    fn ellipse_quad(&self, splat: &GpuSplat) -> EllipseQuad {
+        let scale = splat.scale.map(|scale| scale * self.splat_scale);
+        let m = self.projected_contour(scale, splat.rotation, splat.center);
+        // extractTranslationOfEllipse
+        let inverse_discriminant = 1.0 / (m[0][0] * m[1][1] - m[0][1] * m[0][1]);
+        let translation = [
+            (m[0][1] * m[1][2] - m[1][1] * m[0][2]) * inverse_discriminant,
+            (m[0][1] * m[0][2] - m[0][0] * m[1][2]) * inverse_discriminant,
+        ];
+        // extractRotationOfEllipse
+        let a = (m[0][0] - m[1][1]) * (m[0][0] - m[1][1]);
+        let b = a + 4.0 * m[0][1] * m[0][1];
+        let c = 0.5 * (a / b).sqrt();
+        let mut j = (0.5 - c).sqrt();
+        let mut k = -(0.5 + c).sqrt() * sign(m[0][1]) * sign(m[0][0] - m[1][1]);
+        if m[0][1] < 0.0 || m[0][0] - m[1][1] < 0.0 {
+            k = -k;
+            j = -j;
+        }
+        if m[0][0] - m[1][1] < 0.0 {
+            let t = j;
+            j = -k;
+            k = t;
+        }
+        let rotation = [j, k];
+        let semi_axes = if self.use_covariance_for_scale {
+            // extractScaleOfCovariance
+            let covariance = self.projected_covariance(scale, splat.rotation, splat.center);
+            let a = (covariance[0][0] - covariance[1][1]) * (covariance[0][0] - covariance[1][1]);
+            let b = (a + 4.0 * covariance[0][1] * covariance[0][1]).sqrt();
+            [
+                ((covariance[0][0] + covariance[1][1] + b) * 0.5).sqrt(),
+                ((covariance[0][0] + covariance[1][1] - b) * 0.5).sqrt(),
+            ]
+        } else {
+            // extractScaleOfEllipse
+            let d = 2.0 * m[0][1] * rotation[0] * rotation[1];
+            let e = m[2][2]
+                - (m[0][0] * translation[0] * translation[0]
+                    + m[1][1] * translation[1] * translation[1]
+                    + 2.0 * m[0][1] * translation[0] * translation[1]);
+            [
+                (e / (m[0][0] * rotation[1] * rotation[1] + m[1][1] * rotation[0] * rotation[0] - d))
+                    .abs()
+                    .sqrt(),
+                (e / (m[0][0] * rotation[0] * rotation[0] + m[1][1] * rotation[1] * rotation[1] + d))
+                    .abs()
+                    .sqrt(),
+            ]
+        };
+        EllipseQuad {
+            translation,
+            axis_x: [rotation[1], -rotation[0]].map(|value| value * (self.ellipse_size_bias + semi_axes[0])),
+            axis_y: [rotation[0], rotation[1]].map(|value| value * (self.ellipse_size_bias + semi_axes[1])),
+            ellipse_margin: self.ellipse_margin,
+            use_unaligned_rectangles: self.use_unaligned_rectangles,
+        }
+    }
+}
+
+/// The quad of a splat in the view plane, spanned by the semi axes of its ellipse
+struct EllipseQuad {
+    translation: [f32; 2],
+    axis_x: [f32; 2],
+    axis_y: [f32; 2],
+    ellipse_margin: f32,
+    use_unaligned_rectangles: bool,
+}
+
+impl EllipseQuad {
+    /// Radius of the square the quad is enlarged to, unless it is an unaligned rectangle
+    fn radius(&self) -> f32 {
+        let [axis_x, axis_y] = [self.axis_x, self.axis_y];
+        (axis_x[0] * axis_x[0] + axis_x[1] * axis_x[1]).max(axis_y[0] * axis_y[0] + axis_y[1] * axis_y[1]).sqrt()
+    }
+
+    /// Maps a point in the view plane into the quad, in standard deviations of the ellipse
+    ///
+    /// Returns the interpolated `gl_TexCoord` of the shaders if the point is covered by the quad.
+    fn coordinates(&self, view_plane_position: [f32; 2]) -> Option<[f32; 2]> {
+        let [axis_x, axis_y] = [self.axis_x, self.axis_y];
+        let offset = [view_plane_position[0] - self.translation[0], view_plane_position[1] - self.translation[1]];
+        let determinant = axis_x[0] * axis_y[1] - axis_x[1] * axis_y[0];
+        let coordinates = [
+            (axis_y[1] * offset[0] - axis_y[0] * offset[1]) / determinant,
+            (axis_x[0] * offset[1] - axis_x[1] * offset[0]) / determinant,
+        ];
+        let is_covered = if self.use_unaligned_rectangles {
+            coordinates.iter().all(|coordinate| coordinate.abs() <= self.ellipse_margin)
+        } else {
+            let radius = self.radius();
+            offset.iter().all(|offset| offset.abs() <= radius * self.ellipse_margin)
+        };
+        if is_covered {
+            Some(coordinates)
+        } else {
+            None
+        }
+    }
+}
+
+/// Mirrors `fragment` in the shaders, the opacity of a splat at the coordinates of [EllipseQuad::coordinates]
+///
+/// Returns `None` for fragments which are discarded.
+fn fragment_alpha(splat: &GpuSplat, coordinates: [f32; 2]) -> Option<f32> {
+    let power = coordinates[0] * coordinates[0] + coordinates[1] * coordinates[1];
+    let alpha = splat.alpha * (-0.5 * power).exp();
+    if alpha.is_nan() || alpha < 1.0 / 255.0 {
+        None
+    } else {
+        Some(alpha)
+    }
+}
+
+/// What [Renderer::pick] found under a pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PickResult {
+    /// The splat with the largest contribution, its opacity times the transmittance in front of it
+    pub splat_index: usize,
+    /// Center of that splat in world space
+    pub splat_position: [f32; 3],
+    /// Average distance of the splat centers from the camera along the pixel ray, weighted by their contributions
+    pub expected_depth: f32,
+    /// The point on the pixel ray at the expected depth
+    pub position: [f32; 3],
+    /// Accumulated opacity of the pixel
+    pub alpha: f32,
+}
+
+impl Renderer {
+    /// Finds the splats blended into the pixel at `x`, `y` of a frame rendered with [Renderer::render_frame]
+    ///
+    /// Returns `None` if no splat covers the pixel. Only reads the splats near the pixel ray back from the GPU.
+    pub fn pick(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        camera: &Camera,
+        x: u32,
+        y: u32,
+    ) -> Result<Option<PickResult>, SceneError> {
+        let view = View::new(self, camera);
+        let view_plane_position = view.pixel_to_view_plane([x as f32 + 0.5, y as f32 + 0.5]);
+        let ray_direction = transform(&view.camera_matrix, [view_plane_position[0], view_plane_position[1], 1.0]);
+        let ray_length = ray_direction.iter().map(|component| component * component).sum::<f32>().sqrt();
+        let ray_direction = ray_direction.map(|component| component / ray_length);
+
+        // The quads of the splats reach `ellipse_margin` times their standard deviations plus the size bias beyond their centers,
+        // the bias is in the view plane so it grows with the distance
+        let mut splat_indices: Vec<usize> = scene
+            .spatial_index()
+            .query_ray_within(
+                view.camera_position,
+                ray_direction,
+                self.config.ellipse_margin * self.config.splat_scale,
+                self.config.ellipse_margin * view.ellipse_size_bias,
+            )
+            .into_iter()
+            .map(|(splat_index, _)| splat_index)
+            .collect();
+        splat_indices.sort_unstable();
+        let splat_data = scene.download_splat_indices(device, queue, &splat_indices)?;
+        let mut entries: Vec<(f32, usize, &GpuSplat)> = splat_indices
+            .into_iter()
+            .zip(splat_data.iter())
+            .filter_map(|(splat_index, splat)| {
+                let clip_space_position = view.world_to_clip_space(splat.center);
+                if view.is_in_frustum(clip_space_position) {
+                    Some((clip_space_position[2], splat_index, splat))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Blends front to back like the blend state of the render pipeline
+        let mut transmittance = 1.0;
+        let mut dominant: Option<(f32, usize, &GpuSplat)> = None;
+        let mut weighted_depth = 0.0;
+        for (_, splat_index, splat) in entries {
+            let alpha = match view.ellipse_quad(splat).coordinates(view_plane_position).and_then(|coordinates| fragment_alpha(splat, coordinates)) {
+                Some(alpha) => alpha,
+                None => continue,
+            };
+            let weight = alpha * transmittance;
+            let depth: f32 = (0..3)
+                .map(|axis| (splat.center[axis] - view.camera_position[axis]) * ray_direction[axis])
+                .sum();
+            weighted_depth += weight * depth;
+            if dominant.is_none_or(|(dominant_weight, _, _)| weight > dominant_weight) {
+                dominant = Some((weight, splat_index, splat));
+            }
+            transmittance *= 1.0 - alpha;
+        }
+        Ok(dominant.map(|(_, splat_index, splat)| {
+            let alpha = 1.0 - transmittance;
+            let expected_depth = weighted_depth / alpha;
+            PickResult {
+                splat_index,
+                splat_position: splat.center,
+                expected_depth,
+                position: [0, 1, 2].map(|axis| view.camera_position[axis] + ray_direction[axis] * expected_depth),
+                alpha,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{procedural::ProceduralScene, renderer::DepthSorting, utils::test_renderer};
+
+    /// The pixel a point in world space is rendered at
+    fn world_to_pixel(view: &View, position: [f32; 3]) -> [u32; 2] {
+        let clip_space_position = view.world_to_clip_space(position);
+        [
+            ((0.5 + 0.5 * clip_space_position[0]) * view.image_size[0]) as u32,
+            ((0.5 - 0.5 * clip_space_position[1]) * view.image_size[1]) as u32,
+        ]
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn pick_hits_and_misses() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let camera = Camera::from_look_at([0.0, 0.0, -5.0], [0.0; 3], [0.0, 1.0, 0.0], std::f64::consts::FRAC_PI_3, 64, 64);
+        let view = View::new(&renderer, &camera);
+        let mut scene = Scene::new_procedural(&device, &queue, &renderer, ProceduralScene::Grid, 8, 1).unwrap();
+        let splat_data = ProceduralScene::Grid.generate(8, 1);
+
+        // The splat in front of the grid is the dominant one at the pixel of its center
+        let [x, y] = world_to_pixel(&view, splat_data[0].center);
+        let pick_result = renderer.pick(&device, &queue, &scene, &camera, x, y).unwrap().unwrap();
+        assert_eq!(pick_result.splat_index, 0);
+        assert!(pick_result.alpha > 0.0 && pick_result.alpha <= 1.0);
+        assert!(pick_result.expected_depth > 4.0 && pick_result.expected_depth < 6.0);
+        assert_eq!(renderer.pick(&device, &queue, &scene, &camera, 0, 0).unwrap(), None);
+
+        // A splat much smaller than a pixel is still drawn with the size bias of the shaders
+        let view_plane_position = view.pixel_to_view_plane([16.6, 16.5]);
+        let direction = transform(&view.camera_matrix, [view_plane_position[0], view_plane_position[1], 1.0]);
+        let tiny_splat = GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center: [0, 1, 2].map(|axis| view.camera_position[axis] + 5.0 * direction[axis]),
+            scale: [5.0e-4, 1.0e-3, 5.0e-4],
+            alpha: 1.0,
+            ..GpuSplat::default()
+        };
+        scene.append_splats(&device, &queue, &renderer, &[tiny_splat]).unwrap();
+        let pick_result = renderer.pick(&device, &queue, &scene, &camera, 16, 16).unwrap().unwrap();
+        assert_eq!(pick_result.splat_index, 8);
+        assert_eq!(renderer.pick(&device, &queue, &scene, &camera, 17, 16).unwrap(), None);
+    }
+}
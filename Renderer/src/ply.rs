@@ -0,0 +1,523 @@
+//! Reading of PLY (Polygon File Format) headers and records
+use std::{
+    convert::TryInto,
+    io::{BufRead, Write},
+};
+
+/// Errors which can occur while reading a PLY file
+#[derive(Debug)]
+pub enum PlyError {
+    /// Reading the file failed
+    Io(std::io::Error),
+    /// The file ends before all records declared in its header
+    Truncated,
+    /// The header is not a valid PLY header
+    MalformedHeader(String),
+    /// A value of an ASCII record is not a number
+    Parse {
+        /// Number of the line of the record in the file, counted from 1
+        line: usize,
+        /// Name of the property the value belongs to
+        property: String,
+    },
+    /// An ASCII record has more or fewer values than its element has properties
+    RecordLength { line: usize, expected: usize, found: usize },
+}
+
+impl std::fmt::Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Truncated => write!(f, "the PLY file is truncated"),
+            Self::MalformedHeader(message) => write!(f, "malformed header: {}", message),
+            Self::Parse { line, property } => write!(f, "line {}: the value of property {} is not a number", line, property),
+            Self::RecordLength { line, expected, found } => write!(f, "line {}: expected {} values, found {}", line, expected, found),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PlyError {
+    fn from(error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            Self::Truncated
+        } else {
+            Self::Io(error)
+        }
+    }
+}
+
+/// Encoding of the body of a PLY file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    /// Whitespace separated text, one record per line
+    Ascii,
+    /// Packed binary records in little endian byte order
+    BinaryLittleEndian,
+    /// Packed binary records in big endian byte order
+    BinaryBigEndian,
+}
+
+/// Scalar type of a PLY property
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyScalarType {
+    /// Parses both the classic (`float`) and the sized (`float32`) type names
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::UInt8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::UInt16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::UInt32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => return None,
+        })
+    }
+
+    /// The classic name of the type, as written in headers
+    fn name(self) -> &'static str {
+        match self {
+            Self::Int8 => "char",
+            Self::UInt8 => "uchar",
+            Self::Int16 => "short",
+            Self::UInt16 => "ushort",
+            Self::Int32 => "int",
+            Self::UInt32 => "uint",
+            Self::Float32 => "float",
+            Self::Float64 => "double",
+        }
+    }
+
+    /// Size of a value in a binary record in bytes
+    pub fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+
+    /// Decodes a binary value and converts it to f64, which represents every PLY scalar exactly
+    fn decode(self, bytes: &[u8], format: PlyFormat) -> f64 {
+        macro_rules! decode {
+            ($type:ty) => {{
+                let bytes = bytes[0..std::mem::size_of::<$type>()].try_into().unwrap();
+                (if format == PlyFormat::BinaryBigEndian {
+                    <$type>::from_be_bytes(bytes)
+                } else {
+                    <$type>::from_le_bytes(bytes)
+                }) as f64
+            }};
+        }
+        match self {
+            Self::Int8 => decode!(i8),
+            Self::UInt8 => decode!(u8),
+            Self::Int16 => decode!(i16),
+            Self::UInt16 => decode!(u16),
+            Self::Int32 => decode!(i32),
+            Self::UInt32 => decode!(u32),
+            Self::Float32 => decode!(f32),
+            Self::Float64 => decode!(f64),
+        }
+    }
+
+    /// Converts a f64 to this type and appends its binary encoding to `bytes`
+    fn encode(self, value: f64, format: PlyFormat, bytes: &mut Vec<u8>) {
+        macro_rules! encode {
+            ($type:ty) => {{
+                let value = value as $type;
+                if format == PlyFormat::BinaryBigEndian {
+                    bytes.extend_from_slice(&value.to_be_bytes());
+                } else {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }};
+        }
+        match self {
+            Self::Int8 => encode!(i8),
+            Self::UInt8 => encode!(u8),
+            Self::Int16 => encode!(i16),
+            Self::UInt16 => encode!(u16),
+            Self::Int32 => encode!(i32),
+            Self::UInt32 => encode!(u32),
+            Self::Float32 => encode!(f32),
+            Self::Float64 => encode!(f64),
+        }
+    }
+}
+
+/// A scalar property of a PLY element
+#[derive(Clone, Debug)]
+pub struct PlyProperty {
+    pub name: String,
+    pub scalar_type: PlyScalarType,
+    /// Byte offset of the property inside a binary record
+    pub offset: usize,
+}
+
+/// An element declaration of a PLY header, such as `element vertex 1234`
+#[derive(Clone, Debug)]
+pub struct PlyElement {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<PlyProperty>,
+    /// Size of a binary record in bytes
+    pub stride: usize,
+}
+
+impl PlyElement {
+    /// Declares an element whose records store the given properties in order
+    pub fn new<S: AsRef<str>>(name: &str, count: usize, properties: &[(S, PlyScalarType)]) -> Self {
+        let mut element = Self {
+            name: name.to_string(),
+            count,
+            properties: Vec::with_capacity(properties.len()),
+            stride: 0,
+        };
+        for (property_name, scalar_type) in properties {
+            element.properties.push(PlyProperty {
+                name: property_name.as_ref().to_string(),
+                scalar_type: *scalar_type,
+                offset: element.stride,
+            });
+            element.stride += scalar_type.size();
+        }
+        element
+    }
+
+    /// Returns the index of the property called `name`
+    pub fn property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|property| property.name == name)
+    }
+}
+
+/// The parsed header of a PLY file
+#[derive(Clone, Debug)]
+pub struct PlyHeader {
+    pub format: PlyFormat,
+    pub elements: Vec<PlyElement>,
+    /// Length of the header including `end_header` in bytes
+    pub header_size: u64,
+    /// Number of lines of the header including `end_header`
+    pub header_line_count: usize,
+}
+
+impl PlyHeader {
+    /// Parses the header and leaves `reader` at the beginning of the body
+    pub fn parse<R: BufRead>(reader: &mut R) -> Result<Self, PlyError> {
+        let malformed = |message: String| PlyError::MalformedHeader(message);
+        let mut format = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+        let mut header_size = 0;
+        let mut header_line_count = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let length = reader.read_line(&mut line)?;
+            if length == 0 {
+                return Err(malformed("PLY header is not terminated by end_header".into()));
+            }
+            header_size += length as u64;
+            header_line_count += 1;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("ply") | Some("comment") | Some("obj_info") | None => {}
+                Some("format") => {
+                    format = Some(match words.next() {
+                        Some("ascii") => PlyFormat::Ascii,
+                        Some("binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                        Some("binary_big_endian") => PlyFormat::BinaryBigEndian,
+                        other => return Err(malformed(format!("Unsupported PLY format {:?}", other))),
+                    });
+                }
+                Some("element") => {
+                    let name = words.next().ok_or_else(|| malformed("PLY element without name".into()))?;
+                    let count = words
+                        .next()
+                        .and_then(|count| count.parse().ok())
+                        .ok_or_else(|| malformed(format!("PLY element {} without count", name)))?;
+                    elements.push(PlyElement {
+                        name: name.to_string(),
+                        count,
+                        properties: Vec::new(),
+                        stride: 0,
+                    });
+                }
+                Some("property") => {
+                    let element = elements
+                        .last_mut()
+                        .ok_or_else(|| malformed("PLY property outside of an element".into()))?;
+                    let type_name = words.next().ok_or_else(|| malformed("PLY property without type".into()))?;
+                    if type_name == "list" {
+                        return Err(malformed(format!(
+                            "PLY list properties are not supported, found one in element {}",
+                            element.name
+                        )));
+                    }
+                    let scalar_type = PlyScalarType::parse(type_name).ok_or_else(|| malformed(format!("Unknown PLY property type {}", type_name)))?;
+                    let name = words.next().ok_or_else(|| malformed("PLY property without name".into()))?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        scalar_type,
+                        offset: element.stride,
+                    });
+                    element.stride += scalar_type.size();
+                }
+                Some("end_header") => break,
+                Some(keyword) => return Err(malformed(format!("Unknown PLY header keyword {}", keyword))),
+            }
+        }
+        let header = Self {
+            format: format.ok_or_else(|| malformed("PLY header without format".into()))?,
+            elements,
+            header_size,
+            header_line_count,
+        };
+        if header
+            .elements
+            .iter()
+            .try_fold(header_size, |size, element| {
+                (element.count as u64)
+                    .checked_mul(element.stride as u64)
+                    .and_then(|element_size| size.checked_add(element_size))
+            })
+            .is_none()
+        {
+            return Err(malformed("PLY element sizes overflow".into()));
+        }
+        Ok(header)
+    }
+
+    /// Writes the header including `end_header`
+    ///
+    /// Ignores `header_size` and `header_line_count`, which are only known once the header is written.
+    pub fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let format = match self.format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian",
+        };
+        writeln!(writer, "ply\nformat {} 1.0", format)?;
+        for element in &self.elements {
+            writeln!(writer, "element {} {}", element.name, element.count)?;
+            for property in &element.properties {
+                writeln!(writer, "property {} {}", property.scalar_type.name(), property.name)?;
+            }
+        }
+        writeln!(writer, "end_header")
+    }
+
+    /// Returns the index of the element called `name`
+    pub fn element_index(&self, name: &str) -> Option<usize> {
+        self.elements.iter().position(|element| element.name == name)
+    }
+
+    /// Byte offset of the first record of an element in a binary file
+    pub fn element_offset(&self, element_index: usize) -> u64 {
+        self.header_size
+            + self.elements[0..element_index]
+                .iter()
+                .map(|element| (element.count * element.stride) as u64)
+                .sum::<u64>()
+    }
+
+    /// Number of lines preceding the first record of an element in the body of an ASCII file
+    pub fn element_line_offset(&self, element_index: usize) -> usize {
+        self.elements[0..element_index].iter().map(|element| element.count).sum()
+    }
+
+    /// Number of the line of a record in an ASCII file counted from 1, as reported by [PlyError::Parse]
+    pub fn record_line(&self, element_index: usize, record_index: usize) -> usize {
+        self.header_line_count + self.element_line_offset(element_index) + record_index + 1
+    }
+}
+
+/// Reads the next record of `element` and stores all its properties as f64 in `values`
+///
+/// `line` is the number of the line of the record in an ASCII file, see [PlyHeader::record_line], and only used in errors.
+pub fn read_record<R: BufRead>(
+    reader: &mut R,
+    format: PlyFormat,
+    element: &PlyElement,
+    line: usize,
+    values: &mut Vec<f64>,
+    buffer: &mut Vec<u8>,
+) -> Result<(), PlyError> {
+    values.clear();
+    if format == PlyFormat::Ascii {
+        read_ascii_line(reader, buffer)?;
+        let words = String::from_utf8_lossy(buffer);
+        let words: Vec<&str> = words.split_whitespace().collect();
+        if words.len() != element.properties.len() {
+            return Err(PlyError::RecordLength {
+                line,
+                expected: element.properties.len(),
+                found: words.len(),
+            });
+        }
+        for (word, property) in words.iter().zip(element.properties.iter()) {
+            values.push(word.parse::<f64>().map_err(|_| PlyError::Parse {
+                line,
+                property: property.name.clone(),
+            })?);
+        }
+    } else {
+        buffer.resize(element.stride, 0);
+        reader.read_exact(buffer)?;
+        values.extend(
+            element
+                .properties
+                .iter()
+                .map(|property| property.scalar_type.decode(&buffer[property.offset..], format)),
+        );
+    }
+    Ok(())
+}
+
+/// Writes one record of `element`, converting `values` to the types of its properties
+pub fn write_record<W: Write>(writer: &mut W, format: PlyFormat, element: &PlyElement, values: &[f64], buffer: &mut Vec<u8>) -> std::io::Result<()> {
+    buffer.clear();
+    if format == PlyFormat::Ascii {
+        for (index, (property, value)) in element.properties.iter().zip(values).enumerate() {
+            if index > 0 {
+                buffer.push(b' ');
+            }
+            match property.scalar_type {
+                PlyScalarType::Float32 => write!(buffer, "{}", *value as f32)?,
+                PlyScalarType::Float64 => write!(buffer, "{}", value)?,
+                _ => write!(buffer, "{}", *value as i64)?,
+            }
+        }
+        buffer.push(b'\n');
+    } else {
+        for (property, value) in element.properties.iter().zip(values) {
+            property.scalar_type.encode(*value, format, buffer);
+        }
+    }
+    writer.write_all(buffer)
+}
+
+/// Skips `count` records of the body of an ASCII file
+pub fn skip_ascii_records<R: BufRead>(reader: &mut R, count: usize, buffer: &mut Vec<u8>) -> Result<(), PlyError> {
+    for _ in 0..count {
+        read_ascii_line(reader, buffer)?;
+    }
+    Ok(())
+}
+
+fn read_ascii_line<R: BufRead>(reader: &mut R, buffer: &mut Vec<u8>) -> Result<(), PlyError> {
+    buffer.clear();
+    if reader.read_until(b'\n', buffer)? == 0 {
+        return Err(PlyError::Truncated);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat binary_big_endian 1.0\ncomment test\nelement chunk 2\nproperty float min_x\nelement vertex 3\nproperty double x\nproperty uchar red\nend_header\n";
+
+    #[test]
+    fn parse_header() {
+        let mut reader = std::io::Cursor::new(HEADER.as_bytes());
+        let header = PlyHeader::parse(&mut reader).unwrap();
+        assert_eq!(header.format, PlyFormat::BinaryBigEndian);
+        assert_eq!(header.header_size, HEADER.len() as u64);
+        let vertex_index = header.element_index("vertex").unwrap();
+        let vertex = &header.elements[vertex_index];
+        assert_eq!(vertex.count, 3);
+        assert_eq!(vertex.stride, 9);
+        assert_eq!(vertex.property_index("red"), Some(1));
+        assert_eq!(vertex.properties[1].offset, 8);
+        assert_eq!(header.element_offset(vertex_index), HEADER.len() as u64 + 8);
+    }
+
+    #[test]
+    fn read_binary_and_ascii_records() {
+        let mut reader = std::io::Cursor::new(HEADER.as_bytes());
+        let header = PlyHeader::parse(&mut reader).unwrap();
+        let vertex = &header.elements[1];
+        let mut record = 2.5f64.to_be_bytes().to_vec();
+        record.push(200);
+        let (mut values, mut buffer) = (Vec::new(), Vec::new());
+        read_record(&mut std::io::Cursor::new(record), header.format, vertex, 0, &mut values, &mut buffer).unwrap();
+        assert_eq!(values, [2.5, 200.0]);
+        read_record(&mut std::io::Cursor::new("-1.5 7\n"), PlyFormat::Ascii, vertex, 0, &mut values, &mut buffer).unwrap();
+        assert_eq!(values, [-1.5, 7.0]);
+        assert!(matches!(
+            read_record(&mut std::io::Cursor::new(vec![0; 4]), header.format, vertex, 0, &mut values, &mut buffer),
+            Err(PlyError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn ascii_errors_report_line_and_property() {
+        let mut reader = std::io::Cursor::new(HEADER.as_bytes());
+        let header = PlyHeader::parse(&mut reader).unwrap();
+        assert_eq!(header.header_line_count, 9);
+        let line = header.record_line(1, 1);
+        assert_eq!(line, 13);
+        let (mut values, mut buffer) = (Vec::new(), Vec::new());
+        match read_record(&mut std::io::Cursor::new("1.5 red\n"), PlyFormat::Ascii, &header.elements[1], line, &mut values, &mut buffer) {
+            Err(PlyError::Parse { line: 13, property }) => assert_eq!(property, "red"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            read_record(&mut std::io::Cursor::new("1.5\n"), PlyFormat::Ascii, &header.elements[1], line, &mut values, &mut buffer),
+            Err(PlyError::RecordLength { line: 13, expected: 2, found: 1 })
+        ));
+    }
+
+    #[test]
+    fn write_and_parse_header() {
+        let element = PlyElement::new("vertex", 2, &[("x", PlyScalarType::Float32), ("red", PlyScalarType::UInt8)]);
+        let header = PlyHeader {
+            format: PlyFormat::BinaryLittleEndian,
+            elements: vec![element],
+            header_size: 0,
+            header_line_count: 0,
+        };
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        let written_header_size = bytes.len() as u64;
+        let mut buffer = Vec::new();
+        write_record(&mut bytes, header.format, &header.elements[0], &[0.25, 17.0], &mut buffer).unwrap();
+        let mut reader = std::io::Cursor::new(bytes);
+        let parsed = PlyHeader::parse(&mut reader).unwrap();
+        assert_eq!(parsed.header_size, written_header_size);
+        assert_eq!(parsed.elements[0].stride, 5);
+        let mut values = Vec::new();
+        read_record(&mut reader, parsed.format, &parsed.elements[0], 0, &mut values, &mut buffer).unwrap();
+        assert_eq!(values, [0.25, 17.0]);
+    }
+
+    #[test]
+    fn reject_unterminated_header() {
+        let mut reader = std::io::Cursor::new("ply\nformat ascii 1.0\nelement vertex 3\n");
+        assert!(matches!(PlyHeader::parse(&mut reader), Err(PlyError::MalformedHeader(_))));
+    }
+}
@@ -0,0 +1,233 @@
+//! Seedable procedural scenes, for tests and benchmarks without splat files
+use crate::{
+    renderer::Renderer,
+    scene::{GpuSplat, Scene, SceneError},
+};
+
+/// SplitMix64, so that the generated scenes are the same on every platform
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in [min, max)
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.unit()
+    }
+
+    /// Standard normal distribution, by the Box-Muller transform
+    fn normal(&mut self) -> f32 {
+        let radius = (-2.0 * (1.0 - self.unit()).ln()).sqrt();
+        radius * (std::f32::consts::TAU * self.unit()).cos()
+    }
+
+    /// Uniform on the unit sphere
+    fn direction(&mut self) -> [f32; 3] {
+        let z = self.range(-1.0, 1.0);
+        let angle = self.range(0.0, std::f32::consts::TAU);
+        let radius = (1.0 - z * z).sqrt();
+        [radius * angle.cos(), radius * angle.sin(), z]
+    }
+
+    /// Uniformly distributed unit quaternion (w, x, y, z)
+    fn rotation(&mut self) -> [f32; 4] {
+        let rotation = [self.normal(), self.normal(), self.normal(), self.normal()];
+        let norm = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+        rotation.map(|component| component / norm)
+    }
+}
+
+/// Unit quaternion (w, x, y, z) rotating the z axis onto `direction`
+fn rotation_from_z_axis(direction: [f32; 3]) -> [f32; 4] {
+    // The shortest arc is not unique for the negative z axis, any half turn around a horizontal axis works
+    if direction[2] < -0.999_999 {
+        return [0.0, 1.0, 0.0, 0.0];
+    }
+    let rotation = [1.0 + direction[2], -direction[1], direction[0], 0.0];
+    let norm = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+    rotation.map(|component| component / norm)
+}
+
+/// The kinds of scenes [ProceduralScene::generate] produces
+///
+/// All scenes fit into the cube [-1, 1]³ and vary their colors, opacities and shapes by the seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProceduralScene {
+    /// Isotropic splats on a cubic grid, filled in x, y, z order
+    Grid,
+    /// Splats with random rotations and anisotropic scales, uniformly distributed in the cube
+    RandomBox,
+    /// Flat splats on the unit sphere, with random spherical harmonics of degree 3 so their color depends on the view direction
+    SphereShell,
+    /// Translucent layers parallel to the xy plane, each in its own color
+    ///
+    /// The splats of the layers are interleaved in index order, so that rendering them without depth sorting is visibly wrong.
+    TranslucentLayers { layer_count: usize },
+}
+
+impl ProceduralScene {
+    /// Degree of the spherical harmonics of the generated splats
+    pub fn spherical_harmonics_degree(&self) -> usize {
+        match self {
+            Self::SphereShell => 3,
+            _ => 0,
+        }
+    }
+
+    /// Generates `splat_count` splats, the same ones for the same seed
+    pub fn generate(&self, splat_count: usize, seed: u64) -> Vec<GpuSplat> {
+        let mut random = Random::new(seed);
+        let mut splat_data = Vec::with_capacity(splat_count);
+        match *self {
+            Self::Grid => {
+                let side = (1..).find(|side| side * side * side >= splat_count).unwrap();
+                let spacing = 2.0 / side as f32;
+                for splat_index in 0..splat_count {
+                    let cell = [splat_index % side, splat_index / side % side, splat_index / (side * side)];
+                    let mut splat = GpuSplat {
+                        rotation: [1.0, 0.0, 0.0, 0.0],
+                        center: cell.map(|cell| -1.0 + (cell as f32 + 0.5) * spacing),
+                        scale: [0.25 * spacing; 3],
+                        alpha: random.range(0.5, 1.0),
+                        ..GpuSplat::default()
+                    };
+                    splat.set_base_color(cell.map(|cell| (cell as f32 + 0.5) / side as f32));
+                    splat_data.push(splat);
+                }
+            }
+            Self::RandomBox => {
+                for _ in 0..splat_count {
+                    let mut splat = GpuSplat {
+                        rotation: random.rotation(),
+                        center: [random.range(-1.0, 1.0), random.range(-1.0, 1.0), random.range(-1.0, 1.0)],
+                        // Log-uniform between 0.005 and 0.05
+                        scale: [0; 3].map(|_| 0.005 * 10.0f32.powf(random.unit())),
+                        alpha: random.range(0.1, 1.0),
+                        ..GpuSplat::default()
+                    };
+                    splat.set_base_color([random.unit(), random.unit(), random.unit()]);
+                    splat_data.push(splat);
+                }
+            }
+            Self::SphereShell => {
+                // Covers the sphere about twice
+                let radius = (8.0 / splat_count.max(1) as f32).sqrt();
+                for _ in 0..splat_count {
+                    let normal = random.direction();
+                    let mut splat = GpuSplat {
+                        rotation: rotation_from_z_axis(normal),
+                        center: normal,
+                        scale: [0.5 * radius, 0.5 * radius, 0.05 * radius],
+                        alpha: random.range(0.5, 1.0),
+                        ..GpuSplat::default()
+                    };
+                    splat.set_base_color([random.unit(), random.unit(), random.unit()]);
+                    for coefficient in splat.color[3..].iter_mut() {
+                        *coefficient = 0.3 * random.normal();
+                    }
+                    splat_data.push(splat);
+                }
+            }
+            Self::TranslucentLayers { layer_count } => {
+                let layer_count = layer_count.max(1);
+                let layer_colors: Vec<[f32; 3]> = (0..layer_count).map(|_| [random.unit(), random.unit(), random.unit()]).collect();
+                let layer_splat_count = splat_count.div_ceil(layer_count);
+                let radius = 2.0 / (layer_splat_count as f32).sqrt();
+                for splat_index in 0..splat_count {
+                    let layer = splat_index % layer_count;
+                    let mut splat = GpuSplat {
+                        rotation: [1.0, 0.0, 0.0, 0.0],
+                        center: [
+                            random.range(-1.0, 1.0),
+                            random.range(-1.0, 1.0),
+                            -1.0 + 2.0 * (layer as f32 + 0.5) / layer_count as f32,
+                        ],
+                        scale: [radius, radius, 0.01],
+                        alpha: random.range(0.2, 0.4),
+                        ..GpuSplat::default()
+                    };
+                    splat.set_base_color(layer_colors[layer]);
+                    splat_data.push(splat);
+                }
+            }
+        }
+        splat_data
+    }
+}
+
+impl Scene {
+    /// Constructs a [Scene] holding the splats of [ProceduralScene::generate]
+    pub fn new_procedural(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        procedural_scene: ProceduralScene,
+        splat_count: usize,
+        seed: u64,
+    ) -> Result<Self, SceneError> {
+        let mut scene = Self::new(device, renderer, splat_count)?;
+        scene.spherical_harmonics_degree = procedural_scene.spherical_harmonics_degree();
+        scene.upload_splats(queue, 0, &procedural_scene.generate(splat_count, seed));
+        Ok(scene)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROCEDURAL_SCENES: [ProceduralScene; 4] = [
+        ProceduralScene::Grid,
+        ProceduralScene::RandomBox,
+        ProceduralScene::SphereShell,
+        ProceduralScene::TranslucentLayers { layer_count: 3 },
+    ];
+
+    #[test]
+    fn generators_are_seedable() {
+        for procedural_scene in PROCEDURAL_SCENES {
+            let splat_data = procedural_scene.generate(100, 7);
+            assert_eq!(splat_data.len(), 100);
+            assert_eq!(splat_data, procedural_scene.generate(100, 7));
+            assert_ne!(splat_data, procedural_scene.generate(100, 8));
+            for splat in &splat_data {
+                let norm = splat.rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+                assert!((norm - 1.0).abs() < 1.0e-5);
+                assert!(splat.center.iter().all(|component| component.abs() <= 1.0));
+                assert!(splat.scale.iter().all(|scale| *scale > 0.0));
+                assert!(splat.alpha > 0.0 && splat.alpha <= 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_shell_splats_face_outwards() {
+        for splat in ProceduralScene::SphereShell.generate(50, 1) {
+            let length = splat.center.iter().map(|component| component * component).sum::<f32>().sqrt();
+            assert!((length - 1.0).abs() < 1.0e-5);
+            // The thin z axis of the splat points along its center
+            let normal = crate::transform::rotate(&crate::transform::rotation_matrix(splat.rotation), [0.0, 0.0, 1.0]);
+            for (normal, center) in normal.iter().zip(splat.center) {
+                assert!((*normal as f32 - center).abs() < 1.0e-4);
+            }
+            assert!(splat.color[3..].iter().any(|coefficient| *coefficient != 0.0));
+        }
+    }
+}
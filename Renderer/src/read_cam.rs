@@ -0,0 +1,91 @@
+
+//! Camera matrices computed like the training code of the splats, which [Camera::from_colmap](crate::camera::Camera::from_colmap) is tested against
+use std::collections::HashMap;
+use geometric_algebra::ppga3d::Point;
+use geometric_algebra::Zero;
+use nalgebra::{Matrix3, Matrix4, Vector3};
+use crate::colmap::{Camera, Image};
+
+fn qvec2rotmat(qvec: &[f64; 4]) -> Matrix3<f64> {
+    Matrix3::new(   
+            1.0 - 2.0 * qvec[2].powi(2) - 2.0 * qvec[3].powi(2), 2.0 * qvec[1] * qvec[2] - 2.0 * qvec[0] * qvec[3], 2.0 * qvec[3] * qvec[1] + 2.0 * qvec[0] * qvec[2],
+            2.0 * qvec[1] * qvec[2] + 2.0 * qvec[0] * qvec[3], 1.0 - 2.0 * qvec[1].powi(2) - 2.0 * qvec[3].powi(2), 2.0 * qvec[2] * qvec[3] - 2.0 * qvec[0] * qvec[1],
+            2.0 * qvec[3] * qvec[1] - 2.0 * qvec[0] * qvec[2], 2.0 * qvec[2] * qvec[3] + 2.0 * qvec[0] * qvec[1], 1.0 - 2.0 * qvec[1].powi(2) - 2.0 * qvec[2].powi(2)
+    )
+}
+
+fn focal2fov(focal: f64, pixels: f64) -> f64 {
+    2.0 * (pixels / (2.0 * focal)).atan()
+}
+
+pub(crate) fn compute_matrix(cameras_intrinsics: &HashMap<u64, Camera>,cameras_extrinsic : &HashMap<u64, Image> ,id_image: &u64 ) -> ([Point; 4], [Point; 4], [Point; 4], f64, f64) {
+    let image = cameras_extrinsic.get(id_image).unwrap();
+    let camera = cameras_intrinsics.get(&(image.camera_id as u64)).unwrap();
+    
+    let r = qvec2rotmat(&image.qvec).transpose();
+    let t = Vector3::new(image.tvec[0],image.tvec[1],image.tvec[2]);
+
+    let focal_length_x = camera.params[0];
+    let focal_length_y = camera.params[1];
+    let fo_vy = focal2fov(focal_length_y, camera.height as f64);
+    let fo_vx = focal2fov(focal_length_x, camera.width as f64);
+    let znear = 0.001;
+    let zfar = 100.00;
+
+    let world_view_transform = get_world_to_view2(&r, &t);
+    let world_view_transform_p = matrix_vers_points(world_view_transform);
+    let projection_matrix = get_projection_matrix(znear, zfar, fo_vx, fo_vy);
+    let projection_matrix_p = matrix_vers_points(projection_matrix);
+    let camera_center = world_view_transform.try_inverse().expect("erreur d'inversion de world_view_transform");
+    let camera_center_p = matrix_vers_points(camera_center);
+    (world_view_transform_p,projection_matrix_p,camera_center_p,fo_vy,fo_vx)
+
+}
+
+fn matrix_vers_points(matrix: Matrix4<f32>) -> [Point; 4] {
+    let mut points: [Point; 4] = [Point::zero();4];
+
+    for i in 0..4 {
+        let x = matrix[(0, i)];
+        let y = matrix[(1, i)];
+        let z = matrix[(2, i)];
+        let w = matrix[(3, i)];
+
+        points[i] = Point::new( x, y, z, w );
+    }
+
+    points
+}
+
+fn get_world_to_view2(r: &Matrix3<f64>, t: &Vector3<f64>) -> Matrix4<f32> {
+    let mut rt = Matrix4::zeros();
+    rt.fixed_slice_mut::<3, 3>(0, 0).copy_from(&r.transpose());
+    rt.fixed_slice_mut::<3, 1>(0, 3).copy_from(t);
+    rt[(3, 3)] = 1.0;
+    rt.cast::<f32>()
+}
+
+
+fn get_projection_matrix(znear: f64, zfar: f64, fov_x: f64, fov_y: f64) -> Matrix4<f32> {
+    let tan_half_fov_y = (fov_y / 2.0).tan();
+    let tan_half_fov_x = (fov_x / 2.0).tan();
+
+    let top = tan_half_fov_y * znear;
+    let bottom = -top;
+    let right = tan_half_fov_x * znear;
+    let left = -right;
+
+    let mut p = Matrix4::zeros();
+
+    let z_sign = 1.0;
+
+    p[(0, 0)] = 2.0 * znear / (right - left);
+    p[(1, 1)] = 2.0 * znear / (top - bottom);
+    p[(0, 2)] = (right + left) / (right - left);
+    p[(1, 2)] = (top + bottom) / (top - bottom);
+    p[(3, 2)] = z_sign;
+    p[(2, 2)] = z_sign * zfar / (zfar - znear);
+    p[(2, 3)] = -(zfar * znear) / (zfar - znear);
+
+    p.cast::<f32>()
+}
@@ -0,0 +1,154 @@
+//! Regions of space for cropping scenes
+use crate::{scene::GpuSplat, transform};
+
+/// A volume in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    /// Axis aligned box between two corners
+    AxisAlignedBox {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+    /// Box rotated by a unit quaternion (w, x, y, z) around its center
+    OrientedBox {
+        center: [f32; 3],
+        half_extents: [f32; 3],
+        rotation: [f32; 4],
+    },
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+}
+
+impl Region {
+    /// Whether the point lies inside or on the boundary of the region
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        match self {
+            Self::AxisAlignedBox { min, max } => (0..3).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis]),
+            Self::OrientedBox {
+                center,
+                half_extents,
+                rotation,
+            } => {
+                // Rotating by the conjugate moves the point into the frame of the box
+                let inverse_rotation = [rotation[0], -rotation[1], -rotation[2], -rotation[3]];
+                let offset = [0, 1, 2].map(|axis| (point[axis] - center[axis]) as f64);
+                let local_point = transform::rotate(&transform::rotation_matrix(inverse_rotation), offset);
+                (0..3).all(|axis| local_point[axis].abs() <= half_extents[axis] as f64)
+            }
+            Self::Sphere { center, radius } => {
+                let squared_distance: f32 = (0..3).map(|axis| (point[axis] - center[axis]) * (point[axis] - center[axis])).sum();
+                squared_distance <= radius * radius
+            }
+        }
+    }
+
+    /// Minimum and maximum corner of the axis aligned box enclosing the region
+    pub fn bounding_box(&self) -> [[f32; 3]; 2] {
+        match self {
+            Self::AxisAlignedBox { min, max } => [*min, *max],
+            Self::OrientedBox {
+                center,
+                half_extents,
+                rotation,
+            } => {
+                let rotation = transform::rotation_matrix(*rotation);
+                let extents = [0, 1, 2].map(|axis| {
+                    (0..3)
+                        .map(|column| rotation[axis][column].abs() * half_extents[column] as f64)
+                        .sum::<f64>() as f32
+                });
+                [
+                    [0, 1, 2].map(|axis| center[axis] - extents[axis]),
+                    [0, 1, 2].map(|axis| center[axis] + extents[axis]),
+                ]
+            }
+            Self::Sphere { center, radius } => [center.map(|component| component - radius), center.map(|component| component + radius)],
+        }
+    }
+}
+
+/// Selects splats by the position of their centers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplatFilter {
+    /// Keeps the splats inside the region
+    Inside(Region),
+    /// Keeps the splats outside the region, to exclude a volume
+    Outside(Region),
+}
+
+impl SplatFilter {
+    pub fn keeps(&self, splat: &GpuSplat) -> bool {
+        match self {
+            Self::Inside(region) => region.contains(splat.center),
+            Self::Outside(region) => !region.contains(splat.center),
+        }
+    }
+
+    /// Whether all filters keep the splat
+    pub fn all_keep(filters: &[Self], splat: &GpuSplat) -> bool {
+        filters.iter().all(|filter| filter.keeps(splat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_contain_points() {
+        let axis_aligned_box = Region::AxisAlignedBox {
+            min: [-1.0, 0.0, 0.0],
+            max: [1.0, 2.0, 3.0],
+        };
+        assert!(axis_aligned_box.contains([0.0, 2.0, 1.0]));
+        assert!(!axis_aligned_box.contains([0.0, 2.5, 1.0]));
+        // Rotated by 90 degrees around z, so the long axis points along y
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let oriented_box = Region::OrientedBox {
+            center: [1.0, 1.0, 1.0],
+            half_extents: [2.0, 0.5, 0.5],
+            rotation: [h, 0.0, 0.0, h],
+        };
+        assert!(oriented_box.contains([1.0, 2.9, 1.0]));
+        assert!(!oriented_box.contains([2.9, 1.0, 1.0]));
+        let sphere = Region::Sphere {
+            center: [0.0, 0.0, 1.0],
+            radius: 2.0,
+        };
+        assert!(sphere.contains([0.0, 1.9, 1.0]));
+        assert!(!sphere.contains([1.5, 1.5, 1.0]));
+        assert_eq!(sphere.bounding_box(), [[-2.0, -2.0, -1.0], [2.0, 2.0, 3.0]]);
+        let [min, max] = oriented_box.bounding_box();
+        for (axis, expected) in [0.5, 2.0, 0.5].iter().enumerate() {
+            assert!((max[axis] - min[axis] - 2.0 * expected).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn filters_keep_splats() {
+        let sphere = Region::Sphere {
+            center: [0.0; 3],
+            radius: 1.0,
+        };
+        let inside = GpuSplat::default();
+        let outside = GpuSplat {
+            center: [2.0, 0.0, 0.0],
+            ..GpuSplat::default()
+        };
+        assert!(SplatFilter::Inside(sphere).keeps(&inside));
+        assert!(!SplatFilter::Inside(sphere).keeps(&outside));
+        assert!(SplatFilter::Outside(sphere).keeps(&outside));
+        let filters = [
+            SplatFilter::Outside(sphere),
+            SplatFilter::Inside(Region::AxisAlignedBox {
+                min: [0.0; 3],
+                max: [1.0; 3],
+            }),
+        ];
+        assert!(!SplatFilter::all_keep(&filters, &inside));
+        assert!(!SplatFilter::all_keep(&filters, &outside));
+        assert!(SplatFilter::all_keep(&[], &outside));
+    }
+}
@@ -1,8 +1,12 @@
 use std::convert::TryInto;
 
 use crate::{
-    scene::{Scene, Splat},
-    utils::{mat4_multiplication, mat4_transform, motor3d_to_mat4, perspective_projection, transmute_slice,read_and_print_radii_buffer},
+    camera::Camera,
+    codebook::CODEBOOK_ENTRY_SIZE,
+    depth::{DepthPipelines, DepthTarget},
+    scene::{self, Scene},
+    spatial_index::Frustum,
+    utils::{mat4_transform, transmute_slice},
 };
 use geometric_algebra::ppga3d::Point;
 use wgpu::util::DeviceExt;
@@ -21,6 +25,98 @@ pub enum DepthSorting {
     GpuIndirectDraw,
 }
 
+/// Selects how splats are stored in the GPU buffer of a [Scene]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplatLayout {
+    /// All attributes as f32, see [GpuSplat](crate::scene::GpuSplat)
+    Full,
+    /// Positions and opacities as f32, the spherical harmonics, scales and rotations as f16
+    ///
+    /// Nearly halves the GPU memory per splat, at the cost of some precision.
+    HalfPrecision,
+    /// Only the base color and an index into the [SphericalHarmonicsCodebook](crate::codebook::SphericalHarmonicsCodebook) of the scene
+    ///
+    /// Takes less than a third of the GPU memory per splat. The higher order spherical harmonics of uploaded splats are dropped,
+    /// so set the codebook they are indexed into with [Scene::set_spherical_harmonics_codebook] first.
+    Codebook,
+}
+
+/// What shows through where the splats do not cover the frame completely
+pub enum Background {
+    /// Leaves the frame transparent, with its colors premultiplied by the accumulated opacity
+    Transparent,
+    /// A constant color, in the color space of the splats, whose alpha is its opacity
+    Color(wgpu::Color),
+    /// A texture stretched over the frame, with straight alpha
+    ///
+    /// Its texture needs [wgpu::TextureUsages::TEXTURE_BINDING] and a filterable float format, see [Background::from_image].
+    Texture(wgpu::TextureView),
+}
+
+impl Background {
+    /// Uploads an image into a texture to use as background for frame buffers of the given format
+    ///
+    /// Frame buffers in an sRGB format encode the splat colors, so the image is decoded to cancel that out
+    /// and appears unchanged in the frame.
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, frame_buffer_format: wgpu::TextureFormat, image: &image::RgbaImage) -> Self {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("background"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: if frame_buffer_format.is_srgb() {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            size,
+        );
+        Self::Texture(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+}
+
+/// Format of float frame buffers, whose colors stay linear and are neither clamped nor quantized to 8 bits
+///
+/// [Renderer::render_frame] renders into it besides the format of the surface configuration,
+/// see [OffscreenTarget](crate::offscreen::OffscreenTarget).
+pub const FLOAT_FRAME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Blends front to back, the alpha channel of the frame buffer holds the transmittance
+pub(crate) const FRONT_TO_BACK_BLENDING: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::DstAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
 /// Rendering configuration
 pub struct Configuration {
     /// Format of the frame buffer texture
@@ -32,6 +128,8 @@ pub struct Configuration {
     /// Decomposes the conic sections and renders them as rotated rectangles
     pub use_unaligned_rectangles: bool,
     /// How many spherical harmonics coefficients to use, possible values are 0..=3
+    ///
+    /// Capped at the degree stored in the rendered [Scene]
     pub spherical_harmonics_order: usize,
     /// Maximum number of splats to allocate memory for
     pub max_splat_count: usize,
@@ -43,6 +141,10 @@ pub struct Configuration {
     pub ellipse_margin: f32,
     /// Factor to scale splat ellipsoids with. Should be 1.0
     pub splat_scale: f32,
+    /// How splats are stored on the GPU
+    pub splat_layout: SplatLayout,
+    /// What shows through behind the splats, changed with [Renderer::set_background]
+    pub background: Background,
 }
 
 #[repr(C)]
@@ -52,11 +154,37 @@ pub(crate) struct Uniforms {
     view_projection_matrix: [Point; 4],
     view_size: [f32; 2],
     image_size: [u32; 2],
+    view_offset: [f32; 2],
     frustum_culling_tolerance: f32,
     ellipse_size_bias: f32,
     ellipse_margin: f32,
     splat_scale: f32,
-    padding: [f32; 0],
+    spherical_harmonics_order: u32,
+    splat_count: u32,
+    use_spherical_harmonics_codebook: u32,
+    padding: [u32; 3],
+}
+
+/// Mirrors `BackgroundUniforms` in the background shader
+#[repr(C)]
+struct BackgroundUniforms {
+    color: [f32; 4],
+    use_texture: u32,
+    padding: [u32; 3],
+}
+
+impl BackgroundUniforms {
+    fn new(background: &Background) -> Self {
+        let color = match background {
+            Background::Color(color) => [color.r, color.g, color.b, color.a].map(|channel| channel as f32),
+            Background::Transparent | Background::Texture(_) => [0.0; 4],
+        };
+        Self {
+            color,
+            use_texture: matches!(background, Background::Texture(_)) as u32,
+            padding: [0; 3],
+        }
+    }
 }
 
 /// Splats forward renderer
@@ -75,14 +203,23 @@ pub struct Renderer {
     radix_sort_b_pipeline: wgpu::ComputePipeline,
     radix_sort_c_pipeline: wgpu::ComputePipeline,
     render_pipeline: wgpu::RenderPipeline,
+    /// Like `render_pipeline` for frame buffers in the [FLOAT_FRAME_FORMAT]
+    float_render_pipeline: wgpu::RenderPipeline,
     pub(crate) uniform_buffer: wgpu::Buffer,
     pub(crate) sorting_pass_buffers: [wgpu::Buffer; 4],
     pub(crate) sorting_buffer: wgpu::Buffer,
-    pub(crate) entry_buffer_a: wgpu::Buffer,
-    pub(crate) entry_buffer_b: wgpu::Buffer,
-    pub(crate) radii_buffer: wgpu::Buffer, // ce buffer contiendra les radii associcé aux splat, après le tri ? Utile pour la densification
     radii_compute_a_pipeline: wgpu::ComputePipeline,
-
+    pub(crate) depth_pipelines: DepthPipelines,
+    background_bind_group_layout: wgpu::BindGroupLayout,
+    background_pipeline: wgpu::RenderPipeline,
+    /// Like `background_pipeline` for frame buffers in the [FLOAT_FRAME_FORMAT]
+    float_background_pipeline: wgpu::RenderPipeline,
+    background_uniform_buffer: wgpu::Buffer,
+    background_sampler: wgpu::Sampler,
+    /// Bound in place of a background texture for [Background::Color]
+    placeholder_texture_view: wgpu::TextureView,
+    /// Bind group of the [Configuration::background], `None` if it is transparent
+    background_bind_group: Option<wgpu::BindGroup>,
 } 
 
 impl Renderer {
@@ -97,10 +234,20 @@ impl Renderer {
         let workgroup_invocations_c = radix_base;
         let workgroup_entries_a = workgroup_invocations_a * entries_per_invocation_a;
         let workgroup_entries_c = workgroup_invocations_c * entries_per_invocation_c;
-        let max_tile_count_c = (config.max_splat_count + workgroup_entries_c - 1) / workgroup_entries_c;
+        let max_tile_count_c = config.max_splat_count.div_ceil(workgroup_entries_c);
         let sorting_buffer_size =
             (radix_base * (radix_digit_places + max_tile_count_c) * std::mem::size_of::<u32>()) + std::mem::size_of::<u32>() * 5;
         let mut string: String = include_str!("shaders.wgsl").into();
+        let splat_layout_source = match config.splat_layout {
+            SplatLayout::Full => None,
+            SplatLayout::HalfPrecision => Some(include_str!("packed_splat.wgsl")),
+            SplatLayout::Codebook => Some(include_str!("codebook_splat.wgsl")),
+        };
+        if let Some(splat_layout_source) = splat_layout_source {
+            let layout_start = string.find("// BEGIN SPLAT LAYOUT").unwrap();
+            let layout_end = string.find("// END SPLAT LAYOUT").unwrap();
+            string.replace_range(layout_start..layout_end, splat_layout_source);
+        }
 
         // Pipeline overrides are not implemented in wgpu yet
         // injection des paramètres dnas les shaders Webgpu
@@ -183,7 +330,7 @@ impl Renderer {
         };
         let entries_b_layout = wgpu::BindGroupLayoutEntry {
             binding: 4,
-            ..entries_a_layout.clone()
+            ..entries_a_layout
         };
         let splats_layout = wgpu::BindGroupLayoutEntry {
             binding: 6,
@@ -191,7 +338,7 @@ impl Renderer {
             ty: wgpu::BindingType::Buffer {
                 ty: wgpu::BufferBindingType::Storage { read_only: true },
                 has_dynamic_offset: false,
-                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Splat>() as u64),
+                min_binding_size: wgpu::BufferSize::new(scene::splat_size(config.splat_layout) as u64),
             },
             count: None,
         };
@@ -210,6 +357,16 @@ impl Renderer {
                     count: None,
                 },
                 splats_layout,
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new((CODEBOOK_ENTRY_SIZE * std::mem::size_of::<f32>()) as u64),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -268,51 +425,44 @@ impl Renderer {
             entry_point: "radixSortC",
         });
         
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vertex",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fragment",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.surface_configuration.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::DstAlpha,
-                            dst_factor: wgpu::BlendFactor::One,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::Zero,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                unclipped_depth: false,
-                cull_mode: None,
-                conservative: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let create_render_pipeline = |format: wgpu::TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(FRONT_TO_BACK_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    unclipped_depth: false,
+                    cull_mode: None,
+                    conservative: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+        };
+        let render_pipeline = create_render_pipeline(config.surface_configuration.format);
+        let float_render_pipeline = create_render_pipeline(FLOAT_FRAME_FORMAT);
         let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             size: std::mem::size_of::<Uniforms>() as u64,
@@ -336,56 +486,109 @@ impl Renderer {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
-        let entry_buffer_usage = if matches!(config.depth_sorting, DepthSorting::Cpu) {
-            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
-        } else {
-            wgpu::BufferUsages::STORAGE
-        } | wgpu::BufferUsages::COPY_SRC;
-        let entry_buffer_a = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (config.max_splat_count * std::mem::size_of::<(u32, u32)>()) as u64,
-            usage: entry_buffer_usage,
-            mapped_at_creation: false,
-        });
-        let entry_buffer_b = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (config.max_splat_count * std::mem::size_of::<(u32, u32)>()) as u64,
-            usage: entry_buffer_usage,
-            mapped_at_creation: false,
-        });
-
-
-
-        // calcul du Radii
-        // création du buffer qui contiendra les info pour le calcul du Radii
-        /// Cela permet de visualiser que le calcul du bffer a bien fonctionner et qu'il y'a bien splat_count radii
-        let radii_buffer_size = (config.max_splat_count * std::mem::size_of::<f32>()) as u64;
-        let radii_data = vec![100.0f32; config.max_splat_count]; // Création d'un vecteur rempli de 0.0 pour chaque élément du buffer.
-
-        let radii_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Radii Buffer"),
-            contents: bytemuck::cast_slice(&radii_data),
-
-
-            usage:wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST ,
-        });
-        
-       
-
-        let radii_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&compute_bind_group_layout],
-            push_constant_ranges: &[],
-        });
         let radii_compute_a_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("Mon Pipeline de Calcul"),
             layout: Some(&compute_pipeline_layout),
             module: &shader_module,
             entry_point: "computeRadii",
         });
-      
-    
-        Self {
+        let depth_pipelines = DepthPipelines::new(device, &shader_module, &render_bind_group_layout);
+
+        let background_shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("background"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("background.wgsl").into()),
+        });
+        let background_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("background"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<BackgroundUniforms>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let background_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("background"),
+            bind_group_layouts: &[&background_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_background_pipeline = |format: wgpu::TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("background"),
+                layout: Some(&background_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &background_shader_module,
+                    entry_point: "backgroundVertex",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &background_shader_module,
+                    entry_point: "backgroundFragment",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(FRONT_TO_BACK_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let background_pipeline = create_background_pipeline(config.surface_configuration.format);
+        let float_background_pipeline = create_background_pipeline(FLOAT_FRAME_FORMAT);
+        let background_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("background"),
+            contents: transmute_slice::<_, u8>(&[BackgroundUniforms::new(&config.background)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let background_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("background"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+        let placeholder_texture_view = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("background placeholder"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut renderer = Self {
             config,
             radix_digit_places,
             radix_base,
@@ -399,48 +602,110 @@ impl Renderer {
             radix_sort_b_pipeline,
             radix_sort_c_pipeline,
             render_pipeline,
+            float_render_pipeline,
             uniform_buffer,
             sorting_pass_buffers,
             sorting_buffer,
-            entry_buffer_a,
-            entry_buffer_b,
-            radii_buffer,
             radii_compute_a_pipeline,
-        }
+            depth_pipelines,
+            background_bind_group_layout,
+            background_pipeline,
+            float_background_pipeline,
+            background_uniform_buffer,
+            background_sampler,
+            placeholder_texture_view,
+            background_bind_group: None,
+        };
+        renderer.background_bind_group = renderer.create_background_bind_group(device);
+        renderer
+    }
+
+    /// Replaces the [Configuration::background] and binds it for the following frames
+    pub fn set_background(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, background: Background) {
+        queue.write_buffer(&self.background_uniform_buffer, 0, transmute_slice::<_, u8>(&[BackgroundUniforms::new(&background)]));
+        self.config.background = background;
+        self.background_bind_group = self.create_background_bind_group(device);
+    }
+
+    fn create_background_bind_group(&self, device: &wgpu::Device) -> Option<wgpu::BindGroup> {
+        let texture_view = match &self.config.background {
+            Background::Transparent => return None,
+            Background::Color(_) => &self.placeholder_texture_view,
+            Background::Texture(texture_view) => texture_view,
+        };
+        Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("background"),
+            layout: &self.background_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.background_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.background_sampler),
+                },
+            ],
+        }))
     }
     
-    /// Renders the given `scene` into `frame_view`
-    pub fn render_frame(
+    /// Renders the given `scene` into `texture`, as seen by `camera`
+    ///
+    /// The [Background] of the configuration is blended behind the splats.
+    /// The texture has the format of the surface configuration or the [FLOAT_FRAME_FORMAT].
+    /// Only submits the commands, see [OffscreenTarget](crate::offscreen::OffscreenTarget) to read the frame back.
+    pub fn render_frame(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture, scene: &Scene, camera: &Camera) {
+        self.render(device, queue, texture, None, scene, camera);
+    }
+
+    /// Like [Renderer::render_frame], and also renders the depth maps of the frame into `depth_target`
+    pub(crate) fn render_frame_with_depth(
         &self,
         device: &wgpu::Device,
-        queue: &mut wgpu::Queue,
+        queue: &wgpu::Queue,
         texture: &wgpu::Texture,
-        viewport_size: wgpu::Extent3d,
+        depth_target: &DepthTarget,
         scene: &Scene,
-        view_matrix: [Point; 4], 
-        projection_matrix : [Point; 4], 
-        camera_matrix : [Point; 4], 
-        fo_vy : f64, 
-        fo_vx : f64
-    )-> wgpu::Buffer{
-
-        let field_of_view_y = fo_vy as f32; 
-        let field_of_view_x = fo_vx as f32; 
-        let view_height = (field_of_view_y * 0.5).tan() as f32;
-        let view_width = (field_of_view_x * 0.5).tan() as f32;
+        camera: &Camera,
+    ) {
+        self.render(device, queue, texture, Some(depth_target), scene, camera);
+    }
 
-        let view_projection_matrix = mat4_multiplication(&projection_matrix, &view_matrix);
+    fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        depth_target: Option<&DepthTarget>,
+        scene: &Scene,
+        camera: &Camera,
+    ) {
+        let view_projection_matrix = camera.view_projection_matrix();
+        let [view_width, _] = camera.view_size();
 
-        let mut splat_count = scene.splat_count;
+        let mut splat_count = scene.loaded_splat_count;
         let frame_view = &texture.create_view(&wgpu::TextureViewDescriptor::default());
-        if matches!(self.config.depth_sorting, DepthSorting::Cpu) {
-            let mut entries: Vec<(u32, u32)> = (0..scene.splat_count)
+        let (render_pipeline, background_pipeline) = if texture.format() == FLOAT_FRAME_FORMAT {
+            (&self.float_render_pipeline, &self.float_background_pipeline)
+        } else {
+            (&self.render_pipeline, &self.background_pipeline)
+        };
+        if let (DepthSorting::Cpu, Some(splat_positions)) = (&self.config.depth_sorting, &scene.splat_positions) {
+            let frustum = Frustum::from_view_projection(&view_projection_matrix, self.config.frustum_culling_tolerance);
+            let mut entries: Vec<(u32, u32)> = scene
+                .spatial_index()
+                .query_frustum(&frustum)
+                .into_iter()
                 .filter_map(|splat_index| {
                     // On récupère la position dans le monde du splat
                     let world_position = Point::new(
-                        scene.splat_positions[splat_index * 3 + 0],
-                        scene.splat_positions[splat_index * 3 + 1],
-                        scene.splat_positions[splat_index * 3 + 2],
+                        splat_positions[splat_index * 3],
+                        splat_positions[splat_index * 3 + 1],
+                        splat_positions[splat_index * 3 + 2],
                         1.0,
                     );
                     //La position du monde est transformée en espace de clip à l'aide de la view_projection_matrix
@@ -452,28 +717,32 @@ impl Renderer {
                         && (clip_space_position[2] - 0.5).abs() < 0.5
                     {
                         //n prépare un tuple contenant la profondeur (convertie de f32 à u32 pour le tri) et l'index du "splat"
-                        Some((unsafe { std::mem::transmute::<f32, u32>(clip_space_position[2]) }, splat_index as u32))
+                        Some((clip_space_position[2].to_bits(), splat_index as u32))
                     } else {
                         None
                     }
                 })
                 .collect();
-            //Une fois triés, les indices et profondeurs des "splats" sont écrits dans le buffer self.entry_buffer_a. Ce buffer est utilisé plus tard dans le pipeline de rendu pour dessiner les "splats" dans l'ordre correct.
+            //Une fois triés, les indices et profondeurs des "splats" sont écrits dans le buffer entry_buffer_a de la scène. Ce buffer est utilisé plus tard dans le pipeline de rendu pour dessiner les "splats" dans l'ordre correct.
             splat_count = entries.len();
-            entries.sort_by(|a, b| a.0.cmp(&b.0));
-            queue.write_buffer(&self.entry_buffer_a, 0, transmute_slice::<_, u8>(&entries));
+            entries.sort_by_key(|entry| entry.0);
+            queue.write_buffer(&scene.buffers.entry_buffer_a, 0, transmute_slice::<_, u8>(&entries));
         }
         let uniform_data = &[Uniforms {
-            camera_matrix,
-            view_matrix,
+            camera_matrix: camera.camera_matrix(),
+            view_matrix: camera.view_matrix(),
             view_projection_matrix,
-            view_size: [view_width, view_height],
-            image_size: [viewport_size.width, viewport_size.height],
+            view_size: camera.view_size(),
+            image_size: [camera.width, camera.height],
+            view_offset: camera.view_offset(),
             frustum_culling_tolerance: self.config.frustum_culling_tolerance,
-            ellipse_size_bias: 0.2 * view_width / viewport_size.width as f32,
+            ellipse_size_bias: 0.2 * view_width / camera.width as f32,
             ellipse_margin: self.config.ellipse_margin,
             splat_scale: self.config.splat_scale,
-            padding: [0.0; 0],
+            spherical_harmonics_order: self.config.spherical_harmonics_order.min(scene.spherical_harmonics_degree) as u32,
+            splat_count: scene.loaded_splat_count as u32,
+            use_spherical_harmonics_codebook: scene.spherical_harmonics_codebook().is_some() as u32,
+            padding: [0; 3],
         }];
         //les données uniformes dans le buffer uniforme (uniform_buffer) utilisé par le shader. 
         queue.write_buffer(&self.uniform_buffer, 0, transmute_slice::<_, u8>(uniform_data));
@@ -483,9 +752,9 @@ impl Renderer {
             encoder.clear_buffer(&self.sorting_buffer, 0, None);
             {
                 let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-                compute_pass.set_bind_group(0, &scene.compute_bind_groups[1], &[]);
+                compute_pass.set_bind_group(0, &scene.buffers.compute_bind_groups[1], &[]);
                 compute_pass.set_pipeline(&self.radix_sort_a_pipeline);
-                compute_pass.dispatch_workgroups(((splat_count + self.workgroup_entries_a - 1) / self.workgroup_entries_a) as u32, 1, 1);
+                compute_pass.dispatch_workgroups(splat_count.div_ceil(self.workgroup_entries_a) as u32, 1, 1);
                 compute_pass.set_pipeline(&self.radix_sort_b_pipeline);
                 compute_pass.dispatch_workgroups(1, self.radix_digit_places as u32, 1);
             }
@@ -499,15 +768,15 @@ impl Renderer {
                 }
                 let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
                 compute_pass.set_pipeline(&self.radix_sort_c_pipeline);
-                compute_pass.set_bind_group(0, &scene.compute_bind_groups[pass_index], &[]);
-                compute_pass.dispatch_workgroups(1, ((splat_count + self.workgroup_entries_c - 1) / self.workgroup_entries_c) as u32, 1);
+                compute_pass.set_bind_group(0, &scene.buffers.compute_bind_groups[pass_index], &[]);
+                compute_pass.dispatch_workgroups(1, splat_count.div_ceil(self.workgroup_entries_c) as u32, 1);
             }
         }
-        let workgroups_x = ((splat_count + 64) /65) as u32;
+        let workgroups_x = splat_count.div_ceil(65) as u32;
 
 
         {let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-        compute_pass.set_bind_group(0, &scene.compute_bind_groups[1], &[]);
+        compute_pass.set_bind_group(0, &scene.buffers.compute_bind_groups[1], &[]);
 
         compute_pass.set_pipeline(&self.radii_compute_a_pipeline);
         compute_pass.dispatch_workgroups(workgroups_x, 1, 1);    }
@@ -519,111 +788,46 @@ impl Renderer {
                     view: frame_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
+                        // Black with a transmittance of one
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: true,
                     },
                 })],
                 depth_stencil_attachment: None,
             });
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &scene.render_bind_group, &[]);
-            if matches!(self.config.depth_sorting, DepthSorting::GpuIndirectDraw) {
-                render_pass.draw_indirect(&self.sorting_buffer, (self.sorting_buffer_size - std::mem::size_of::<u32>() * 5) as u64);
-            } else {
-                render_pass.draw(0..4, 0..splat_count as u32);
-            }
+            render_pass.set_pipeline(render_pipeline);
+            self.draw_splats(&mut render_pass, scene, splat_count);
+        }
+        if let Some(background_bind_group) = &self.background_bind_group {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("background pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(background_pipeline);
+            render_pass.set_bind_group(0, background_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        if let Some(depth_target) = depth_target {
+            depth_target.encode(queue, &mut encoder, self, scene, splat_count);
         }
-            // VERIF DE RADII:
-        let buffer_size = (self.config.max_splat_count * std::mem::size_of::<f32>()) as u64;
-        
-        let copy_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Copy Buffer"),
-            size: buffer_size,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        // Encoder la commande pour copier les données du radii_buffer vers le copy_buffer
-        //encoder.copy_buffer_to_buffer(&self.radii_buffer, 0, &copy_buffer, 0, buffer_size);
-
-        let texel_size = texture.format().block_size(None).unwrap();
-        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            size: (texel_size * texture.width() * texture.height()).into(),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            label: None,
-            mapped_at_creation: false,
-        });
-
-
-        encoder.copy_texture_to_buffer(
-            // source
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            // destination
-            wgpu::ImageCopyBuffer {
-                buffer: &output_buffer,
-                layout: wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * texture.width()),
-                    rows_per_image: Some(texture.height()),
-                },
-            },
-            // copy_size
-            texture.size(),
-        );
-
-
-
         queue.submit(Some(encoder.finish()));
-        // Attendre que la copie soit terminée
-        //device.poll(wgpu::Maintain::Wait);
-
-        
-        output_buffer
-        
-        /*let buffer_slice = copy_buffer.slice(..);
-        let (sender, receiver) = futures::channel::oneshot::channel();
-        buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-        device.poll(wgpu::Maintain::Wait); // S'assurer que la demande de mappage est traitée
-        let mut sum = 0;
-        let mut max_radius_not_100 = f32::NEG_INFINITY; // Initialisation avec la plus petite valeur possible
-        let mut iteration_count = 0; // Variable de comptage des itérations
-
-        if let Ok(Ok(())) = futures::executor::block_on(receiver) {
-            // Accès aux données du buffer de copie
-            let data = buffer_slice.get_mapped_range();
-            let radii: &[f32] = bytemuck::cast_slice(&data);
-            for &radius in radii.iter() {
-                println!("Radius: {}", radius);
-                
-                if radius != 100.0 {
-                    sum += 1;
-
-                }
-                iteration_count += 1; 
-
-                if radius != 100.0 && radius > max_radius_not_100 {
-                    max_radius_not_100 = radius; // Mettre à jour le maximum
-                }
-                if iteration_count >= 100 {
-                   break; // Sortir de la boucle après 10 itérations
-                }
-                
-                
-            }
-
-            // Démapper le buffer après utilisation
-            drop(data);
-            copy_buffer.unmap();
-            println!("La somme est : {}", sum);
-            println!("La somme est : {}", splat_count);
-            println!("La valeur maximale de radii qui n'est pas égale à 100.0 est : {}", max_radius_not_100);
+    }
 
-        }*/
-        
+    /// Draws the splats in the order sorted for the frame, `splat_count` of them unless the draw is indirect
+    pub(crate) fn draw_splats<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, scene: &'a Scene, splat_count: usize) {
+        render_pass.set_bind_group(0, &scene.buffers.render_bind_group, &[]);
+        if matches!(self.config.depth_sorting, DepthSorting::GpuIndirectDraw) {
+            render_pass.draw_indirect(&self.sorting_buffer, (self.sorting_buffer_size - std::mem::size_of::<u32>() * 5) as u64);
+        } else {
+            render_pass.draw(0..4, 0..splat_count as u32);
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,26 +1,146 @@
 use crate::{
-    renderer::{DepthSorting, Renderer, Uniforms},
-    utils::{transmute_slice, transmute_slice_mut},
+    codebook::{self, CodebookFileLayout, SphericalHarmonicsCodebook, CODEBOOK_ENTRY_SIZE},
+    compressed_ply::{self, CompressedPlyLayout},
+    ply::{self, PlyElement, PlyError, PlyFormat, PlyHeader, PlyScalarType},
+    region::SplatFilter,
+    renderer::{DepthSorting, Renderer, SplatLayout, Uniforms},
+    spatial_index::SpatialIndex,
+    statistics::SplatSummary,
+    transform::{Similarity, SphericalHarmonicsRotation},
+    utils::{pack2x16float, transmute_slice, unpack2x16float},
 };
 use geometric_algebra::{ppga3d::Rotor, Signum};
 use std::{
     convert::TryInto,
     fs::File,
-    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
 };
+use wgpu::util::DeviceExt;
+
+/// Errors which can occur while loading a [Scene]
+#[derive(Debug)]
+pub enum SceneError {
+    /// Reading the file failed
+    Io(std::io::Error),
+    /// The file ends before all splats declared in its header
+    Truncated,
+    /// The extension of the file is none of those of [SplatFormat]
+    UnknownFormat(std::path::PathBuf),
+    /// The header is not a valid PLY header or lacks splat attributes
+    MalformedHeader(String),
+    /// A record of a PLY file can not be parsed
+    MalformedRecord(PlyError),
+    /// More splats were requested than the renderer or the scene can hold
+    SplatCountOverflow { splat_count: usize, max_splat_count: usize },
+    /// The scene has no spherical harmonics codebook to save
+    MissingCodebook,
+    /// A splat index is not less than the `splat_count` of the scene
+    SplatIndexOutOfBounds { splat_index: usize, splat_count: usize },
+    /// The camera renders images of another size, in pixels, than the target it renders into
+    ViewportSizeMismatch { camera_size: [u32; 2], target_size: [u32; 2] },
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::Truncated => write!(f, "the splat file is truncated"),
+            Self::UnknownFormat(path) => write!(f, "unknown splat file format of {}", path.display()),
+            Self::MalformedHeader(message) => write!(f, "malformed header: {}", message),
+            Self::MalformedRecord(error) => write!(f, "malformed record: {}", error),
+            Self::SplatCountOverflow { splat_count, max_splat_count } => write!(
+                f,
+                "{} splats exceed the maximum splat count of {}",
+                splat_count, max_splat_count
+            ),
+            Self::MissingCodebook => write!(f, "the scene has no spherical harmonics codebook"),
+            Self::SplatIndexOutOfBounds { splat_index, splat_count } => {
+                write!(f, "splat index {} is out of bounds for {} splats", splat_index, splat_count)
+            }
+            Self::ViewportSizeMismatch { camera_size, target_size } => write!(
+                f,
+                "the camera renders {}x{} pixels into a target of {}x{} pixels",
+                camera_size[0], camera_size[1], target_size[0], target_size[1]
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::MalformedRecord(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<PlyError> for SceneError {
+    fn from(error: PlyError) -> Self {
+        match error {
+            PlyError::Io(error) => Self::Io(error),
+            PlyError::Truncated => Self::Truncated,
+            PlyError::MalformedHeader(message) => Self::MalformedHeader(message),
+            PlyError::Parse { .. } | PlyError::RecordLength { .. } => Self::MalformedRecord(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::UnexpectedEof {
+            Self::Truncated
+        } else {
+            Self::Io(error)
+        }
+    }
+}
 
 // Because of numerical precision issues in the shader we have to limit the excentricity of ellipsoids
 const MAX_SIZE_VARIANCE: f32 = 5.0;
 
+// Opacities are clamped to [MIN_ALPHA, 1 - MIN_ALPHA] before the inverse sigmoid to keep them finite
+const MIN_ALPHA: f32 = 1.0e-6;
+
+// How many splats are copied into a single staging buffer when reading back the GPU
+const DOWNLOAD_CHUNK_SIZE: usize = 1 << 16;
+
+// Zeroth order spherical harmonics basis function, relates the DC coefficients to RGB colors
+pub(crate) const SH_C0: f32 = 0.282_094_8;
+
+// Size of a splat in the compact `.splat` format
+pub(crate) const COMPACT_SPLAT_SIZE: usize = 32;
+
+pub(crate) fn sigmoid(value: f32) -> f32 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+pub(crate) fn inverse_sigmoid(value: f32) -> f32 {
+    let value = value.clamp(MIN_ALPHA, 1.0 - MIN_ALPHA);
+    (value / (1.0 - value)).ln()
+}
+
+/// A splat as stored in 3DGS files
+///
+/// The color holds the 16 spherical harmonics coefficients channel by channel:
+/// first the three `f_dc_*` values, then the 15 `f_rest_*` values of red, green and blue.
+/// Coefficients beyond the degree of the file are zero.
+/// Convert it into a [GpuSplat] to add splats with the attributes of 3DGS files by [Scene::append_splats].
 #[derive(Clone)]
 #[repr(C)]
-struct SerializedSplat {
-    center: [f32; 3],
-    n: [f32; 3],
-    color: [f32; 3 * 16],
-    alpha: f32,
-    scale: [f32; 3],
-    rotation: [f32; 4],
+pub struct SerializedSplat {
+    pub center: [f32; 3],
+    /// The normal, which 3DGS files store but the renderer ignores
+    pub n: [f32; 3],
+    pub color: [f32; 3 * 16],
+    /// Opacity before the sigmoid activation
+    pub alpha: f32,
+    /// Natural logarithms of the scales along the axes of the ellipsoid
+    pub scale: [f32; 3],
+    /// The rotation quaternion (w, x, y, z), not necessarily normalized
+    pub rotation: [f32; 4],
 }
 
 impl Default for SerializedSplat {
@@ -29,25 +149,538 @@ impl Default for SerializedSplat {
     }
 }
 
-pub(crate) type Splat = [f32; 60];
+impl SerializedSplat {
+    /// Decodes a splat of the compact `.splat` format
+    ///
+    /// The record holds the position and linear scale as f32, the RGBA color and the rotation (w, x, y, z) as u8.
+    /// Only the DC coefficients of the color are set.
+    pub(crate) fn from_compact(record: &[u8]) -> Self {
+        let float = |index: usize| f32::from_le_bytes(record[index * 4..index * 4 + 4].try_into().unwrap());
+        let mut serialized_splat = Self::default();
+        for axis in 0..3 {
+            serialized_splat.center[axis] = float(axis);
+            serialized_splat.scale[axis] = float(3 + axis).max(f32::MIN_POSITIVE).ln();
+            serialized_splat.color[axis] = (record[24 + axis] as f32 / 255.0 - 0.5) / SH_C0;
+        }
+        serialized_splat.alpha = inverse_sigmoid(record[27] as f32 / 255.0);
+        for component in 0..4 {
+            serialized_splat.rotation[component] = (record[28 + component] as f32 - 128.0) / 128.0;
+        }
+        serialized_splat
+    }
 
-/// A point cloud of splats
-pub struct Scene {
+    /// Encodes a splat in the GPU layout into the compact `.splat` format, inverting [SerializedSplat::from_compact]
+    ///
+    /// Higher order spherical harmonics are dropped.
+    pub(crate) fn compact_record(splat: &GpuSplat) -> [u8; COMPACT_SPLAT_SIZE] {
+        let mut record = [0; COMPACT_SPLAT_SIZE];
+        for axis in 0..3 {
+            record[axis * 4..axis * 4 + 4].copy_from_slice(&splat.center[axis].to_le_bytes());
+            record[12 + axis * 4..16 + axis * 4].copy_from_slice(&splat.scale[axis].to_le_bytes());
+            record[24 + axis] = ((0.5 + SH_C0 * splat.color[axis]) * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        record[27] = (splat.alpha * 255.0).round().clamp(0.0, 255.0) as u8;
+        for component in 0..4 {
+            record[28 + component] = (splat.rotation[component] * 128.0 + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+        record
+    }
+
+    /// Appends the values of the properties named by [splat_property_names] to `values`
+    fn property_values(&self, spherical_harmonics_degree: usize, values: &mut Vec<f64>) {
+        let coefficients_per_channel = (spherical_harmonics_degree + 1) * (spherical_harmonics_degree + 1) - 1;
+        let mut extend = |slice: &[f32]| values.extend(slice.iter().map(|value| *value as f64));
+        extend(&self.center);
+        extend(&self.n);
+        extend(&self.color[0..3]);
+        for channel in 0..3 {
+            extend(&self.color[3 + channel * 15..3 + channel * 15 + coefficients_per_channel]);
+        }
+        extend(&[self.alpha]);
+        extend(&self.scale);
+        extend(&self.rotation);
+    }
+}
+
+/// Names of the vertex properties of a standard 3DGS file, in the order they are written
+fn splat_property_names(spherical_harmonics_degree: usize) -> Vec<String> {
+    let coefficients_per_channel = (spherical_harmonics_degree + 1) * (spherical_harmonics_degree + 1) - 1;
+    ["x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2"]
+        .iter()
+        .map(|name| name.to_string())
+        .chain((0..3 * coefficients_per_channel).map(|index| format!("f_rest_{}", index)))
+        .chain(
+            ["opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3"]
+                .iter()
+                .map(|name| name.to_string()),
+        )
+        .collect()
+}
+
+/// Number of consecutive `f_rest_*` properties of an element
+pub(crate) fn color_rest_count(element: &PlyElement) -> usize {
+    (0..).take_while(|index| element.property_index(&format!("f_rest_{}", index)).is_some()).count()
+}
+
+/// Degree of the spherical harmonics stored in `color_rest_count` `f_rest_*` properties
+pub(crate) fn spherical_harmonics_degree(color_rest_count: usize) -> Result<usize, SceneError> {
+    match color_rest_count {
+        0 => Ok(0),
+        9 => Ok(1),
+        24 => Ok(2),
+        45 => Ok(3),
+        _ => Err(SceneError::MalformedHeader(format!(
+            "Splat file has {} f_rest properties, which matches no spherical harmonics degree",
+            color_rest_count
+        ))),
+    }
+}
+
+/// A splat in the layout of the `Splat` struct in the shaders
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct GpuSplat {
+    /// Normalized rotor (w, x, y, z)
+    pub rotation: [f32; 4],
+    pub center: [f32; 3],
+    /// Index into the [SphericalHarmonicsCodebook] of the scene, if it has one
+    pub codebook_index: u32,
+    /// Linear scale along the three axes
+    pub scale: [f32; 3],
+    /// Opacity in [0, 1]
+    pub alpha: f32,
+    /// The 16 spherical harmonics coefficients, each holding red, green and blue
+    pub color: [f32; 3 * 16],
+}
+
+unsafe impl bytemuck::Zeroable for GpuSplat {}
+unsafe impl bytemuck::Pod for GpuSplat {}
+
+impl Default for GpuSplat {
+    fn default() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+impl GpuSplat {
+    /// Color seen from every direction, given by the zeroth spherical harmonics coefficient
+    pub fn base_color(&self) -> [f32; 3] {
+        [0, 1, 2].map(|channel| 0.5 + SH_C0 * self.color[channel])
+    }
+
+    /// Overrides the zeroth spherical harmonics coefficient, keeping the view dependent part
+    pub fn set_base_color(&mut self, color: [f32; 3]) {
+        for (channel, value) in color.iter().enumerate() {
+            self.color[channel] = (value - 0.5) / SH_C0;
+        }
+    }
+}
+
+/// A splat in the layout of the `Splat` struct in `packed_splat.wgsl`
+///
+/// Keeps the center and opacity as f32 and stores everything else as pairs of f16.
+/// The scales are stored as their natural logarithms like in 3DGS files, as small scales would lose their precision
+/// in the subnormal range of f16 or even become zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub(crate) struct PackedGpuSplat {
+    center: [f32; 3],
+    alpha: f32,
+    rotation: [u32; 2],
+    /// The fourth f16 holds the codebook index
+    scale: [u32; 2],
+    color: [u32; 3 * 16 / 2],
+}
+
+unsafe impl bytemuck::Zeroable for PackedGpuSplat {}
+unsafe impl bytemuck::Pod for PackedGpuSplat {}
+
+impl From<&GpuSplat> for PackedGpuSplat {
+    fn from(splat: &GpuSplat) -> Self {
+        let mut color = [0; 3 * 16 / 2];
+        for (packed, pair) in color.iter_mut().zip(splat.color.chunks_exact(2)) {
+            *packed = pack2x16float([pair[0], pair[1]]);
+        }
+        Self {
+            center: splat.center,
+            alpha: splat.alpha,
+            rotation: [
+                pack2x16float([splat.rotation[0], splat.rotation[1]]),
+                pack2x16float([splat.rotation[2], splat.rotation[3]]),
+            ],
+            scale: [
+                pack2x16float([splat.scale[0].ln(), splat.scale[1].ln()]),
+                pack2x16float([splat.scale[2].ln(), 0.0]) | splat.codebook_index << 16,
+            ],
+            color,
+        }
+    }
+}
+
+impl From<&PackedGpuSplat> for GpuSplat {
+    fn from(packed_splat: &PackedGpuSplat) -> Self {
+        let [rotation_w, rotation_x] = unpack2x16float(packed_splat.rotation[0]);
+        let [rotation_y, rotation_z] = unpack2x16float(packed_splat.rotation[1]);
+        let [scale_x, scale_y] = unpack2x16float(packed_splat.scale[0]);
+        let [scale_z, _] = unpack2x16float(packed_splat.scale[1]);
+        let mut splat = Self {
+            rotation: [rotation_w, rotation_x, rotation_y, rotation_z],
+            center: packed_splat.center,
+            codebook_index: packed_splat.scale[1] >> 16,
+            scale: [scale_x.exp(), scale_y.exp(), scale_z.exp()],
+            alpha: packed_splat.alpha,
+            ..Self::default()
+        };
+        for (pair, packed) in splat.color.chunks_exact_mut(2).zip(packed_splat.color.iter()) {
+            pair.copy_from_slice(&unpack2x16float(*packed));
+        }
+        splat
+    }
+}
+
+/// A splat in the layout of the `Splat` struct in `codebook_splat.wgsl`
+///
+/// Stores the base color and the index into the codebook of the scene instead of all spherical harmonics coefficients.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub(crate) struct CodebookGpuSplat {
+    rotation: [f32; 4],
+    center: [f32; 3],
+    codebook_index: u32,
+    scale: [f32; 3],
+    alpha: f32,
+    color_dc: [f32; 3],
+    padding: u32,
+}
+
+unsafe impl bytemuck::Zeroable for CodebookGpuSplat {}
+unsafe impl bytemuck::Pod for CodebookGpuSplat {}
+
+impl From<&GpuSplat> for CodebookGpuSplat {
+    fn from(splat: &GpuSplat) -> Self {
+        Self {
+            rotation: splat.rotation,
+            center: splat.center,
+            codebook_index: splat.codebook_index,
+            scale: splat.scale,
+            alpha: splat.alpha,
+            color_dc: [splat.color[0], splat.color[1], splat.color[2]],
+            padding: 0,
+        }
+    }
+}
+
+impl From<&CodebookGpuSplat> for GpuSplat {
+    /// Leaves the higher order coefficients zero, see [SphericalHarmonicsCodebook::decode]
+    fn from(codebook_splat: &CodebookGpuSplat) -> Self {
+        let mut splat = Self {
+            rotation: codebook_splat.rotation,
+            center: codebook_splat.center,
+            codebook_index: codebook_splat.codebook_index,
+            scale: codebook_splat.scale,
+            alpha: codebook_splat.alpha,
+            ..Self::default()
+        };
+        splat.color[0..3].copy_from_slice(&codebook_splat.color_dc);
+        splat
+    }
+}
+
+/// Splits ascending splat indices into runs of consecutive ones
+///
+/// Returns the first splat index of each run and the positions of the run in `splat_indices`.
+pub(crate) fn index_runs(splat_indices: &[usize]) -> Vec<(usize, std::ops::Range<usize>)> {
+    let mut runs = Vec::new();
+    let mut run_start = 0;
+    for position in 1..=splat_indices.len() {
+        if position < splat_indices.len() && splat_indices[position] == splat_indices[position - 1] + 1 {
+            continue;
+        }
+        runs.push((splat_indices[run_start], run_start..position));
+        run_start = position;
+    }
+    runs
+}
+
+/// Size of a splat in the GPU buffer, see [GpuSplat], [PackedGpuSplat] and [CodebookGpuSplat]
+pub(crate) fn splat_size(splat_layout: SplatLayout) -> usize {
+    match splat_layout {
+        SplatLayout::Full => std::mem::size_of::<GpuSplat>(),
+        SplatLayout::HalfPrecision => std::mem::size_of::<PackedGpuSplat>(),
+        SplatLayout::Codebook => std::mem::size_of::<CodebookGpuSplat>(),
+    }
+}
+
+impl From<&SerializedSplat> for GpuSplat {
+    fn from(serialized_splat: &SerializedSplat) -> Self {
+        let mut splat = Self {
+            rotation: <[f32; 4]>::from(Rotor::from(serialized_splat.rotation).signum()),
+            center: serialized_splat.center,
+            alpha: sigmoid(serialized_splat.alpha),
+            ..Self::default()
+        };
+        let average = (serialized_splat.scale[0] + serialized_splat.scale[1] + serialized_splat.scale[2]) / 3.0;
+        for axis in 0..3 {
+            splat.scale[axis] = serialized_splat.scale[axis]
+                .max(average - MAX_SIZE_VARIANCE)
+                .min(average + MAX_SIZE_VARIANCE)
+                .exp();
+        }
+        // The GPU layout interleaves the color channels of each coefficient
+        splat.color[0..3].copy_from_slice(&serialized_splat.color[0..3]);
+        for coefficient in 1..16 {
+            for channel in 0..3 {
+                splat.color[coefficient * 3 + channel] = serialized_splat.color[3 + channel * 15 + coefficient - 1];
+            }
+        }
+        splat
+    }
+}
+
+impl From<&GpuSplat> for SerializedSplat {
+    /// Converts back from the layout used on the GPU, inverting the conversion into [GpuSplat]
+    fn from(splat: &GpuSplat) -> Self {
+        let mut serialized_splat = Self {
+            center: splat.center,
+            alpha: inverse_sigmoid(splat.alpha),
+            rotation: splat.rotation,
+            ..Self::default()
+        };
+        for axis in 0..3 {
+            serialized_splat.scale[axis] = splat.scale[axis].ln();
+        }
+        serialized_splat.color[0..3].copy_from_slice(&splat.color[0..3]);
+        for coefficient in 1..16 {
+            for channel in 0..3 {
+                serialized_splat.color[3 + channel * 15 + coefficient - 1] = splat.color[coefficient * 3 + channel];
+            }
+        }
+        serialized_splat
+    }
+}
+
+/// Indices of the vertex properties which hold the attributes of a [SerializedSplat]
+struct SplatPropertyMap {
+    center: [usize; 3],
+    normal: Option<[usize; 3]>,
+    color_dc: [usize; 3],
+    /// The `f_rest_*` properties, which hold the coefficients of each channel one after another
+    color_rest: Vec<usize>,
+    alpha: usize,
+    scale: [usize; 3],
+    rotation: [usize; 4],
+}
+
+impl SplatPropertyMap {
+    /// Gathers the attributes of a splat from the values of a vertex record
+    fn serialized_splat(&self, values: &[f64]) -> SerializedSplat {
+        let value = |index: usize| values[index] as f32;
+        let mut serialized_splat = SerializedSplat::default();
+        for axis in 0..3 {
+            serialized_splat.center[axis] = value(self.center[axis]);
+            serialized_splat.scale[axis] = value(self.scale[axis]);
+            serialized_splat.color[axis] = value(self.color_dc[axis]);
+            if let Some(normal) = self.normal {
+                serialized_splat.n[axis] = value(normal[axis]);
+            }
+        }
+        let coefficients_per_channel = self.color_rest.len() / 3;
+        for (index, property_index) in self.color_rest.iter().enumerate() {
+            let channel = index / coefficients_per_channel;
+            let coefficient = index % coefficients_per_channel;
+            serialized_splat.color[3 + channel * 15 + coefficient] = value(*property_index);
+        }
+        serialized_splat.alpha = value(self.alpha);
+        for component in 0..4 {
+            serialized_splat.rotation[component] = value(self.rotation[component]);
+        }
+        serialized_splat
+    }
+}
+
+/// Formats of the splat files [Scene::parse_file_header] reads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplatFormat {
+    /// A 3DGS PLY file or a compressed PLY file
+    Ply,
+    /// A headerless `.splat` file with 32 bytes per splat
+    Splat,
+    /// A `.splat` file with a spherical harmonics codebook, written by [Scene::save_codebook_file]
+    Codebook,
+}
+
+impl SplatFormat {
+    /// Recognizes the extensions `.ply`, `.splat` and `.shcb` of codebook files, ignoring their case
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "ply" => Self::Ply,
+            "splat" => Self::Splat,
+            "shcb" => Self::Codebook,
+            _ => return None,
+        })
+    }
+}
+
+/// How the splats are encoded in a file
+#[allow(clippy::large_enum_variant)]
+enum SplatFileLayout {
+    /// A 3DGS PLY file with one vertex per splat
+    Ply {
+        ply: PlyHeader,
+        vertex_element_index: usize,
+        property_map: SplatPropertyMap,
+    },
+    /// A PlayCanvas compressed PLY file with quantized splats
+    CompressedPly { ply: PlyHeader, layout: CompressedPlyLayout },
+    /// A headerless `.splat` file with 32 bytes per splat
+    Compact,
+    /// A `.splat` file with a spherical harmonics codebook
+    Codebook(CodebookFileLayout),
+}
+
+/// Header of a splat file, describing where the splat attributes are stored
+pub struct SplatFileHeader {
+    /// Number of splats in the file
+    pub splat_count: usize,
+    /// Degree of the spherical harmonics stored in the file, 0..=3
+    pub spherical_harmonics_degree: usize,
+    /// Splats rejected by any of these filters are skipped while loading
+    pub filters: Vec<SplatFilter>,
+    layout: SplatFileLayout,
+}
+
+impl SplatFileHeader {
+    /// The codebook of a codebook file
+    ///
+    /// Splats read from such a file carry their codebook index and the coefficients of its entry,
+    /// pass the codebook to [Scene::set_spherical_harmonics_codebook] to render through it.
+    pub fn spherical_harmonics_codebook(&self) -> Option<&SphericalHarmonicsCodebook> {
+        match &self.layout {
+            SplatFileLayout::Codebook(layout) => Some(&layout.codebook),
+            _ => None,
+        }
+    }
+
+    /// Reads and converts the splats in `splat_index_range` which pass the filters, without uploading them
+    ///
+    /// Continues where the previous call left off if the range follows the one it read,
+    /// so reading a file chunk by chunk does not seek, nor skip the records of ASCII files again.
+    pub(crate) fn read_splats(&self, file: &mut SplatFileReader, splat_index_range: std::ops::Range<usize>) -> Result<Vec<GpuSplat>, SceneError> {
+        let next_splat_index = file.next_splat_index.take();
+        let reader = &mut file.reader;
+        let splat_data = match &self.layout {
+            SplatFileLayout::Ply {
+                ply,
+                vertex_element_index,
+                property_map,
+            } => {
+                let vertex_element = &ply.elements[*vertex_element_index];
+                if ply.format == PlyFormat::Ascii {
+                    let skipped_record_count = match next_splat_index {
+                        Some(next_splat_index) if next_splat_index <= splat_index_range.start => splat_index_range.start - next_splat_index,
+                        _ => {
+                            reader.seek(SeekFrom::Start(ply.header_size))?;
+                            ply.element_line_offset(*vertex_element_index) + splat_index_range.start
+                        }
+                    };
+                    ply::skip_ascii_records(reader, skipped_record_count, &mut Vec::new())?;
+                } else if next_splat_index != Some(splat_index_range.start) {
+                    reader.seek(SeekFrom::Start(
+                        ply.element_offset(*vertex_element_index) + (splat_index_range.start * vertex_element.stride) as u64,
+                    ))?;
+                }
+                let mut values = Vec::with_capacity(vertex_element.properties.len());
+                let mut buffer = Vec::new();
+                let splat_data = splat_index_range
+                    .clone()
+                    .map(|splat_index| {
+                        let line = ply.record_line(*vertex_element_index, splat_index);
+                        ply::read_record(reader, ply.format, vertex_element, line, &mut values, &mut buffer)?;
+                        Ok(GpuSplat::from(&property_map.serialized_splat(&values)))
+                    })
+                    .collect::<Result<_, SceneError>>()?;
+                file.next_splat_index = Some(splat_index_range.end);
+                splat_data
+            }
+            // The records of a splat are spread over several elements, so the reader seeks anyway
+            SplatFileLayout::CompressedPly { ply, layout } => layout.read_splats(ply, reader, splat_index_range.clone())?,
+            SplatFileLayout::Compact => {
+                if next_splat_index != Some(splat_index_range.start) {
+                    reader.seek(SeekFrom::Start((splat_index_range.start * COMPACT_SPLAT_SIZE) as u64))?;
+                }
+                let mut buffer = vec![0; splat_index_range.len() * COMPACT_SPLAT_SIZE];
+                reader.read_exact(&mut buffer)?;
+                file.next_splat_index = Some(splat_index_range.end);
+                buffer
+                    .chunks_exact(COMPACT_SPLAT_SIZE)
+                    .map(|record| GpuSplat::from(&SerializedSplat::from_compact(record)))
+                    .collect()
+            }
+            SplatFileLayout::Codebook(layout) => layout.read_splats(reader, splat_index_range.clone())?,
+        };
+        if self.filters.is_empty() {
+            return Ok(splat_data);
+        }
+        Ok(splat_data.into_iter().filter(|splat| SplatFilter::all_keep(&self.filters, splat)).collect())
+    }
+}
+
+/// An open splat file, returned with its [SplatFileHeader] by [Scene::parse_file_header]
+///
+/// Keeps its buffer and position between the chunks read by [Scene::load_chunk] or a [SceneLoader](crate::loader::SceneLoader).
+pub struct SplatFileReader {
+    reader: BufReader<File>,
+    /// Index of the splat whose record the reader is at, if known
+    next_splat_index: Option<usize>,
+}
+
+/// GPU buffers of a [Scene] and the bind groups referencing them, sized for its capacity
+pub(crate) struct SceneBuffers {
     pub(crate) compute_bind_groups: [wgpu::BindGroup; 4],
     pub(crate) render_bind_group: wgpu::BindGroup,
     pub(crate) splat_buffer: wgpu::Buffer,
-    pub(crate) splat_positions: Vec<f32>,
-    pub splat_count: usize,
+    pub(crate) entry_buffer_a: wgpu::Buffer,
+    pub(crate) entry_buffer_b: wgpu::Buffer,
+    // Only referenced through the bind groups
+    #[allow(dead_code)]
+    pub(crate) radii_buffer: wgpu::Buffer,
+    pub(crate) codebook_buffer: wgpu::Buffer,
 }
 
-impl Scene {
-    /// Constructs a new [Scene] and allocates memory for it
-    pub fn new(device: &wgpu::Device, renderer: &Renderer, mut splat_count: usize) -> Self {
-        splat_count = splat_count.min(renderer.config.max_splat_count);
+impl SceneBuffers {
+    /// Allocates buffers for `capacity` splats
+    fn new(device: &wgpu::Device, renderer: &Renderer, capacity: usize, codebook: Option<&SphericalHarmonicsCodebook>) -> Self {
+        // Bindings can not be empty, so even an empty scene allocates room for one splat
+        let capacity = capacity.max(1);
         let splat_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: (splat_count * std::mem::size_of::<Splat>()) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            size: (capacity * splat_size(renderer.config.splat_layout)) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let entry_buffer_usage = if matches!(renderer.config.depth_sorting, DepthSorting::Cpu) {
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+        } else {
+            wgpu::BufferUsages::STORAGE
+        } | wgpu::BufferUsages::COPY_SRC;
+        let entry_buffer_a = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (capacity * std::mem::size_of::<(u32, u32)>()) as u64,
+            usage: entry_buffer_usage,
+            mapped_at_creation: false,
+        });
+        let entry_buffer_b = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (capacity * std::mem::size_of::<(u32, u32)>()) as u64,
+            usage: entry_buffer_usage,
+            mapped_at_creation: false,
+        });
+        // Holds the screen space radius of every splat, useful for densification
+        let radii_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Radii Buffer"),
+            size: (capacity * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
         let uniform_bind_group_entry = wgpu::BindGroupEntry {
@@ -58,12 +691,12 @@ impl Scene {
                 size: std::num::NonZeroU64::new(std::mem::size_of::<Uniforms>() as u64),
             }),
         };
-        let radii_bind_group_entry= wgpu::BindGroupEntry {
-            binding: 7, // Le binding du buffer des radii, correspondant à la définition dans le layout
+        let radii_bind_group_entry = wgpu::BindGroupEntry {
+            binding: 7,
             resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                buffer: &renderer.radii_buffer,
+                buffer: &radii_buffer,
                 offset: 0,
-                size: std::num::NonZeroU64::new((splat_count* std::mem::size_of::<f32>()) as u64), // Utilisez la totalité du buffer
+                size: None,
             }),
         };
         let sorting_bind_group_entry = wgpu::BindGroupEntry {
@@ -79,10 +712,9 @@ impl Scene {
             resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
                 buffer: &splat_buffer,
                 offset: 0,
-                size: std::num::NonZeroU64::new((splat_count * std::mem::size_of::<Splat>()) as u64),
+                size: None,
             }),
         };
-        
         let compute_bind_groups: [wgpu::BindGroup; 4] = (0..4)
             .map(|pass_index| {
                 device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -102,177 +734,1062 @@ impl Scene {
                         wgpu::BindGroupEntry {
                             binding: 3,
                             resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                                buffer: if pass_index & 1 == 0 {
-                                    &renderer.entry_buffer_a
-                                } else {
-                                    &renderer.entry_buffer_b
-                                },
+                                buffer: if pass_index & 1 == 0 { &entry_buffer_a } else { &entry_buffer_b },
                                 offset: 0,
-                                size: std::num::NonZeroU64::new((splat_count * std::mem::size_of::<(u32, u32)>()) as u64),
+                                size: None,
                             }),
                         },
                         wgpu::BindGroupEntry {
                             binding: 4,
                             resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                                buffer: if pass_index & 1 == 0 {
-                                    &renderer.entry_buffer_b
-                                } else {
-                                    &renderer.entry_buffer_a
-                                },
+                                buffer: if pass_index & 1 == 0 { &entry_buffer_b } else { &entry_buffer_a },
                                 offset: 0,
-                                size: std::num::NonZeroU64::new((splat_count * std::mem::size_of::<(u32, u32)>()) as u64),
+                                size: None,
                             }),
                         },
                         splats_bind_group_entry.clone(),
                         radii_bind_group_entry.clone(),
-
                     ],
                 })
             })
             .collect::<Vec<wgpu::BindGroup>>()
             .try_into()
             .unwrap();
-        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        let codebook_buffer = Self::create_codebook_buffer(device, codebook);
+        let render_bind_group = Self::create_render_bind_group(device, renderer, &entry_buffer_a, &entry_buffer_b, &splat_buffer, &codebook_buffer);
+        Self {
+            compute_bind_groups,
+            render_bind_group,
+            splat_buffer,
+            entry_buffer_a,
+            entry_buffer_b,
+            radii_buffer,
+            codebook_buffer,
+        }
+    }
+
+    /// Holds the codebook entries, or a single zero entry without codebook as bindings can not be empty
+    fn create_codebook_buffer(device: &wgpu::Device, codebook: Option<&SphericalHarmonicsCodebook>) -> wgpu::Buffer {
+        let entries = match codebook {
+            Some(codebook) => codebook.entries.clone(),
+            None => vec![[0.0; CODEBOOK_ENTRY_SIZE]],
+        };
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: transmute_slice(&entries),
+            usage: wgpu::BufferUsages::STORAGE,
+        })
+    }
+
+    fn create_render_bind_group(
+        device: &wgpu::Device,
+        renderer: &Renderer,
+        entry_buffer_a: &wgpu::Buffer,
+        entry_buffer_b: &wgpu::Buffer,
+        splat_buffer: &wgpu::Buffer,
+        codebook_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &renderer.render_bind_group_layout,
             entries: &[
-                uniform_bind_group_entry,
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &renderer.uniform_buffer,
+                        offset: 0,
+                        size: std::num::NonZeroU64::new(std::mem::size_of::<Uniforms>() as u64),
+                    }),
+                },
                 wgpu::BindGroupEntry {
                     binding: 5,
                     resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
                         buffer: if matches!(renderer.config.depth_sorting, DepthSorting::Cpu) || renderer.radix_digit_places & 1 == 0 {
-                            &renderer.entry_buffer_a
+                            entry_buffer_a
                         } else {
-                            &renderer.entry_buffer_b
+                            entry_buffer_b
                         },
                         offset: 0,
-                        size: std::num::NonZeroU64::new((splat_count * std::mem::size_of::<(u32, u32)>()) as u64),
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: splat_buffer,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: codebook_buffer,
+                        offset: 0,
+                        size: None,
                     }),
                 },
-                splats_bind_group_entry,
-                
-
             ],
-        });
-        Self {
-            compute_bind_groups,
-            render_bind_group,
-            splat_buffer,
+        })
+    }
+
+    /// Replaces the codebook buffer, keeping the splats
+    fn set_codebook(&mut self, device: &wgpu::Device, renderer: &Renderer, codebook: Option<&SphericalHarmonicsCodebook>) {
+        self.codebook_buffer = Self::create_codebook_buffer(device, codebook);
+        self.render_bind_group = Self::create_render_bind_group(
+            device,
+            renderer,
+            &self.entry_buffer_a,
+            &self.entry_buffer_b,
+            &self.splat_buffer,
+            &self.codebook_buffer,
+        );
+    }
+}
+
+/// A point cloud of splats
+///
+/// The GPU buffers are allocated for a capacity, which grows when splats are appended
+/// up to the `max_splat_count` of the [Renderer].
+pub struct Scene {
+    pub(crate) buffers: SceneBuffers,
+    /// Copy of the splat centers, only kept for sorting on the CPU
+    pub(crate) splat_positions: Option<Vec<f32>>,
+    /// Copy of the attributes [Scene::statistics] is computed from
+    pub(crate) splat_summaries: Vec<SplatSummary>,
+    spatial_index: SpatialIndex,
+    capacity: usize,
+    pub splat_count: usize,
+    /// Number of splats at the start of the buffer which have been uploaded, only these are rendered
+    pub loaded_splat_count: usize,
+    /// Degree of the spherical harmonics of the loaded splats, 0..=3
+    pub spherical_harmonics_degree: usize,
+    /// How the splats are stored in the GPU buffer, copied from the renderer configuration
+    splat_layout: SplatLayout,
+    spherical_harmonics_codebook: Option<SphericalHarmonicsCodebook>,
+    /// Splats rejected by any of these filters are left out of saved files
+    pub export_filters: Vec<SplatFilter>,
+}
+
+impl Scene {
+    /// Constructs a new [Scene] and allocates memory for it
+    ///
+    /// Fails if `splat_count` exceeds the `max_splat_count` of the renderer
+    pub fn new(device: &wgpu::Device, renderer: &Renderer, splat_count: usize) -> Result<Self, SceneError> {
+        if splat_count > renderer.config.max_splat_count {
+            return Err(SceneError::SplatCountOverflow {
+                splat_count,
+                max_splat_count: renderer.config.max_splat_count,
+            });
+        }
+        Ok(Self {
+            buffers: SceneBuffers::new(device, renderer, splat_count, None),
             splat_positions: if matches!(renderer.config.depth_sorting, DepthSorting::Cpu) {
-                vec![0.0; splat_count * 3]
+                Some(vec![0.0; splat_count * 3])
             } else {
-                Vec::new()
+                None
             },
+            splat_summaries: vec![SplatSummary::default(); splat_count],
+            spatial_index: SpatialIndex::new(),
+            capacity: splat_count,
             splat_count,
-        }
+            loaded_splat_count: 0,
+            spherical_harmonics_degree: 3,
+            splat_layout: renderer.config.splat_layout,
+            spherical_harmonics_codebook: None,
+            export_filters: Vec::new(),
+        })
     }
 
-    /// Loads the development test scene
-    pub fn new_dev_test(device: &wgpu::Device, renderer: &Renderer, queue: &wgpu::Queue) -> Self {
-        let mut scene = Self::new(device, renderer, 3);
-        let splat_index_range = 0..3;
-        let mut splat_data = vec![[0.0; 60]; splat_index_range.len()];
-        for index in 0..splat_data.len() {
-            splat_data[index][0] = 1.0;
-            splat_data[index][1] = 0.0;
-            splat_data[index][2] = 0.0;
-            splat_data[index][3] = 0.0;
-            splat_data[index][4] = if index == 0 { 1.0 } else { 0.0 };
-            splat_data[index][5] = if index == 1 { 1.0 } else { 0.0 };
-            splat_data[index][6] = if index == 2 { 1.0 } else { 0.0 };
-            splat_data[index][8] = 0.2;
-            splat_data[index][9] = 0.1;
-            splat_data[index][10] = 0.05;
-            splat_data[index][11] = 1.0;
-            splat_data[index][12] = if index == 0 { 1.0 } else { 0.0 };
-            splat_data[index][13] = if index == 1 { 1.0 } else { 0.0 };
-            splat_data[index][14] = if index == 2 { 1.0 } else { 0.0 };
-        }
-        queue.write_buffer(
-            &scene.splat_buffer,
-            (splat_index_range.start * std::mem::size_of::<Splat>()) as u64,
-            transmute_slice(&splat_data),
+    /// Size of a splat in the GPU buffer
+    pub(crate) fn splat_size(&self) -> usize {
+        splat_size(self.splat_layout)
+    }
+
+    /// Index over the bounding boxes of the loaded splats
+    pub fn spatial_index(&self) -> &SpatialIndex {
+        &self.spatial_index
+    }
+
+    /// Number of splats the GPU buffers have room for without reallocating
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Makes room for at least `additional` more splats
+    ///
+    /// Grows the capacity geometrically, reallocating the GPU buffers and copying the existing splats over.
+    /// Fails if the scene would exceed the `max_splat_count` of the renderer.
+    pub fn reserve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer, additional: usize) -> Result<(), SceneError> {
+        let capacity = self.grown_capacity(renderer, additional)?;
+        if capacity == self.capacity {
+            return Ok(());
+        }
+        let buffers = SceneBuffers::new(device, renderer, capacity, self.spherical_harmonics_codebook.as_ref());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &self.buffers.splat_buffer,
+            0,
+            &buffers.splat_buffer,
+            0,
+            (self.splat_count * self.splat_size()) as u64,
         );
-        if !scene.splat_positions.is_empty() {
-            for index in 0..splat_data.len() {
-                let splat = &splat_data[index];
-                scene.splat_positions[index * 3 + 0] = splat[4];
-                scene.splat_positions[index * 3 + 1] = splat[5];
-                scene.splat_positions[index * 3 + 2] = splat[6];
+        queue.submit(Some(encoder.finish()));
+        self.buffers = buffers;
+        if let Some(splat_positions) = &mut self.splat_positions {
+            splat_positions.resize(capacity * 3, 0.0);
+        }
+        self.splat_summaries.resize(capacity, SplatSummary::default());
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Capacity for `additional` more splats, at least doubling the current one if it has to grow
+    fn grown_capacity(&self, renderer: &Renderer, additional: usize) -> Result<usize, SceneError> {
+        let required_capacity = self.splat_count + additional;
+        if required_capacity <= self.capacity {
+            return Ok(self.capacity);
+        }
+        if required_capacity > renderer.config.max_splat_count {
+            return Err(SceneError::SplatCountOverflow {
+                splat_count: required_capacity,
+                max_splat_count: renderer.config.max_splat_count,
+            });
+        }
+        Ok(required_capacity.max(self.capacity * 2).min(renderer.config.max_splat_count))
+    }
+
+    /// Adds splats at the end of the scene, growing its capacity if needed
+    ///
+    /// If the scene has a codebook, the splats have to carry indices into it.
+    pub fn append_splats(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer, splat_data: &[GpuSplat]) -> Result<(), SceneError> {
+        self.reserve(device, queue, renderer, splat_data.len())?;
+        let splat_index_offset = self.splat_count;
+        self.splat_count += splat_data.len();
+        self.upload_splats(queue, splat_index_offset, splat_data);
+        Ok(())
+    }
+
+    /// Removes the splats at the given indices, keeping the order of the remaining splats
+    ///
+    /// The remaining splats are compacted on the GPU, the capacity stays the same.
+    /// Fails without removing any splat if an index is not less than `splat_count`.
+    pub fn remove_splats(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        splat_indices: &[usize],
+    ) -> Result<(), SceneError> {
+        let mut removed = vec![false; self.splat_count];
+        for splat_index in splat_indices {
+            if *splat_index >= self.splat_count {
+                return Err(SceneError::SplatIndexOutOfBounds {
+                    splat_index: *splat_index,
+                    splat_count: self.splat_count,
+                });
             }
+            removed[*splat_index] = true;
         }
-        scene
+        let buffers = SceneBuffers::new(device, renderer, self.capacity, self.spherical_harmonics_codebook.as_ref());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut kept_splat_count = 0;
+        let mut kept_loaded_splat_count = 0;
+        let mut splat_index = 0;
+        while splat_index < self.splat_count {
+            if removed[splat_index] {
+                splat_index += 1;
+                continue;
+            }
+            let run_start = splat_index;
+            while splat_index < self.splat_count && !removed[splat_index] {
+                splat_index += 1;
+            }
+            encoder.copy_buffer_to_buffer(
+                &self.buffers.splat_buffer,
+                (run_start * self.splat_size()) as u64,
+                &buffers.splat_buffer,
+                (kept_splat_count * self.splat_size()) as u64,
+                ((splat_index - run_start) * self.splat_size()) as u64,
+            );
+            if let Some(splat_positions) = &mut self.splat_positions {
+                splat_positions.copy_within(run_start * 3..splat_index * 3, kept_splat_count * 3);
+            }
+            self.splat_summaries.copy_within(run_start..splat_index, kept_splat_count);
+            kept_loaded_splat_count += splat_index.min(self.loaded_splat_count).saturating_sub(run_start);
+            kept_splat_count += splat_index - run_start;
+        }
+        queue.submit(Some(encoder.finish()));
+        self.buffers = buffers;
+        self.spatial_index.remove_splats(splat_indices);
+        self.splat_count = kept_splat_count;
+        self.loaded_splat_count = kept_loaded_splat_count;
+        Ok(())
     }
 
-    /// Parses the header of a splat file
+    /// Inserts splats at the given ascending indices of the grown scene, shifting the following splats up
     ///
-    /// Returns the header length in bytes, the number of splats in the file and the file handle
-    pub fn parse_file_header(file: File) -> (u16, usize, File) {
-        let mut splat_count: usize = 0;
+    /// Inverts [Scene::remove_splats] given the removed indices and splats, including the splats which are not loaded yet.
+    pub(crate) fn insert_splats(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        splat_indices: &[usize],
+        splat_data: &[GpuSplat],
+    ) -> Result<(), SceneError> {
+        let splat_count = self.splat_count + splat_indices.len();
+        if let Some(splat_index) = splat_indices.iter().find(|splat_index| **splat_index >= splat_count) {
+            return Err(SceneError::SplatIndexOutOfBounds {
+                splat_index: *splat_index,
+                splat_count,
+            });
+        }
+        let capacity = self.grown_capacity(renderer, splat_indices.len())?;
+        // The runs of kept splats as their old and new first index and their length
+        let mut moves = Vec::new();
+        let mut old_splat_index = 0;
+        let mut inserted_splat_count = 0;
+        let mut loaded_splat_count = None;
+        for (splat_index, positions) in index_runs(splat_indices) {
+            let length = splat_index - (old_splat_index + inserted_splat_count);
+            moves.push((old_splat_index, old_splat_index + inserted_splat_count, length));
+            if loaded_splat_count.is_none() && old_splat_index + length > self.loaded_splat_count {
+                loaded_splat_count = Some(self.loaded_splat_count + inserted_splat_count);
+            }
+            old_splat_index += length;
+            inserted_splat_count += positions.len();
+        }
+        moves.push((
+            old_splat_index,
+            old_splat_index + inserted_splat_count,
+            self.splat_count - old_splat_index,
+        ));
+        let loaded_splat_count = loaded_splat_count.unwrap_or(self.loaded_splat_count + inserted_splat_count);
+
+        let buffers = SceneBuffers::new(device, renderer, capacity, self.spherical_harmonics_codebook.as_ref());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for (old_splat_index, new_splat_index, length) in &moves {
+            encoder.copy_buffer_to_buffer(
+                &self.buffers.splat_buffer,
+                (old_splat_index * self.splat_size()) as u64,
+                &buffers.splat_buffer,
+                (new_splat_index * self.splat_size()) as u64,
+                (length * self.splat_size()) as u64,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        if let Some(splat_positions) = &mut self.splat_positions {
+            splat_positions.resize(capacity * 3, 0.0);
+        }
+        self.splat_summaries.resize(capacity, SplatSummary::default());
+        // Back to front, as the runs move up
+        for (old_splat_index, new_splat_index, length) in moves.into_iter().rev() {
+            if let Some(splat_positions) = &mut self.splat_positions {
+                splat_positions.copy_within(old_splat_index * 3..(old_splat_index + length) * 3, new_splat_index * 3);
+            }
+            self.splat_summaries
+                .copy_within(old_splat_index..old_splat_index + length, new_splat_index);
+        }
+        self.buffers = buffers;
+        self.capacity = capacity;
+        self.splat_count = splat_count;
+        self.spatial_index.insert_gaps(splat_indices);
+        for (splat_index, positions) in index_runs(splat_indices) {
+            self.upload_splats(queue, splat_index, &splat_data[positions]);
+        }
+        self.loaded_splat_count = loaded_splat_count;
+        Ok(())
+    }
+
+    /// Loads the development test scene
+    pub fn new_dev_test(device: &wgpu::Device, renderer: &Renderer, queue: &wgpu::Queue) -> Result<Self, SceneError> {
+        let mut scene = Self::new(device, renderer, 3)?;
+        scene.spherical_harmonics_degree = 0;
+        let splat_data: Vec<GpuSplat> = (0..3)
+            .map(|index| {
+                let mut splat = GpuSplat {
+                    rotation: [1.0, 0.0, 0.0, 0.0],
+                    scale: [0.2, 0.1, 0.05],
+                    alpha: 1.0,
+                    ..GpuSplat::default()
+                };
+                splat.center[index] = 1.0;
+                splat.color[index] = 1.0;
+                splat
+            })
+            .collect();
+        scene.upload_splats(queue, 0, &splat_data);
+        Ok(scene)
+    }
+
+    /// Opens a splat file and parses its header, in the format given by the extension of `path`
+    ///
+    /// Fails with [SceneError::UnknownFormat] unless [SplatFormat::from_path] recognizes the extension.
+    pub fn open_file<P: AsRef<Path>>(path: P) -> Result<(SplatFileHeader, SplatFileReader), SceneError> {
+        let path = path.as_ref();
+        let format = SplatFormat::from_path(path).ok_or_else(|| SceneError::UnknownFormat(path.to_path_buf()))?;
+        Self::parse_file_header(File::open(path)?, format)
+    }
+
+    /// Parses the header of a splat file in the given format
+    ///
+    /// PLY files are 3DGS PLY files or compressed PLY files, which are told apart by their properties.
+    /// Returns the layout of the splats in the file and the reader to load them with
+    pub fn parse_file_header(file: File, format: SplatFormat) -> Result<(SplatFileHeader, SplatFileReader), SceneError> {
+        let file_size = file.metadata()?.len();
+        if file_size == 0 {
+            return Err(SceneError::MalformedHeader("Splat file is empty".into()));
+        }
         let mut reader = BufReader::new(file);
-        let mut line = String::new();
-        loop {
-            reader.read_line(&mut line).unwrap();
-            if line == "end_header\n" {
-                break;
+        match format {
+            SplatFormat::Codebook => {
+                let layout = CodebookFileLayout::parse(&mut reader, file_size)?;
+                let header = SplatFileHeader {
+                    splat_count: layout.splat_count,
+                    spherical_harmonics_degree: layout.codebook.spherical_harmonics_degree,
+                    filters: Vec::new(),
+                    layout: SplatFileLayout::Codebook(layout),
+                };
+                return Ok((header, SplatFileReader { reader, next_splat_index: None }));
             }
-            if line.starts_with("element vertex ") {
-                splat_count = line[15..line.len() - 1].parse().unwrap();
+            SplatFormat::Splat => {
+                if file_size % COMPACT_SPLAT_SIZE as u64 != 0 {
+                    return Err(SceneError::MalformedHeader(format!(
+                        "Size of a .splat file must be a multiple of {} bytes",
+                        COMPACT_SPLAT_SIZE
+                    )));
+                }
+                let header = SplatFileHeader {
+                    splat_count: (file_size / COMPACT_SPLAT_SIZE as u64) as usize,
+                    spherical_harmonics_degree: 0,
+                    filters: Vec::new(),
+                    layout: SplatFileLayout::Compact,
+                };
+                return Ok((header, SplatFileReader { reader, next_splat_index: None }));
             }
-            line.clear();
+            SplatFormat::Ply => {
+                if !reader.fill_buf()?.starts_with(b"ply") {
+                    return Err(SceneError::MalformedHeader("PLY file does not start with ply".into()));
+                }
+            }
+        }
+        let ply = PlyHeader::parse(&mut reader)?;
+        let vertex_element_index = ply
+            .element_index("vertex")
+            .ok_or_else(|| SceneError::MalformedHeader("Splat file has no vertex element".into()))?;
+        if ply.format != PlyFormat::Ascii && ply.element_offset(ply.elements.len()) > file_size {
+            return Err(SceneError::Truncated);
+        }
+        if let Some(layout) = CompressedPlyLayout::from_header(&ply, vertex_element_index)? {
+            let header = SplatFileHeader {
+                splat_count: ply.elements[vertex_element_index].count,
+                spherical_harmonics_degree: layout.spherical_harmonics_degree,
+                filters: Vec::new(),
+                layout: SplatFileLayout::CompressedPly { ply, layout },
+            };
+            return Ok((header, SplatFileReader { reader, next_splat_index: None }));
         }
-        let file_header_size = reader.stream_position().unwrap() as u16;
-        (file_header_size, splat_count, reader.into_inner())
+        let vertex_element = &ply.elements[vertex_element_index];
+        let property = |name: &str| {
+            vertex_element
+                .property_index(name)
+                .ok_or_else(|| SceneError::MalformedHeader(format!("Splat file is missing the vertex property {}", name)))
+        };
+        let color_rest_count = color_rest_count(vertex_element);
+        let spherical_harmonics_degree = spherical_harmonics_degree(color_rest_count)?;
+        let color_rest = (0..color_rest_count)
+            .map(|index| property(&format!("f_rest_{}", index)))
+            .collect::<Result<_, _>>()?;
+        let normal = if vertex_element.property_index("nx").is_some() {
+            Some([property("nx")?, property("ny")?, property("nz")?])
+        } else {
+            None
+        };
+        let property_map = SplatPropertyMap {
+            center: [property("x")?, property("y")?, property("z")?],
+            normal,
+            color_dc: [property("f_dc_0")?, property("f_dc_1")?, property("f_dc_2")?],
+            color_rest,
+            alpha: property("opacity")?,
+            scale: [property("scale_0")?, property("scale_1")?, property("scale_2")?],
+            rotation: [property("rot_0")?, property("rot_1")?, property("rot_2")?, property("rot_3")?],
+        };
+        let header = SplatFileHeader {
+            splat_count: vertex_element.count,
+            spherical_harmonics_degree,
+            filters: Vec::new(),
+            layout: SplatFileLayout::Ply {
+                ply,
+                vertex_element_index,
+                property_map,
+            },
+        };
+        Ok((header, SplatFileReader { reader, next_splat_index: None }))
     }
 
     /// Loads a chunk of the splat file or the entire file
-    pub fn load_chunk(&mut self, queue: &wgpu::Queue, file: &mut File, file_header_size: u16, mut splat_index_range: std::ops::Range<usize>) {
-        splat_index_range.end = splat_index_range.end.min(self.splat_count);
-        let mut splat_data = vec![[0.0; 60]; splat_index_range.len()];
-        let mut serialized_splats = vec![SerializedSplat::default(); splat_index_range.len()];
-        file.seek(SeekFrom::Start(
-            file_header_size as u64 + (splat_index_range.start * std::mem::size_of::<SerializedSplat>()) as u64,
-        ))
-        .unwrap();
-        file.read_exact(transmute_slice_mut::<_, u8>(&mut serialized_splats)).unwrap();
-        for index in 0..splat_data.len() {
-            let serialized_splat = &serialized_splats[index];
-            let rotor = <[f32; 4]>::from(Rotor::from(serialized_splat.rotation).signum());
-            splat_data[index][0] = rotor[0];
-            splat_data[index][1] = rotor[1];
-            splat_data[index][2] = rotor[2];
-            splat_data[index][3] = rotor[3];
-            splat_data[index][4] = serialized_splat.center[0];
-            splat_data[index][5] = serialized_splat.center[1];
-            splat_data[index][6] = serialized_splat.center[2];
-            let average = (serialized_splat.scale[0] + serialized_splat.scale[1] + serialized_splat.scale[2]) / 3.0;
-            splat_data[index][8] = serialized_splat.scale[0]
-                .max(average - MAX_SIZE_VARIANCE)
-                .min(average + MAX_SIZE_VARIANCE)
-                .exp();
-            splat_data[index][9] = serialized_splat.scale[1]
-                .max(average - MAX_SIZE_VARIANCE)
-                .min(average + MAX_SIZE_VARIANCE)
-                .exp();
-            splat_data[index][10] = serialized_splat.scale[2]
-                .max(average - MAX_SIZE_VARIANCE)
-                .min(average + MAX_SIZE_VARIANCE)
-                .exp();
-            splat_data[index][11] = 1.0 / (1.0 + (-serialized_splat.alpha).exp());
-            splat_data[index][12..].copy_from_slice(&serialized_splat.color[0..3 * 16]);
-        }
-        queue.write_buffer(
-            &self.splat_buffer,
-            (splat_index_range.start * std::mem::size_of::<Splat>()) as u64,
-            transmute_slice(&splat_data),
+    ///
+    /// The splats are packed to half precision on upload if the renderer uses half precision splats.
+    /// If the header has filters, the kept splats are packed behind the loaded ones and the `splat_count`
+    /// of the scene shrinks by the skipped splats, so the chunks have to be loaded in order.
+    /// Fails if the chunk reaches beyond the `splat_count` of the scene
+    pub fn load_chunk(
+        &mut self,
+        queue: &wgpu::Queue,
+        file: &mut SplatFileReader,
+        header: &SplatFileHeader,
+        mut splat_index_range: std::ops::Range<usize>,
+    ) -> Result<(), SceneError> {
+        splat_index_range.end = splat_index_range.end.min(header.splat_count);
+        self.check_file_chunk(&splat_index_range, !header.filters.is_empty())?;
+        let splat_data = header.read_splats(file, splat_index_range.clone())?;
+        self.upload_file_chunk(
+            queue,
+            splat_index_range,
+            &splat_data,
+            !header.filters.is_empty(),
+            header.spherical_harmonics_degree,
         );
-        if !self.splat_positions.is_empty() {
-            for index in 0..splat_data.len() {
-                let splat = &splat_data[index];
-                self.splat_positions[index * 3 + 0] = splat[4];
-                self.splat_positions[index * 3 + 1] = splat[5];
-                self.splat_positions[index * 3 + 2] = splat[6];
+        Ok(())
+    }
+
+    /// Fails if the splats of a file chunk do not fit into the scene, see [Scene::load_chunk]
+    pub(crate) fn check_file_chunk(&self, splat_index_range: &std::ops::Range<usize>, filtered: bool) -> Result<(), SceneError> {
+        let splat_index_end = if filtered {
+            self.loaded_splat_count + splat_index_range.len()
+        } else {
+            splat_index_range.end
+        };
+        if splat_index_end > self.splat_count {
+            return Err(SceneError::SplatCountOverflow {
+                splat_count: splat_index_end,
+                max_splat_count: self.splat_count,
+            });
+        }
+        Ok(())
+    }
+
+    /// Uploads the splats read from a file chunk, see [Scene::load_chunk]
+    pub(crate) fn upload_file_chunk(
+        &mut self,
+        queue: &wgpu::Queue,
+        splat_index_range: std::ops::Range<usize>,
+        splat_data: &[GpuSplat],
+        filtered: bool,
+        spherical_harmonics_degree: usize,
+    ) {
+        self.spherical_harmonics_degree = spherical_harmonics_degree;
+        if filtered {
+            self.splat_count -= splat_index_range.len() - splat_data.len();
+            self.upload_splats(queue, self.loaded_splat_count, splat_data);
+        } else {
+            self.upload_splats(queue, splat_index_range.start, splat_data);
+        }
+    }
+
+    /// Removes the loaded splats rejected by any of the filters, compacting the buffer
+    ///
+    /// Returns the number of removed splats.
+    pub fn filter(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer, filters: &[SplatFilter]) -> Result<usize, SceneError> {
+        let splat_data = self.download_splats(device, queue, 0..self.loaded_splat_count)?;
+        let removed_splat_indices: Vec<usize> = splat_data
+            .iter()
+            .enumerate()
+            .filter(|(_, splat)| !SplatFilter::all_keep(filters, splat))
+            .map(|(splat_index, _)| splat_index)
+            .collect();
+        self.remove_splats(device, queue, renderer, &removed_splat_indices)?;
+        Ok(removed_splat_indices.len())
+    }
+
+    /// Reads the splats which are written to files back from the GPU, leaving out those rejected by the export filters
+    ///
+    /// Only the loaded splats are written, those still to be loaded are zeroed.
+    fn download_exported_splats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<GpuSplat>, SceneError> {
+        let mut splat_data = self.download_splats(device, queue, 0..self.loaded_splat_count)?;
+        if !self.export_filters.is_empty() {
+            splat_data.retain(|splat| SplatFilter::all_keep(&self.export_filters, splat));
+        }
+        Ok(splat_data)
+    }
+
+    /// Reads the splats in `splat_index_range` back from the GPU
+    pub(crate) fn download_splats(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        splat_index_range: std::ops::Range<usize>,
+    ) -> Result<Vec<GpuSplat>, SceneError> {
+        let mut splat_data: Vec<GpuSplat> = Vec::with_capacity(splat_index_range.len());
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (DOWNLOAD_CHUNK_SIZE.min(splat_index_range.len()).max(1) * self.splat_size()) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        for chunk_start in splat_index_range.clone().step_by(DOWNLOAD_CHUNK_SIZE) {
+            let chunk_size = ((splat_index_range.end - chunk_start).min(DOWNLOAD_CHUNK_SIZE) * self.splat_size()) as u64;
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            encoder.copy_buffer_to_buffer(
+                &self.buffers.splat_buffer,
+                (chunk_start * self.splat_size()) as u64,
+                &staging_buffer,
+                0,
+                chunk_size,
+            );
+            queue.submit(Some(encoder.finish()));
+            self.read_staging_buffer(device, &staging_buffer, chunk_size, &mut splat_data)?;
+        }
+        Ok(splat_data)
+    }
+
+    /// Reads the splats at the given indices back from the GPU, in the order of the indices
+    pub(crate) fn download_splat_indices(&self, device: &wgpu::Device, queue: &wgpu::Queue, splat_indices: &[usize]) -> Result<Vec<GpuSplat>, SceneError> {
+        let mut splat_data: Vec<GpuSplat> = Vec::with_capacity(splat_indices.len());
+        if splat_indices.is_empty() {
+            return Ok(splat_data);
+        }
+        let size = (splat_indices.len() * self.splat_size()) as u64;
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        // Consecutive indices are copied at once
+        for (splat_index, positions) in index_runs(splat_indices) {
+            encoder.copy_buffer_to_buffer(
+                &self.buffers.splat_buffer,
+                (splat_index * self.splat_size()) as u64,
+                &staging_buffer,
+                (positions.start * self.splat_size()) as u64,
+                (positions.len() * self.splat_size()) as u64,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        self.read_staging_buffer(device, &staging_buffer, size, &mut splat_data)?;
+        Ok(splat_data)
+    }
+
+    /// Maps the first `size` bytes of a staging buffer and appends the splats in it
+    ///
+    /// The splats of a scene with a codebook get the coefficients of their entries, which is what they are rendered with.
+    fn read_staging_buffer(&self, device: &wgpu::Device, staging_buffer: &wgpu::Buffer, size: u64, splat_data: &mut Vec<GpuSplat>) -> Result<(), SceneError> {
+        let buffer_slice = staging_buffer.slice(0..size);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .unwrap()
+            .map_err(|error| SceneError::Io(std::io::Error::other(error)))?;
+        let mapped_range = buffer_slice.get_mapped_range();
+        let splat_index_offset = splat_data.len();
+        match self.splat_layout {
+            SplatLayout::Full => splat_data.extend_from_slice(transmute_slice::<u8, GpuSplat>(&mapped_range)),
+            SplatLayout::HalfPrecision => splat_data.extend(transmute_slice::<u8, PackedGpuSplat>(&mapped_range).iter().map(GpuSplat::from)),
+            SplatLayout::Codebook => splat_data.extend(transmute_slice::<u8, CodebookGpuSplat>(&mapped_range).iter().map(GpuSplat::from)),
+        }
+        drop(mapped_range);
+        staging_buffer.unmap();
+        if let Some(codebook) = &self.spherical_harmonics_codebook {
+            for splat in &mut splat_data[splat_index_offset..] {
+                codebook.decode(splat);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the splats to a standard 3DGS PLY file
+    ///
+    /// Inverts the transformations applied by [Scene::load_chunk],
+    /// so loading the written file reproduces this scene.
+    pub fn save_ply<P: AsRef<Path>>(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: P) -> Result<(), SceneError> {
+        let splat_data = self.download_exported_splats(device, queue)?;
+        let property_names = splat_property_names(self.spherical_harmonics_degree);
+        let properties: Vec<(&String, PlyScalarType)> = property_names.iter().map(|name| (name, PlyScalarType::Float32)).collect();
+        let header = PlyHeader {
+            format: PlyFormat::BinaryLittleEndian,
+            elements: vec![PlyElement::new("vertex", splat_data.len(), &properties)],
+            header_size: 0,
+            header_line_count: 0,
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        header.write(&mut writer)?;
+        let mut values = Vec::with_capacity(property_names.len());
+        let mut buffer = Vec::new();
+        for splat in &splat_data {
+            values.clear();
+            SerializedSplat::from(splat).property_values(self.spherical_harmonics_degree, &mut values);
+            ply::write_record(&mut writer, header.format, &header.elements[0], &values, &mut buffer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the splats to a PlayCanvas compressed PLY file
+    ///
+    /// Positions, scales and colors are quantized relative to the bounds of chunks of 256 splats,
+    /// which makes the file about four times smaller than [Scene::save_ply].
+    pub fn save_compressed_ply<P: AsRef<Path>>(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: P) -> Result<(), SceneError> {
+        let splat_data = self.download_exported_splats(device, queue)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        compressed_ply::write(&mut writer, &splat_data, self.spherical_harmonics_degree)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the splats to a compact `.splat` file
+    ///
+    /// The format only stores the base color, so higher order spherical harmonics are dropped
+    /// and colors, opacities and rotations are quantized to 8 bits.
+    pub fn save_splat<P: AsRef<Path>>(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: P) -> Result<(), SceneError> {
+        let splat_data = self.download_exported_splats(device, queue)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        for splat in &splat_data {
+            writer.write_all(&SerializedSplat::compact_record(splat))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The codebook the higher order spherical harmonics are rendered through, if any
+    pub fn spherical_harmonics_codebook(&self) -> Option<&SphericalHarmonicsCodebook> {
+        self.spherical_harmonics_codebook.as_ref()
+    }
+
+    /// Renders the higher order spherical harmonics through `codebook`, indexed by [GpuSplat::codebook_index]
+    ///
+    /// Passing `None` renders the coefficients stored in the splats again, which [SplatLayout::Codebook] does not keep.
+    pub fn set_spherical_harmonics_codebook(&mut self, device: &wgpu::Device, renderer: &Renderer, codebook: Option<SphericalHarmonicsCodebook>) {
+        self.buffers.set_codebook(device, renderer, codebook.as_ref());
+        self.spherical_harmonics_codebook = codebook;
+    }
+
+    /// Vector quantizes the higher order spherical harmonics of the loaded splats into a codebook of `codebook_size` entries
+    ///
+    /// Trains the codebook with `iterations` steps of k-means on the CPU, assigns every splat its nearest entry
+    /// and renders them through the codebook from then on.
+    /// With [SplatLayout::Codebook] the scene only keeps the coefficients of its current codebook, if any.
+    pub fn compress_spherical_harmonics(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &Renderer,
+        codebook_size: usize,
+        iterations: usize,
+    ) -> Result<(), SceneError> {
+        let mut splat_data = self.download_splats(device, queue, 0..self.loaded_splat_count)?;
+        let codebook = SphericalHarmonicsCodebook::train(&splat_data, self.spherical_harmonics_degree, codebook_size, iterations);
+        codebook.quantize(&mut splat_data);
+        self.upload_splats(queue, 0, &splat_data);
+        self.set_spherical_harmonics_codebook(device, renderer, Some(codebook));
+        Ok(())
+    }
+
+    /// Writes the splats and their codebook to a codebook file
+    ///
+    /// Stores the attributes of each splat as f32 and its 16 bit codebook index, which makes the file
+    /// about four times smaller than [Scene::save_ply] with third degree spherical harmonics.
+    /// Fails with [SceneError::MissingCodebook] unless [Scene::compress_spherical_harmonics] was called.
+    pub fn save_codebook_file<P: AsRef<Path>>(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: P) -> Result<(), SceneError> {
+        let codebook = self.spherical_harmonics_codebook.as_ref().ok_or(SceneError::MissingCodebook)?;
+        let splat_data = self.download_exported_splats(device, queue)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        codebook::write(&mut writer, &splat_data, codebook)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Moves, rotates and scales the loaded splats, rotating their spherical harmonics along
+    ///
+    /// Use [Similarity::transform_splats] to transform splats on the CPU before uploading them.
+    /// Files written afterwards, e.g. by [Scene::save_ply], contain the transformed splats.
+    pub fn transform(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, renderer: &Renderer, similarity: &Similarity) -> Result<(), SceneError> {
+        let mut splat_data = self.download_splats(device, queue, 0..self.loaded_splat_count)?;
+        similarity.transform_splats(&mut splat_data);
+        self.upload_splats(queue, 0, &splat_data);
+        if let Some(mut codebook) = self.spherical_harmonics_codebook.take() {
+            let spherical_harmonics_rotation = SphericalHarmonicsRotation::new(similarity.rotation);
+            for entry in &mut codebook.entries {
+                spherical_harmonics_rotation.apply(entry);
+            }
+            self.set_spherical_harmonics_codebook(device, renderer, Some(codebook));
+        }
+        Ok(())
+    }
+
+    /// Writes splats to the GPU, starting at `splat_index_offset`
+    pub(crate) fn upload_splats(&mut self, queue: &wgpu::Queue, splat_index_offset: usize, splat_data: &[GpuSplat]) {
+        let splat_buffer_offset = (splat_index_offset * self.splat_size()) as u64;
+        match self.splat_layout {
+            SplatLayout::Full => queue.write_buffer(&self.buffers.splat_buffer, splat_buffer_offset, transmute_slice(splat_data)),
+            SplatLayout::HalfPrecision => {
+                let packed_splat_data: Vec<PackedGpuSplat> = splat_data.iter().map(PackedGpuSplat::from).collect();
+                queue.write_buffer(&self.buffers.splat_buffer, splat_buffer_offset, transmute_slice(&packed_splat_data));
+            }
+            SplatLayout::Codebook => {
+                let codebook_splat_data: Vec<CodebookGpuSplat> = splat_data.iter().map(CodebookGpuSplat::from).collect();
+                queue.write_buffer(&self.buffers.splat_buffer, splat_buffer_offset, transmute_slice(&codebook_splat_data));
+            }
+        }
+        self.loaded_splat_count = self.loaded_splat_count.max(splat_index_offset + splat_data.len());
+        if let Some(splat_positions) = &mut self.splat_positions {
+            for (index, splat) in splat_data.iter().enumerate() {
+                let position_index = (splat_index_offset + index) * 3;
+                splat_positions[position_index..position_index + 3].copy_from_slice(&splat.center);
+            }
+        }
+        for (summary, splat) in self.splat_summaries[splat_index_offset..].iter_mut().zip(splat_data) {
+            *summary = SplatSummary::from(splat);
+        }
+        self.spatial_index.insert_splats(splat_index_offset, splat_data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{numbered_splats, splat_numbers, test_renderer};
+
+    /// Alignment and size of a WGSL type in storage buffers
+    fn wgsl_layout(wgsl_type: &str) -> (usize, usize) {
+        match wgsl_type {
+            "f32" | "u32" => (4, 4),
+            "vec2<f32>" | "vec2<u32>" => (8, 8),
+            "vec3<f32>" => (16, 12),
+            "vec4<f32>" => (16, 16),
+            _ => {
+                let element_count = wgsl_type
+                    .strip_prefix("array<f32,")
+                    .or_else(|| wgsl_type.strip_prefix("array<u32,"))
+                    .and_then(|rest| rest.strip_suffix('>'))
+                    .and_then(|count| count.trim().parse::<usize>().ok())
+                    .unwrap_or_else(|| panic!("unexpected WGSL type {}", wgsl_type));
+                (4, 4 * element_count)
             }
         }
     }
+
+    /// Size and field offsets of the `Splat` struct in a shader
+    fn wgsl_splat_layout(shader: &str) -> (usize, Vec<(String, usize)>) {
+        let struct_start = shader.find("struct Splat {").unwrap() + "struct Splat {".len();
+        let struct_body = &shader[struct_start..struct_start + shader[struct_start..].find('}').unwrap()];
+        let mut offset: usize = 0;
+        let mut struct_alignment = 1;
+        let mut field_offsets = Vec::new();
+        for field in struct_body.lines().map(|line| line.trim().trim_end_matches(',')).filter(|field| !field.is_empty()) {
+            let (name, wgsl_type) = field.split_once(':').unwrap();
+            let (alignment, size) = wgsl_layout(wgsl_type.trim());
+            offset = offset.div_ceil(alignment) * alignment;
+            field_offsets.push((name.trim().to_string(), offset));
+            offset += size;
+            struct_alignment = struct_alignment.max(alignment);
+        }
+        (offset.div_ceil(struct_alignment) * struct_alignment, field_offsets)
+    }
+
+    fn assert_field_offsets(field_offsets: &[(String, usize)], expected_offsets: &[(&str, usize)]) {
+        assert_eq!(field_offsets.len(), expected_offsets.len());
+        for ((name, offset), (expected_name, expected_offset)) in field_offsets.iter().zip(expected_offsets.iter()) {
+            assert_eq!(name, expected_name);
+            assert_eq!(offset, expected_offset, "field {} is at a different offset", name);
+        }
+    }
+
+    #[test]
+    fn gpu_splat_matches_wgsl() {
+        let (size, field_offsets) = wgsl_splat_layout(include_str!("shaders.wgsl"));
+        assert_eq!(std::mem::size_of::<GpuSplat>(), size);
+        assert_field_offsets(
+            &field_offsets,
+            &[
+                ("rotation", std::mem::offset_of!(GpuSplat, rotation)),
+                ("center", std::mem::offset_of!(GpuSplat, center)),
+                ("codebookIndex", std::mem::offset_of!(GpuSplat, codebook_index)),
+                ("scale", std::mem::offset_of!(GpuSplat, scale)),
+                ("alpha", std::mem::offset_of!(GpuSplat, alpha)),
+                ("colorSH", std::mem::offset_of!(GpuSplat, color)),
+            ],
+        );
+    }
+
+    #[test]
+    fn packed_gpu_splat_matches_wgsl() {
+        let (size, field_offsets) = wgsl_splat_layout(include_str!("packed_splat.wgsl"));
+        assert_eq!(std::mem::size_of::<PackedGpuSplat>(), size);
+        assert_field_offsets(
+            &field_offsets,
+            &[
+                ("center", std::mem::offset_of!(PackedGpuSplat, center)),
+                ("alpha", std::mem::offset_of!(PackedGpuSplat, alpha)),
+                ("rotation", std::mem::offset_of!(PackedGpuSplat, rotation)),
+                ("scale", std::mem::offset_of!(PackedGpuSplat, scale)),
+                ("colorSH", std::mem::offset_of!(PackedGpuSplat, color)),
+            ],
+        );
+    }
+
+    #[test]
+    fn codebook_gpu_splat_matches_wgsl() {
+        let (size, field_offsets) = wgsl_splat_layout(include_str!("codebook_splat.wgsl"));
+        assert_eq!(std::mem::size_of::<CodebookGpuSplat>(), size);
+        assert_field_offsets(
+            &field_offsets,
+            &[
+                ("rotation", std::mem::offset_of!(CodebookGpuSplat, rotation)),
+                ("center", std::mem::offset_of!(CodebookGpuSplat, center)),
+                ("codebookIndex", std::mem::offset_of!(CodebookGpuSplat, codebook_index)),
+                ("scale", std::mem::offset_of!(CodebookGpuSplat, scale)),
+                ("alpha", std::mem::offset_of!(CodebookGpuSplat, alpha)),
+                ("colorDC", std::mem::offset_of!(CodebookGpuSplat, color_dc)),
+            ],
+        );
+    }
+
+    #[test]
+    fn packed_gpu_splat_round_trip() {
+        let mut splat = GpuSplat {
+            rotation: [0.5, -0.5, 0.5, 0.5],
+            center: [1.0e5, -2.0, 3.0],
+            scale: [1.0e-6, 0.5, 2.0],
+            alpha: 0.7,
+            ..GpuSplat::default()
+        };
+        for (index, coefficient) in splat.color.iter_mut().enumerate() {
+            *coefficient = index as f32 * 0.01 - 0.2;
+        }
+        let round_trip = GpuSplat::from(&PackedGpuSplat::from(&splat));
+        // Center and opacity are kept exactly
+        assert_eq!(round_trip.center, splat.center);
+        assert_eq!(round_trip.alpha, splat.alpha);
+        assert_eq!(round_trip.rotation, splat.rotation);
+        // Scales far below the smallest normal f16 keep their relative precision as logarithms
+        for (value, round_trip_value) in splat.scale.iter().zip(round_trip.scale.iter()) {
+            assert!((value - round_trip_value).abs() <= value / 256.0, "{} became {}", value, round_trip_value);
+        }
+        for (value, round_trip_value) in splat.color.iter().zip(round_trip.color.iter()) {
+            assert!(
+                (value - round_trip_value).abs() <= value.abs() / 1024.0 + 1.0e-7,
+                "{} became {}",
+                value,
+                round_trip_value
+            );
+        }
+    }
+
+    #[test]
+    fn gpu_splat_round_trip() {
+        let mut serialized_splat = SerializedSplat {
+            center: [1.0, 2.0, 3.0],
+            alpha: 0.5,
+            scale: [-1.0, -2.0, -3.0],
+            rotation: [0.5, 0.5, 0.5, 0.5],
+            ..SerializedSplat::default()
+        };
+        for (index, coefficient) in serialized_splat.color.iter_mut().enumerate() {
+            *coefficient = index as f32 * 0.01;
+        }
+        let splat = GpuSplat::from(&serialized_splat);
+        assert_eq!(splat.color[3..6], [serialized_splat.color[3], serialized_splat.color[18], serialized_splat.color[33]]);
+        let round_trip = SerializedSplat::from(&splat);
+        for (value, round_trip_value) in serialized_splat
+            .color
+            .iter()
+            .chain(serialized_splat.scale.iter())
+            .chain([serialized_splat.alpha].iter())
+            .zip(round_trip.color.iter().chain(round_trip.scale.iter()).chain([round_trip.alpha].iter()))
+        {
+            assert!((value - round_trip_value).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn splat_format_is_chosen_by_extension() {
+        assert_eq!(SplatFormat::from_path("scene.PLY"), Some(SplatFormat::Ply));
+        assert_eq!(SplatFormat::from_path("dir.ply/scene.splat"), Some(SplatFormat::Splat));
+        assert_eq!(SplatFormat::from_path("scene.shcb"), Some(SplatFormat::Codebook));
+        assert_eq!(SplatFormat::from_path("scene.txt"), None);
+        assert_eq!(SplatFormat::from_path("scene"), None);
+
+        let directory = std::env::temp_dir().join(format!("splat_format_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str, contents: &[u8]| {
+            let path = directory.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+        assert!(matches!(Scene::open_file(path("scene.txt", &[0; 32])), Err(SceneError::UnknownFormat(_))));
+        assert!(matches!(Scene::open_file(path("empty.splat", &[])), Err(SceneError::MalformedHeader(_))));
+        assert!(matches!(Scene::open_file(path("garbage.ply", &[0; 64])), Err(SceneError::MalformedHeader(_))));
+        let (header, _) = Scene::open_file(path("scene.splat", &[0; 64])).unwrap();
+        assert_eq!(header.splat_count, 2);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn runs_of_consecutive_indices() {
+        assert_eq!(index_runs(&[2, 3, 4, 7, 9, 10]), vec![(2, 0..3), (7, 3..4), (9, 4..6)]);
+        assert_eq!(index_runs(&[5]), vec![(5, 0..1)]);
+        assert!(index_runs(&[]).is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn reserve_append_and_remove_splats() {
+        let (device, queue, renderer) = test_renderer(DepthSorting::Cpu);
+        let mut scene = Scene::new(&device, &renderer, 0).unwrap();
+        scene.reserve(&device, &queue, &renderer, 3).unwrap();
+        assert_eq!((scene.capacity(), scene.splat_count), (3, 0));
+        scene.append_splats(&device, &queue, &renderer, &numbered_splats(0..3)).unwrap();
+        assert_eq!((scene.capacity(), scene.splat_count, scene.loaded_splat_count), (3, 3, 3));
+        // Growing keeps the splats and at least doubles the capacity
+        scene.append_splats(&device, &queue, &renderer, &numbered_splats(3..5)).unwrap();
+        assert_eq!((scene.capacity(), scene.splat_count, scene.loaded_splat_count), (6, 5, 5));
+        assert_eq!(splat_numbers(&device, &queue, &scene), [0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert!(matches!(
+            scene.reserve(&device, &queue, &renderer, renderer.config.max_splat_count),
+            Err(SceneError::SplatCountOverflow { .. })
+        ));
+
+        scene.remove_splats(&device, &queue, &renderer, &[3, 1]).unwrap();
+        assert_eq!((scene.capacity(), scene.splat_count, scene.loaded_splat_count), (6, 3, 3));
+        assert_eq!(splat_numbers(&device, &queue, &scene), [0.0, 2.0, 4.0]);
+        assert_eq!(scene.splat_positions.as_ref().unwrap()[0..9], [0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 4.0, 0.0, 0.0]);
+        assert!(matches!(
+            scene.remove_splats(&device, &queue, &renderer, &[0, 3]),
+            Err(SceneError::SplatIndexOutOfBounds {
+                splat_index: 3,
+                splat_count: 3
+            })
+        ));
+        assert_eq!(splat_numbers(&device, &queue, &scene), [0.0, 2.0, 4.0]);
+
+        // Inserting grows the capacity and shifts the splats which are not loaded yet as well
+        scene.upload_splats(&queue, 3, &numbered_splats(5..8));
+        scene.loaded_splat_count = 3;
+        scene.splat_count = 6;
+        scene
+            .insert_splats(&device, &queue, &renderer, &[1, 3, 7], &numbered_splats(10..13))
+            .unwrap();
+        assert_eq!((scene.capacity(), scene.splat_count, scene.loaded_splat_count), (12, 9, 5));
+        assert_eq!(splat_numbers(&device, &queue, &scene), [0.0, 10.0, 2.0, 11.0, 4.0, 5.0, 6.0, 12.0, 7.0]);
+        assert_eq!(scene.splat_positions.as_ref().unwrap()[9..12], [11.0, 0.0, 0.0]);
+        assert_eq!(scene.splat_summaries[4].center, [4.0, 0.0, 0.0]);
+        assert!(matches!(
+            scene.insert_splats(&device, &queue, &renderer, &[10], &numbered_splats(0..1)),
+            Err(SceneError::SplatIndexOutOfBounds {
+                splat_index: 10,
+                splat_count: 10
+            })
+        ));
+
+        // Saving leaves out the splats which are not loaded yet
+        let path = std::env::temp_dir().join(format!("partial_scene_{}.splat", std::process::id()));
+        scene.save_splat(&device, &queue, &path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), (5 * COMPACT_SPLAT_SIZE) as u64);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
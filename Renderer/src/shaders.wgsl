@@ -4,10 +4,14 @@ struct Uniforms {
     view_projection_matrix: mat4x4<f32>,
     view_size: vec2<f32>,
     image_size: vec2<u32>,
+    view_offset: vec2<f32>,
     frustum_culling_tolerance: f32,
     ellipse_size_bias: f32,
     ellipse_margin: f32,
     splat_scale: f32,
+    spherical_harmonics_order: u32,
+    splat_count: u32,
+    use_spherical_harmonics_codebook: u32,
 }
 struct DrawIndirect {
     vertex_count: u32,
@@ -25,14 +29,30 @@ struct Entry {
     key: u32,
     value: u32,
 }
+// BEGIN SPLAT LAYOUT
+// Replaced by packed_splat.wgsl or codebook_splat.wgsl depending on the splat layout of the renderer
 struct Splat {
     rotation: vec4<f32>,
     center: vec3<f32>,
-    paddingA: f32,
+    codebookIndex: u32,
     scale: vec3<f32>,
     alpha: f32,
     colorSH: array<f32, 48>,
 }
+fn splatRotation(splat_index: u32) -> vec4<f32> {
+    return splats[splat_index].rotation;
+}
+fn splatScale(splat_index: u32) -> vec3<f32> {
+    return splats[splat_index].scale;
+}
+fn splatColorSH(splat_index: u32, coefficient: u32) -> vec3<f32> {
+    let index = coefficient * 3u;
+    return vec3<f32>(splats[splat_index].colorSH[index], splats[splat_index].colorSH[index + 1u], splats[splat_index].colorSH[index + 2u]);
+}
+fn splatCodebookIndex(splat_index: u32) -> u32 {
+    return splats[splat_index].codebookIndex;
+}
+// END SPLAT LAYOUT
 @group(0) @binding(0) var<uniform> uniforms: Uniforms;
 @group(0) @binding(1) var<uniform> sorting_pass_index: u32;
 @group(0) @binding(2) var<storage, read_write> sorting: SortingGlobal;
@@ -41,6 +61,13 @@ struct Splat {
 @group(0) @binding(5) var<storage, read> sorted_entries: array<Entry>;
 @group(0) @binding(6) var<storage> splats: array<Splat>;
 @group(0) @binding(7) var<storage, write> radii: array<f32>;
+@group(0) @binding(8) var<storage> spherical_harmonics_codebook: array<f32>;
+// Bindings of the depth maps, see depth.rs
+@group(1) @binding(0) var<storage, read> depth_bisection_bounds: array<vec2<u32>>;
+@group(0) @binding(10) var depth_accumulation: texture_2d<f32>;
+@group(0) @binding(11) var depth_transmittance: texture_2d<f32>;
+@group(0) @binding(12) var<storage, read_write> depth_bisection_state: array<vec2<u32>>;
+@group(0) @binding(13) var<storage, read_write> depth_maps: array<f32>;
 
 fn screenToClipSpace(screen_space_pos: vec2<f32>) -> vec2<f32> {
     var result = ((screen_space_pos.xy / vec2<f32>(uniforms.image_size)) - vec2<f32>(0.5));
@@ -52,6 +79,11 @@ fn clipToScreenSpace(clip_space_pos: vec2<f32>) -> vec2<f32> {
     return result * vec2<f32>(uniforms.image_size);
 }
 
+// The image plane at distance 1 has x pointing right and y pointing down, clip space y points up
+fn viewPlaneToClipSpace(view_plane_pos: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(1.0, -1.0) * (view_plane_pos - uniforms.view_offset) / uniforms.view_size;
+}
+
 fn worldToClipSpace(world_pos: vec3<f32>) -> vec4<f32> {
     var homogenous_pos = uniforms.view_projection_matrix * vec4<f32>(world_pos, 1.0);
     return vec4<f32>(homogenous_pos.xyz, 1.0) / (homogenous_pos.w + 0.0000001);
@@ -106,8 +138,9 @@ fn projectedCovarianceOfEllipsoid(scale: vec3<f32>, rotation: vec4<f32>, transla
 
     // 3D Covariance
     var view_pos = uniforms.view_matrix * vec4<f32>(translation, 1.0);
-    view_pos.x = clamp(view_pos.x / view_pos.z, -1.0, 1.0) * view_pos.z;
-    view_pos.y = clamp(view_pos.y / view_pos.z, -1.0, 1.0) * view_pos.z;
+    let view_plane_pos = clamp(view_pos.xy / view_pos.z - uniforms.view_offset, vec2<f32>(-1.0), vec2<f32>(1.0)) + uniforms.view_offset;
+    view_pos.x = view_plane_pos.x * view_pos.z;
+    view_pos.y = view_plane_pos.y * view_pos.z;
     let T = transpose(transform) * camera_matrix * mat3x3(
         1.0 / view_pos.z, 0.0, -view_pos.x / (view_pos.z * view_pos.z),
         0.0, 1.0 / view_pos.z, -view_pos.y / (view_pos.z * view_pos.z),
@@ -156,7 +189,7 @@ fn projectedContourOfEllipsoid(scale: vec3<f32>, rotation: vec4<f32>, translatio
     let sqrt_M = transpose(A) * camera_matrix;
     */
 
-    // Given: let pos_in_view_plane = vec3<f32>(screenToClipSpace(stage_in.gl_Position.xy) * uniforms.view_size, 1.0);
+    // Given: let pos_in_view_plane = vec3<f32>(vec2<f32>(1.0, -1.0) * screenToClipSpace(stage_in.gl_Position.xy) * uniforms.view_size + uniforms.view_offset, 1.0);
     // And: let local_ray_direction = camera_matrix * pos_in_view_plane * transform;
     // The matrix A would be sufficient to render the ellipse: dot(local_ray_direction, A * local_ray_direction) = 0
     // However, we want to be independent of the ray direction, as we do not need to do this work per fragment.
@@ -266,32 +299,41 @@ const shc = array<f32, 16>(
 	-0.5900435899266435,
 );
 
+// Higher order coefficients are read through the codebook of the scene if it has one
+fn higherOrderColorSH(splat_index: u32, coefficient: u32) -> vec3<f32> {
+    if(uniforms.use_spherical_harmonics_codebook == 0u) {
+        return splatColorSH(splat_index, coefficient);
+    }
+    let index = splatCodebookIndex(splat_index) * 45u + (coefficient - 1u) * 3u;
+    return vec3<f32>(spherical_harmonics_codebook[index], spherical_harmonics_codebook[index + 1u], spherical_harmonics_codebook[index + 2u]);
+}
+
 // Equivalente à computeColorFromSH de 3DGAUSSIAN SPLATTING
 fn sphericalHarmonicsLookup(ray_direction: vec3<f32>, splat_index: u32) -> vec3<f32> {
     var ray_direction_squared = ray_direction * ray_direction;
     var color = vec3<f32>(0.5);
-    color += shc[ 0] * vec3<f32>(splats[splat_index].colorSH[ 0], splats[splat_index].colorSH[ 1], splats[splat_index].colorSH[ 2]);
+    color += shc[ 0] * splatColorSH(splat_index, 0u);
     
-    if(SPHERICAL_HARMONICS_ORDER > 0u) {
-        color += shc[ 1] * vec3<f32>(splats[splat_index].colorSH[ 3], splats[splat_index].colorSH[ 4], splats[splat_index].colorSH[ 5]) * ray_direction.y;
-        color += shc[ 2] * vec3<f32>(splats[splat_index].colorSH[ 6], splats[splat_index].colorSH[ 7], splats[splat_index].colorSH[ 8]) * ray_direction.z;
-        color += shc[ 3] * vec3<f32>(splats[splat_index].colorSH[ 9], splats[splat_index].colorSH[10], splats[splat_index].colorSH[11]) * ray_direction.x;
-    }
-    if(SPHERICAL_HARMONICS_ORDER > 1u) {
-        color += shc[ 4] * vec3<f32>(splats[splat_index].colorSH[12], splats[splat_index].colorSH[13], splats[splat_index].colorSH[14]) * ray_direction.x * ray_direction.y;
-        color += shc[ 5] * vec3<f32>(splats[splat_index].colorSH[15], splats[splat_index].colorSH[16], splats[splat_index].colorSH[17]) * ray_direction.y * ray_direction.z;
-        color += shc[ 6] * vec3<f32>(splats[splat_index].colorSH[18], splats[splat_index].colorSH[19], splats[splat_index].colorSH[20]) * (2.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
-        color += shc[ 7] * vec3<f32>(splats[splat_index].colorSH[21], splats[splat_index].colorSH[22], splats[splat_index].colorSH[23]) * ray_direction.x * ray_direction.z;
-        color += shc[ 8] * vec3<f32>(splats[splat_index].colorSH[24], splats[splat_index].colorSH[25], splats[splat_index].colorSH[26]) * (ray_direction_squared.x - ray_direction_squared.y);
-    }
-    if(SPHERICAL_HARMONICS_ORDER > 2u) {
-        color += shc[ 9] * vec3<f32>(splats[splat_index].colorSH[27], splats[splat_index].colorSH[28], splats[splat_index].colorSH[29]) * ray_direction.y * (3.0 * ray_direction_squared.x - ray_direction_squared.y);
-        color += shc[10] * vec3<f32>(splats[splat_index].colorSH[30], splats[splat_index].colorSH[31], splats[splat_index].colorSH[32]) * ray_direction.x * ray_direction.y * ray_direction.z;
-        color += shc[11] * vec3<f32>(splats[splat_index].colorSH[33], splats[splat_index].colorSH[34], splats[splat_index].colorSH[35]) * ray_direction.y * (4.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
-        color += shc[12] * vec3<f32>(splats[splat_index].colorSH[36], splats[splat_index].colorSH[37], splats[splat_index].colorSH[38]) * ray_direction.z * (2.0 * ray_direction_squared.z - 3.0 * ray_direction_squared.x - 3.0 * ray_direction_squared.y);
-        color += shc[13] * vec3<f32>(splats[splat_index].colorSH[39], splats[splat_index].colorSH[40], splats[splat_index].colorSH[41]) * ray_direction.x * (4.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
-        color += shc[14] * vec3<f32>(splats[splat_index].colorSH[42], splats[splat_index].colorSH[43], splats[splat_index].colorSH[44]) * ray_direction.z * (ray_direction_squared.x - ray_direction_squared.y);
-        color += shc[15] * vec3<f32>(splats[splat_index].colorSH[45], splats[splat_index].colorSH[46], splats[splat_index].colorSH[47]) * ray_direction.x * (ray_direction_squared.x - 3.0 * ray_direction_squared.y);
+    if(SPHERICAL_HARMONICS_ORDER > 0u && uniforms.spherical_harmonics_order > 0u) {
+        color += shc[ 1] * higherOrderColorSH(splat_index, 1u) * ray_direction.y;
+        color += shc[ 2] * higherOrderColorSH(splat_index, 2u) * ray_direction.z;
+        color += shc[ 3] * higherOrderColorSH(splat_index, 3u) * ray_direction.x;
+    }
+    if(SPHERICAL_HARMONICS_ORDER > 1u && uniforms.spherical_harmonics_order > 1u) {
+        color += shc[ 4] * higherOrderColorSH(splat_index, 4u) * ray_direction.x * ray_direction.y;
+        color += shc[ 5] * higherOrderColorSH(splat_index, 5u) * ray_direction.y * ray_direction.z;
+        color += shc[ 6] * higherOrderColorSH(splat_index, 6u) * (2.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
+        color += shc[ 7] * higherOrderColorSH(splat_index, 7u) * ray_direction.x * ray_direction.z;
+        color += shc[ 8] * higherOrderColorSH(splat_index, 8u) * (ray_direction_squared.x - ray_direction_squared.y);
+    }
+    if(SPHERICAL_HARMONICS_ORDER > 2u && uniforms.spherical_harmonics_order > 2u) {
+        color += shc[ 9] * higherOrderColorSH(splat_index, 9u) * ray_direction.y * (3.0 * ray_direction_squared.x - ray_direction_squared.y);
+        color += shc[10] * higherOrderColorSH(splat_index, 10u) * ray_direction.x * ray_direction.y * ray_direction.z;
+        color += shc[11] * higherOrderColorSH(splat_index, 11u) * ray_direction.y * (4.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
+        color += shc[12] * higherOrderColorSH(splat_index, 12u) * ray_direction.z * (2.0 * ray_direction_squared.z - 3.0 * ray_direction_squared.x - 3.0 * ray_direction_squared.y);
+        color += shc[13] * higherOrderColorSH(splat_index, 13u) * ray_direction.x * (4.0 * ray_direction_squared.z - ray_direction_squared.x - ray_direction_squared.y);
+        color += shc[14] * higherOrderColorSH(splat_index, 14u) * ray_direction.z * (ray_direction_squared.x - ray_direction_squared.y);
+        color += shc[15] * higherOrderColorSH(splat_index, 15u) * ray_direction.x * (ray_direction_squared.x - 3.0 * ray_direction_squared.y);
     }
      // Clampez les valeurs de couleur à l'intervalle [0, 1]
     // En WGSL, vous pouvez utiliser la fonction max pour clamper les valeurs inférieures à 0
@@ -318,7 +360,7 @@ fn radixSortA(
     let start_entry_index = thread_index * ENTRIES_PER_INVOCATION_A;
     let end_entry_index = start_entry_index + ENTRIES_PER_INVOCATION_A;
     for(var entry_index = start_entry_index; entry_index < end_entry_index; entry_index += 1u) {
-        if(entry_index >= arrayLength(&splats)) {
+        if(entry_index >= uniforms.splat_count) {
             continue;
         }
         var key: u32 = 0xFFFFFFFFu; // Stream compaction for frustum culling
@@ -419,7 +461,7 @@ fn computeRadii(@builtin(global_invocation_id) global_id: vec3<u32>) {
    
 
     let world_position = splats[sorted_index].center;
-    let covariance = projectedCovarianceOfEllipsoid(splats[sorted_index].scale * uniforms.splat_scale, splats[sorted_index].rotation, world_position);
+    let covariance = projectedCovarianceOfEllipsoid(splatScale(sorted_index) * uniforms.splat_scale, splatRotation(sorted_index), world_position);
     let mid: f32 = 0.5 * (covariance[0][0] + covariance[1][1]);
     let det: f32 = covariance[0][0] * covariance[1][1] - covariance[0][1] * covariance[1][0];
     let lambda1: f32 = mid + sqrt(max(0.1, mid * mid - det));
@@ -448,7 +490,7 @@ fn radixSortC(
     let assignment = sorting_shared_c.entries[0];
     let global_entry_offset = assignment * WORKGROUP_ENTRIES_C;
     // TODO: Specialize end shader
-    if(gl_LocalInvocationID.x == 0u && assignment * WORKGROUP_ENTRIES_C + WORKGROUP_ENTRIES_C >= arrayLength(&splats)) {
+    if(gl_LocalInvocationID.x == 0u && assignment * WORKGROUP_ENTRIES_C + WORKGROUP_ENTRIES_C >= uniforms.splat_count) {
         // Last workgroup resets the assignment number for the next pass
         sorting.assignment_counter = 0u;
     }
@@ -489,7 +531,7 @@ fn radixSortC(
         }
     }
     atomicStore(&sorting.status_counters[assignment][gl_LocalInvocationID.x], 0x80000000u | (global_digit_count + local_digit_count));
-    if(sorting_pass_index == RADIX_DIGIT_PLACES - 1u && gl_LocalInvocationID.x == WORKGROUP_INVOCATIONS_C - 2u && global_entry_offset + WORKGROUP_ENTRIES_C >= arrayLength(&splats)) {
+    if(sorting_pass_index == RADIX_DIGIT_PLACES - 1u && gl_LocalInvocationID.x == WORKGROUP_INVOCATIONS_C - 2u && global_entry_offset + WORKGROUP_ENTRIES_C >= uniforms.splat_count) {
         sorting.draw_indirect.vertex_count = 4u;
         sorting.draw_indirect.instance_count = global_digit_count + local_digit_count;
     }
@@ -536,7 +578,8 @@ struct VertexOutput {
     @location(0) @interpolate(flat) color: vec4<f32>,
     @location(1) @interpolate(linear) gl_TexCoord: vec2<f32>,
     // @location(2) @interpolate(flat) splat_index: u32,
-
+    // View space depth of the splat center
+    @location(3) @interpolate(flat) depth: f32,
 }
 
 @vertex
@@ -565,12 +608,13 @@ fn vertex(
     let world_position = splats[splat_index].center;
     let ray_direction = normalize(world_position - uniforms.camera_matrix.w.xyz);
     stage_out.color = vec4<f32>(sphericalHarmonicsLookup(ray_direction, splat_index), splats[splat_index].alpha);
-    let M = projectedContourOfEllipsoid(splats[splat_index].scale * uniforms.splat_scale, splats[splat_index].rotation, world_position);
+    stage_out.depth = (uniforms.view_matrix * vec4<f32>(world_position, 1.0)).z;
+    let M = projectedContourOfEllipsoid(splatScale(splat_index) * uniforms.splat_scale, splatRotation(splat_index), world_position);
     let translation = extractTranslationOfEllipse(M);
     let rotation = extractRotationOfEllipse(M);
     var semi_axes: vec2<f32>;
     if(USE_COVARIANCE_FOR_SCALE) {
-        let covariance = projectedCovarianceOfEllipsoid(splats[splat_index].scale * uniforms.splat_scale, splats[splat_index].rotation, world_position);
+        let covariance = projectedCovarianceOfEllipsoid(splatScale(splat_index) * uniforms.splat_scale, splatRotation(splat_index), world_position);
         semi_axes = extractScaleOfCovariance(covariance);
         
     } else {
@@ -594,7 +638,7 @@ fn vertex(
             vec3<f32>(transformation.z, 1.0),
         );
         stage_out.gl_TexCoord = quad_vertices[gl_VertexID] * uniforms.ellipse_margin;
-        stage_out.gl_Position = vec4<f32>((T * vec3<f32>(stage_out.gl_TexCoord, 1.0)).xy / uniforms.view_size, 0.0, 1.0);
+        stage_out.gl_Position = vec4<f32>(viewPlaneToClipSpace((T * vec3<f32>(stage_out.gl_TexCoord, 1.0)).xy), 0.0, 1.0);
     } else {
         let inverse = mat2x2<f32>(
             transformation.y.y, -transformation.x.y,
@@ -602,7 +646,7 @@ fn vertex(
         ) * (1.0 / (transformation.x.x * transformation.y.y - transformation.x.y * transformation.y.x));
         let radius = sqrt(max(dot(transformation.x, transformation.x), dot(transformation.y, transformation.y)));
         stage_out.gl_TexCoord = quad_vertices[gl_VertexID] * radius * uniforms.ellipse_margin;
-        stage_out.gl_Position = vec4<f32>((transformation.z + stage_out.gl_TexCoord) / uniforms.view_size, 0.0, 1.0);
+        stage_out.gl_Position = vec4<f32>(viewPlaneToClipSpace(transformation.z + stage_out.gl_TexCoord), 0.0, 1.0);
         stage_out.gl_TexCoord = inverse * stage_out.gl_TexCoord;
     }
     return stage_out;
@@ -613,16 +657,111 @@ struct FragmentOutput {
     // @builtin(frag_depth) gl_FragDepth: f32,
 }
 
+// Opacity of the splat at the fragment, the fragment shaders discard it below 1/255
+fn fragmentAlpha(stage_in: VertexOutput) -> f32 {
+    let power = dot(stage_in.gl_TexCoord, stage_in.gl_TexCoord);
+    return stage_in.color.a * exp(-0.5 * power);
+}
+
 @fragment
 fn fragment(
     stage_in: VertexOutput,
 ) -> FragmentOutput {
     var stage_out: FragmentOutput;
-    let power = dot(stage_in.gl_TexCoord, stage_in.gl_TexCoord);
-    let alpha = stage_in.color.a * exp(-0.5 * power);
+    let alpha = fragmentAlpha(stage_in);
     if(alpha < 1.0/255.0) {
         discard;
     }
     stage_out.gl_Color = vec4<f32>(stage_in.color.rgb * alpha , alpha);
     return stage_out;
 }
+
+// Blends only the transmittance of the splats
+@fragment
+fn transmittanceFragment(stage_in: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = fragmentAlpha(stage_in);
+    if(alpha < 1.0/255.0) {
+        discard;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, alpha);
+}
+
+// Index of the pixel of a fragment in the per pixel buffers
+fn fragmentPixelIndex(stage_in: VertexOutput) -> u32 {
+    return u32(stage_in.gl_Position.y) * uniforms.image_size.x + u32(stage_in.gl_Position.x);
+}
+
+// Blends the depths of the splats front to back like their colors, weighted by their contributions
+// The depths are relative to the reference depth the bisection ended at, so that half floats only accumulate the spread around it
+@fragment
+fn depthFragment(stage_in: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = fragmentAlpha(stage_in);
+    if(alpha < 1.0/255.0) {
+        discard;
+    }
+    let reference_depth = bitcast<f32>(depth_bisection_bounds[fragmentPixelIndex(stage_in)].y);
+    return vec4<f32>((stage_in.depth - reference_depth) * alpha, 0.0, 0.0, alpha);
+}
+
+// Blends only the transmittance of the splats up to the middle of the bisection bounds of the pixel, in any order
+@fragment
+fn depthBisectionFragment(stage_in: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = fragmentAlpha(stage_in);
+    let bounds = depth_bisection_bounds[fragmentPixelIndex(stage_in)];
+    // Positive floats are ordered like their bits
+    if(alpha < 1.0/255.0 || bitcast<u32>(stage_in.depth) > bounds.x + (bounds.y - bounds.x) / 2u) {
+        discard;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, alpha);
+}
+
+// Transmittance the bisection searches the depth of, 0.5 for the median
+// Where the splats never reach an opacity of 0.5, half of their final opacity, so that there is a reference depth for the expected depth
+fn bisectionThreshold(final_transmittance: f32) -> f32 {
+    if(final_transmittance < 0.5) {
+        return 0.5;
+    }
+    return 0.5 * (1.0 + final_transmittance);
+}
+
+// Halves the bisection bounds of each pixel
+// The transmittance up to the lower bound stays at least the threshold, the one up to the upper bound below it
+@compute @workgroup_size(8, 8)
+fn bisectDepth(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let size = textureDimensions(depth_transmittance);
+    if(global_id.x >= size.x || global_id.y >= size.y) {
+        return;
+    }
+    let pixel_index = global_id.y * size.x + global_id.x;
+    let bounds = depth_bisection_state[pixel_index];
+    let middle = bounds.x + (bounds.y - bounds.x) / 2u;
+    let threshold = bisectionThreshold(textureLoad(depth_accumulation, vec2<i32>(global_id.xy), 0).a);
+    if(textureLoad(depth_transmittance, vec2<i32>(global_id.xy), 0).a >= threshold) {
+        depth_bisection_state[pixel_index].x = middle;
+    } else {
+        depth_bisection_state[pixel_index].y = middle;
+    }
+}
+
+// Writes the expected depth map followed by the median depth map, pixels without a depth are infinite
+@compute @workgroup_size(8, 8)
+fn resolveDepth(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let size = textureDimensions(depth_accumulation);
+    if(global_id.x >= size.x || global_id.y >= size.y) {
+        return;
+    }
+    let pixel_index = global_id.y * size.x + global_id.x;
+    let accumulation = textureLoad(depth_accumulation, vec2<i32>(global_id.xy), 0);
+    let reference_depth = bitcast<f32>(depth_bisection_state[pixel_index].y);
+    let infinity = bitcast<f32>(0x7F800000u);
+    var expected_depth = infinity;
+    if(accumulation.a < 1.0) {
+        expected_depth = reference_depth + accumulation.r / (1.0 - accumulation.a);
+    }
+    var median_depth = infinity;
+    if(accumulation.a < 0.5) {
+        median_depth = reference_depth;
+    }
+    depth_maps[pixel_index] = expected_depth;
+    depth_maps[size.x * size.y + pixel_index] = median_depth;
+}
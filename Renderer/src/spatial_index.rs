@@ -0,0 +1,600 @@
+//! Loose octree over the bounding boxes of splats, for region, frustum and ray queries
+//!
+//! Each splat is stored in the deepest node whose cell contains its center and which is at least as large as the splat,
+//! so the bounding boxes of the splats in a node never reach further than half a cell beyond it.
+use crate::{scene::GpuSplat, transform};
+use geometric_algebra::ppga3d::Point;
+
+/// Nodes holding more splats than this are subdivided
+const NODE_CAPACITY: usize = 16;
+/// Nodes are not subdivided beyond this many levels below the root
+const MAX_DEPTH: i32 = 20;
+/// Splats are bounded by their ellipsoid at this many standard deviations
+const SIGMA_COUNT: f32 = 3.0;
+/// Marks splats which are not in the index
+const NOT_INDEXED: u32 = u32::MAX;
+/// Marks splats with non-finite bounding boxes, which are kept in a list instead of a node
+const UNBOUNDED: u32 = u32::MAX - 1;
+
+/// Minimum and maximum corner of an axis aligned box
+pub type BoundingBox = [[f32; 3]; 2];
+
+/// The axis aligned box containing the ellipsoid of a splat at three standard deviations
+pub fn splat_bounding_box(splat: &GpuSplat) -> BoundingBox {
+    let rotation = transform::rotation_matrix(splat.rotation);
+    let mut bounding_box = [splat.center; 2];
+    for axis in 0..3 {
+        let half_extent = (0..3)
+            .map(|column| (rotation[axis][column] * splat.scale[column] as f64).powi(2))
+            .sum::<f64>()
+            .sqrt() as f32
+            * SIGMA_COUNT;
+        bounding_box[0][axis] -= half_extent;
+        bounding_box[1][axis] += half_extent;
+    }
+    bounding_box
+}
+
+fn boxes_intersect(a: &BoundingBox, b: &BoundingBox) -> bool {
+    (0..3).all(|axis| a[0][axis] <= b[1][axis] && b[0][axis] <= a[1][axis])
+}
+
+fn box_intersects_sphere(bounding_box: &BoundingBox, center: [f32; 3], radius: f32) -> bool {
+    let squared_distance: f32 = (0..3)
+        .map(|axis| {
+            let offset = center[axis] - center[axis].max(bounding_box[0][axis]).min(bounding_box[1][axis]);
+            offset * offset
+        })
+        .sum();
+    squared_distance <= radius * radius
+}
+
+/// Distance along the ray at which it enters the box, zero if it starts inside
+fn ray_entry_distance(bounding_box: &BoundingBox, origin: [f32; 3], direction: [f32; 3]) -> Option<f32> {
+    let mut entry_distance = 0.0f32;
+    let mut exit_distance = f32::INFINITY;
+    for axis in 0..3 {
+        if direction[axis] == 0.0 {
+            // Parallel to the slab, so the ray either stays inside or never enters it
+            if origin[axis] < bounding_box[0][axis] || origin[axis] > bounding_box[1][axis] {
+                return None;
+            }
+            continue;
+        }
+        let mut near = (bounding_box[0][axis] - origin[axis]) / direction[axis];
+        let mut far = (bounding_box[1][axis] - origin[axis]) / direction[axis];
+        if near > far {
+            std::mem::swap(&mut near, &mut far);
+        }
+        entry_distance = entry_distance.max(near);
+        exit_distance = exit_distance.min(far);
+    }
+    if entry_distance <= exit_distance {
+        Some(entry_distance)
+    } else {
+        None
+    }
+}
+
+/// The volume seen by a camera, bounded by six planes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// Planes (a, b, c, d) with a * x + b * y + c * z + d >= 0 on the inside
+    pub planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a column major view projection matrix
+    ///
+    /// The frustum contains the points whose clip space position has x and y within ±`tolerance` and z within [0, 1],
+    /// matching the frustum culling of the renderer.
+    pub fn from_view_projection(matrix: &[Point; 4], tolerance: f32) -> Self {
+        let row = |index: usize| [matrix[0][index], matrix[1][index], matrix[2][index], matrix[3][index]];
+        let combine = |a: [f32; 4], factor: f32, b: [f32; 4]| [0, 1, 2, 3].map(|index| a[index] + factor * b[index]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                combine(x, tolerance, w),
+                combine(w.map(|value| value * tolerance), -1.0, x),
+                combine(y, tolerance, w),
+                combine(w.map(|value| value * tolerance), -1.0, y),
+                z,
+                combine(w, -1.0, z),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: [f32; 3]) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane[0] * point[0] + plane[1] * point[1] + plane[2] * point[2] + plane[3] >= 0.0)
+    }
+
+    /// Conservative test which can accept boxes near the edges of the frustum and boxes with infinite extents
+    pub fn intersects_box(&self, bounding_box: &BoundingBox) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal of the plane
+            let corner = [0, 1, 2].map(|axis| bounding_box[(plane[axis] >= 0.0) as usize][axis]);
+            let distance = plane[0] * corner[0] + plane[1] * corner[1] + plane[2] * corner[2] + plane[3];
+            // Infinite corners can give NaN, which is kept
+            distance >= 0.0 || distance.is_nan()
+        })
+    }
+}
+
+struct Node {
+    center: [f32; 3],
+    half_size: f32,
+    children: Option<[u32; 8]>,
+    splat_indices: Vec<u32>,
+}
+
+impl Node {
+    fn new(center: [f32; 3], half_size: f32) -> Self {
+        Self {
+            center,
+            half_size,
+            children: None,
+            splat_indices: Vec::new(),
+        }
+    }
+
+    /// The cell of the node, doubled in size as splats may reach half a cell beyond it
+    fn loose_bounding_box(&self) -> BoundingBox {
+        [
+            self.center.map(|component| component - 2.0 * self.half_size),
+            self.center.map(|component| component + 2.0 * self.half_size),
+        ]
+    }
+
+    fn octant(&self, point: [f32; 3]) -> usize {
+        (0..3).map(|axis| ((point[axis] >= self.center[axis]) as usize) << axis).sum()
+    }
+
+    fn child_center(&self, octant: usize) -> [f32; 3] {
+        [0, 1, 2].map(|axis| {
+            let offset = if octant & (1 << axis) != 0 { 0.5 } else { -0.5 };
+            self.center[axis] + offset * self.half_size
+        })
+    }
+
+    fn fits(&self, center: [f32; 3], half_extent: f32) -> bool {
+        half_extent <= self.half_size && (0..3).all(|axis| (center[axis] - self.center[axis]).abs() <= self.half_size)
+    }
+}
+
+/// Center and largest half extent of a box
+fn center_and_half_extent(bounding_box: &BoundingBox) -> ([f32; 3], f32) {
+    let center = [0, 1, 2].map(|axis| 0.5 * (bounding_box[0][axis] + bounding_box[1][axis]));
+    let half_extent = (0..3).fold(0.0f32, |half_extent, axis| {
+        half_extent.max(0.5 * (bounding_box[1][axis] - bounding_box[0][axis]))
+    });
+    (center, half_extent)
+}
+
+/// Spatial index over the bounding boxes of splats, identified by their index in the [Scene](crate::scene::Scene)
+///
+/// The root grows to enclose inserted splats, nodes are subdivided as they fill up and are kept when emptied.
+#[derive(Default)]
+pub struct SpatialIndex {
+    nodes: Vec<Node>,
+    root: Option<u32>,
+    /// Bounding box of each splat, by splat index
+    bounding_boxes: Vec<BoundingBox>,
+    /// Node holding each splat, by splat index
+    splat_nodes: Vec<u32>,
+    /// Splats whose bounding boxes are not finite
+    unbounded_splat_indices: Vec<u32>,
+    indexed_splat_count: usize,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of splats in the index
+    pub fn len(&self) -> usize {
+        self.indexed_splat_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indexed_splat_count == 0
+    }
+
+    /// The bounding box of a splat in the index
+    pub fn bounding_box(&self, splat_index: usize) -> Option<BoundingBox> {
+        match self.splat_nodes.get(splat_index) {
+            Some(&NOT_INDEXED) | None => None,
+            Some(_) => Some(self.bounding_boxes[splat_index]),
+        }
+    }
+
+    /// Inserts or replaces the splats at the indices starting at `splat_index_offset`
+    pub fn insert_splats(&mut self, splat_index_offset: usize, splat_data: &[GpuSplat]) {
+        let end = splat_index_offset + splat_data.len();
+        if self.splat_nodes.len() < end {
+            self.splat_nodes.resize(end, NOT_INDEXED);
+            self.bounding_boxes.resize(end, [[0.0; 3]; 2]);
+        }
+        let bounding_boxes: Vec<BoundingBox> = splat_data.iter().map(splat_bounding_box).collect();
+        if self.root.is_none() {
+            // Starts with a root enclosing the first finite batch, so that it rarely has to grow
+            let mut enclosing_box = [[f32::INFINITY; 3], [f32::NEG_INFINITY; 3]];
+            for bounding_box in bounding_boxes
+                .iter()
+                .filter(|bounding_box| bounding_box.iter().flatten().all(|value| value.is_finite()))
+            {
+                for axis in 0..3 {
+                    enclosing_box[0][axis] = enclosing_box[0][axis].min(bounding_box[0][axis]);
+                    enclosing_box[1][axis] = enclosing_box[1][axis].max(bounding_box[1][axis]);
+                }
+            }
+            if enclosing_box[0][0] <= enclosing_box[1][0] {
+                let (center, half_extent) = center_and_half_extent(&enclosing_box);
+                self.root = Some(self.nodes.len() as u32);
+                self.nodes.push(Node::new(center, half_extent.max(f32::MIN_POSITIVE)));
+            }
+        }
+        for (index, bounding_box) in bounding_boxes.into_iter().enumerate() {
+            self.remove_splat(splat_index_offset + index);
+            self.insert_splat(splat_index_offset + index, bounding_box);
+        }
+    }
+
+    fn insert_splat(&mut self, splat_index: usize, bounding_box: BoundingBox) {
+        self.bounding_boxes[splat_index] = bounding_box;
+        self.indexed_splat_count += 1;
+        if !bounding_box.iter().flatten().all(|value| value.is_finite()) {
+            self.splat_nodes[splat_index] = UNBOUNDED;
+            self.unbounded_splat_indices.push(splat_index as u32);
+            return;
+        }
+        let (center, half_extent) = center_and_half_extent(&bounding_box);
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes.push(Node::new(center, half_extent.max(f32::MIN_POSITIVE)));
+                self.nodes.len() as u32 - 1
+            }
+        };
+        self.root = Some(self.grow_root(root, center, half_extent));
+        self.place_splat(self.root.unwrap(), splat_index as u32);
+    }
+
+    /// Doubles the root towards the splat until it fits
+    fn grow_root(&mut self, mut root: u32, center: [f32; 3], half_extent: f32) -> u32 {
+        while !self.nodes[root as usize].fits(center, half_extent) {
+            let (old_center, old_half_size) = (self.nodes[root as usize].center, self.nodes[root as usize].half_size);
+            let mut new_center = old_center;
+            let mut old_root_octant = 0;
+            for axis in 0..3 {
+                if center[axis] < old_center[axis] {
+                    new_center[axis] -= old_half_size;
+                    old_root_octant |= 1 << axis;
+                } else {
+                    new_center[axis] += old_half_size;
+                }
+            }
+            let new_root = Node::new(new_center, 2.0 * old_half_size);
+            let mut children = [0; 8];
+            for (octant, child) in children.iter_mut().enumerate() {
+                *child = if octant == old_root_octant {
+                    root
+                } else {
+                    self.nodes.push(Node::new(new_root.child_center(octant), old_half_size));
+                    self.nodes.len() as u32 - 1
+                };
+            }
+            self.nodes.push(Node {
+                children: Some(children),
+                ..new_root
+            });
+            root = self.nodes.len() as u32 - 1;
+        }
+        root
+    }
+
+    /// Stores the splat in the deepest node below `node` it fits into, subdividing full nodes
+    fn place_splat(&mut self, mut node: u32, splat_index: u32) {
+        let (center, half_extent) = center_and_half_extent(&self.bounding_boxes[splat_index as usize]);
+        while let Some(children) = self.nodes[node as usize].children {
+            let child = children[self.nodes[node as usize].octant(center)];
+            if half_extent > self.nodes[child as usize].half_size {
+                break;
+            }
+            node = child;
+        }
+        self.nodes[node as usize].splat_indices.push(splat_index);
+        self.splat_nodes[splat_index as usize] = node;
+        let min_half_size = self.nodes[self.root.unwrap() as usize].half_size * 0.5f32.powi(MAX_DEPTH);
+        let leaf = &self.nodes[node as usize];
+        if leaf.children.is_none() && leaf.splat_indices.len() > NODE_CAPACITY && leaf.half_size > min_half_size {
+            self.subdivide(node);
+        }
+    }
+
+    fn subdivide(&mut self, node: u32) {
+        let mut children = [0; 8];
+        for (octant, child) in children.iter_mut().enumerate() {
+            let parent = &self.nodes[node as usize];
+            let child_node = Node::new(parent.child_center(octant), 0.5 * parent.half_size);
+            self.nodes.push(child_node);
+            *child = self.nodes.len() as u32 - 1;
+        }
+        self.nodes[node as usize].children = Some(children);
+        for splat_index in std::mem::take(&mut self.nodes[node as usize].splat_indices) {
+            self.place_splat(node, splat_index);
+        }
+    }
+
+    /// Takes a splat out of the index, keeping the indices of the other splats
+    pub fn remove_splat(&mut self, splat_index: usize) {
+        let splat_indices = match self.splat_nodes.get(splat_index) {
+            Some(&NOT_INDEXED) | None => return,
+            Some(&UNBOUNDED) => &mut self.unbounded_splat_indices,
+            Some(&node) => &mut self.nodes[node as usize].splat_indices,
+        };
+        let position = splat_indices.iter().position(|index| *index as usize == splat_index).unwrap();
+        splat_indices.swap_remove(position);
+        self.splat_nodes[splat_index] = NOT_INDEXED;
+        self.indexed_splat_count -= 1;
+    }
+
+    /// Removes splats and shifts the indices of the following ones down, like [Scene::remove_splats](crate::scene::Scene::remove_splats)
+    pub fn remove_splats(&mut self, splat_indices: &[usize]) {
+        let mut removed = vec![false; self.splat_nodes.len()];
+        for splat_index in splat_indices {
+            if *splat_index < removed.len() {
+                self.remove_splat(*splat_index);
+                removed[*splat_index] = true;
+            }
+        }
+        // Number of kept splats before each splat index
+        let mut new_splat_indices = Vec::with_capacity(removed.len());
+        let mut kept_splat_count = 0;
+        for is_removed in &removed {
+            new_splat_indices.push(kept_splat_count);
+            kept_splat_count += !is_removed as u32;
+        }
+        for splat_indices in self
+            .nodes
+            .iter_mut()
+            .map(|node| &mut node.splat_indices)
+            .chain(std::iter::once(&mut self.unbounded_splat_indices))
+        {
+            for splat_index in splat_indices.iter_mut() {
+                *splat_index = new_splat_indices[*splat_index as usize];
+            }
+        }
+        let mut is_removed = removed.iter();
+        self.bounding_boxes.retain(|_| !is_removed.next().unwrap());
+        let mut is_removed = removed.iter();
+        self.splat_nodes.retain(|_| !is_removed.next().unwrap());
+    }
+
+    /// Shifts the indices of the splats up to leave the given ascending indices unindexed, inverting [SpatialIndex::remove_splats]
+    pub fn insert_gaps(&mut self, splat_indices: &[usize]) {
+        let mut new_splat_indices = Vec::with_capacity(self.splat_nodes.len());
+        let mut bounding_boxes = Vec::with_capacity(self.bounding_boxes.len() + splat_indices.len());
+        let mut splat_nodes = Vec::with_capacity(self.splat_nodes.len() + splat_indices.len());
+        let mut gaps = splat_indices.iter().peekable();
+        for (bounding_box, splat_node) in self.bounding_boxes.iter().zip(self.splat_nodes.iter()) {
+            while gaps.next_if(|splat_index| **splat_index == splat_nodes.len()).is_some() {
+                bounding_boxes.push([[0.0; 3]; 2]);
+                splat_nodes.push(NOT_INDEXED);
+            }
+            new_splat_indices.push(splat_nodes.len() as u32);
+            bounding_boxes.push(*bounding_box);
+            splat_nodes.push(*splat_node);
+        }
+        for splat_indices in self
+            .nodes
+            .iter_mut()
+            .map(|node| &mut node.splat_indices)
+            .chain(std::iter::once(&mut self.unbounded_splat_indices))
+        {
+            for splat_index in splat_indices.iter_mut() {
+                *splat_index = new_splat_indices[*splat_index as usize];
+            }
+        }
+        self.bounding_boxes = bounding_boxes;
+        self.splat_nodes = splat_nodes;
+    }
+
+    /// Collects the splats whose bounding boxes pass `intersects`, which also has to pass for the enclosing nodes
+    fn query<F: Fn(&BoundingBox) -> bool>(&self, intersects: F) -> Vec<usize> {
+        let mut found: Vec<usize> = self
+            .unbounded_splat_indices
+            .iter()
+            .map(|splat_index| *splat_index as usize)
+            .filter(|splat_index| intersects(&self.bounding_boxes[*splat_index]))
+            .collect();
+        let mut stack: Vec<u32> = self.root.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node as usize];
+            if !intersects(&node.loose_bounding_box()) {
+                continue;
+            }
+            found.extend(
+                node.splat_indices
+                    .iter()
+                    .map(|splat_index| *splat_index as usize)
+                    .filter(|splat_index| intersects(&self.bounding_boxes[*splat_index])),
+            );
+            stack.extend(node.children.iter().flatten());
+        }
+        found.sort_unstable();
+        found
+    }
+
+    /// Splats whose bounding boxes intersect the box, in ascending order
+    pub fn query_box(&self, bounding_box: &BoundingBox) -> Vec<usize> {
+        self.query(|other| boxes_intersect(bounding_box, other))
+    }
+
+    /// Splats whose bounding boxes intersect the sphere, in ascending order
+    pub fn query_sphere(&self, center: [f32; 3], radius: f32) -> Vec<usize> {
+        self.query(|bounding_box| box_intersects_sphere(bounding_box, center, radius))
+    }
+
+    /// Splats whose bounding boxes may intersect the frustum, in ascending order
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(|bounding_box| frustum.intersects_box(bounding_box))
+    }
+
+    /// Splats whose bounding boxes the ray hits, with the distance at which it enters them in units of `direction`
+    ///
+    /// Sorted front to back.
+    pub fn query_ray(&self, origin: [f32; 3], direction: [f32; 3]) -> Vec<(usize, f32)> {
+        self.query_ray_within(origin, direction, SIGMA_COUNT, 0.0)
+    }
+
+    /// Like [SpatialIndex::query_ray] with the bounding boxes enlarged to `sigma_count` standard deviations, if that is more than three
+    ///
+    /// The boxes are also padded by `spread` times their farthest distance from the origin,
+    /// for splats drawn with a minimum size on screen.
+    pub fn query_ray_within(&self, origin: [f32; 3], direction: [f32; 3], sigma_count: f32, spread: f32) -> Vec<(usize, f32)> {
+        // Enlarging every box around its center and padding it by its farthest distance
+        // keeps the boxes of the splats inside the boxes of their nodes
+        let factor = (sigma_count / SIGMA_COUNT).max(1.0);
+        let enlarge = |bounding_box: &BoundingBox| {
+            let (center, _) = center_and_half_extent(bounding_box);
+            let scaled_box = [0, 1].map(|corner| [0, 1, 2].map(|axis| center[axis] + (bounding_box[corner][axis] - center[axis]) * factor));
+            let farthest_offset = [0, 1, 2].map(|axis| (origin[axis] - scaled_box[0][axis]).abs().max((scaled_box[1][axis] - origin[axis]).abs()));
+            let farthest_distance = farthest_offset.iter().map(|offset| offset * offset).sum::<f32>().sqrt();
+            let padding = if spread > 0.0 { spread * farthest_distance } else { 0.0 };
+            [scaled_box[0].map(|value| value - padding), scaled_box[1].map(|value| value + padding)]
+        };
+        let mut hits: Vec<(usize, f32)> = self
+            .query(|bounding_box| ray_entry_distance(&enlarge(bounding_box), origin, direction).is_some())
+            .into_iter()
+            .map(|splat_index| {
+                (
+                    splat_index,
+                    ray_entry_distance(&enlarge(&self.bounding_boxes[splat_index]), origin, direction).unwrap(),
+                )
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splat_at(center: [f32; 3], scale: f32) -> GpuSplat {
+        GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center,
+            scale: [scale; 3],
+            ..GpuSplat::default()
+        }
+    }
+
+    /// Splats on a 10 x 10 x 10 grid with unit spacing, growing in size along x
+    fn grid_splats() -> Vec<GpuSplat> {
+        (0..1000)
+            .map(|index| {
+                splat_at(
+                    [(index % 10) as f32, (index / 10 % 10) as f32, (index / 100) as f32],
+                    0.01 * (index % 10 + 1) as f32,
+                )
+            })
+            .collect()
+    }
+
+    fn brute_force<F: Fn(&BoundingBox) -> bool>(splat_data: &[GpuSplat], intersects: F) -> Vec<usize> {
+        (0..splat_data.len())
+            .filter(|index| intersects(&splat_bounding_box(&splat_data[*index])))
+            .collect()
+    }
+
+    #[test]
+    fn bounding_box_of_rotated_splat() {
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        // Rotated by 90 degrees around z, so the long axis points along y
+        let splat = GpuSplat {
+            rotation: [h, 0.0, 0.0, h],
+            center: [1.0, 2.0, 3.0],
+            scale: [1.0, 0.1, 0.2],
+            ..GpuSplat::default()
+        };
+        let bounding_box = splat_bounding_box(&splat);
+        let expected = [[0.7, -1.0, 2.4], [1.3, 5.0, 3.6]];
+        for corner in 0..2 {
+            for axis in 0..3 {
+                assert!((bounding_box[corner][axis] - expected[corner][axis]).abs() < 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let splat_data = grid_splats();
+        let mut spatial_index = SpatialIndex::new();
+        // Inserted in chunks, so that the root has to grow
+        for chunk_start in (0..1000).step_by(70) {
+            spatial_index.insert_splats(chunk_start, &splat_data[chunk_start..(chunk_start + 70).min(1000)]);
+        }
+        assert_eq!(spatial_index.len(), 1000);
+        let query_box = [[1.5, 2.0, -1.0], [4.0, 3.05, 2.5]];
+        assert_eq!(
+            spatial_index.query_box(&query_box),
+            brute_force(&splat_data, |other| boxes_intersect(&query_box, other))
+        );
+        assert_eq!(
+            spatial_index.query_sphere([5.0, 5.0, 5.0], 2.1),
+            brute_force(&splat_data, |other| box_intersects_sphere(other, [5.0, 5.0, 5.0], 2.1))
+        );
+        let ray_hits = spatial_index.query_ray([-1.0, 3.0, 4.0], [1.0, 0.0, 0.0]);
+        assert_eq!(ray_hits.iter().map(|hit| hit.0).collect::<Vec<_>>(), (430..440).collect::<Vec<_>>());
+        assert!((ray_hits[0].1 - 0.97).abs() < 1.0e-5);
+        // Looking down the negative z axis from above the grid, with an orthographic projection of the square x, y in [0, 4]
+        let view_projection = [
+            Point::new(0.5, 0.0, 0.0, 0.0),
+            Point::new(0.0, 0.5, 0.0, 0.0),
+            Point::new(0.0, 0.0, -0.1, 0.0),
+            Point::new(-1.0, -1.0, 1.0, 1.0),
+        ];
+        let frustum = Frustum::from_view_projection(&view_projection, 1.0);
+        assert!(frustum.contains_point([2.0, 2.0, 5.0]));
+        assert!(!frustum.contains_point([2.0, 2.0, 11.0]));
+        assert!(!frustum.contains_point([5.0, 2.0, 5.0]));
+        assert_eq!(
+            spatial_index.query_frustum(&frustum),
+            brute_force(&splat_data, |other| frustum.intersects_box(other))
+        );
+    }
+
+    #[test]
+    fn updates_and_removals() {
+        let mut splat_data = grid_splats();
+        let mut spatial_index = SpatialIndex::new();
+        spatial_index.insert_splats(0, &splat_data);
+        // Moves a splat far away
+        splat_data[5] = splat_at([100.0, 0.0, 0.0], 1.0);
+        spatial_index.insert_splats(5, &splat_data[5..6]);
+        assert_eq!(spatial_index.query_sphere([100.0, 0.0, 0.0], 1.0), vec![5]);
+        splat_data.push(splat_at([0.0; 3], f32::INFINITY));
+        spatial_index.insert_splats(1000, &splat_data[1000..]);
+        assert_eq!(spatial_index.len(), 1001);
+        assert!(spatial_index.query_sphere([50.0, 50.0, 50.0], 1.0).contains(&1000));
+        let removed_splat_indices = [0, 5, 17, 999];
+        spatial_index.remove_splats(&removed_splat_indices);
+        for splat_index in removed_splat_indices.iter().rev() {
+            splat_data.remove(*splat_index);
+        }
+        assert_eq!(spatial_index.len(), 997);
+        assert_eq!(spatial_index.bounding_box(0), Some(splat_bounding_box(&splat_data[0])));
+        let query_box = [[0.0, 0.0, 0.0], [9.0, 1.0, 9.0]];
+        assert_eq!(
+            spatial_index.query_box(&query_box),
+            brute_force(&splat_data, |other| boxes_intersect(&query_box, other))
+        );
+        // Only the splat of infinite size is left there
+        assert_eq!(spatial_index.query_sphere([100.0, 0.0, 0.0], 1.0), vec![996]);
+    }
+}
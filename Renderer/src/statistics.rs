@@ -0,0 +1,213 @@
+//! Summary statistics of scenes, to check captures before rendering them
+use crate::{
+    codebook::CODEBOOK_ENTRY_SIZE,
+    scene::{GpuSplat, Scene},
+};
+
+/// Number of bins of the histograms in [SceneStatistics]
+const HISTOGRAM_BIN_COUNT: usize = 10;
+
+/// The attributes of a splat the statistics are computed from, kept on the CPU while loading
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SplatSummary {
+    pub(crate) center: [f32; 3],
+    alpha: f32,
+    largest_scale: f32,
+    smallest_scale: f32,
+    has_higher_order_spherical_harmonics: bool,
+}
+
+impl From<&GpuSplat> for SplatSummary {
+    fn from(splat: &GpuSplat) -> Self {
+        Self {
+            center: splat.center,
+            alpha: splat.alpha,
+            largest_scale: splat.scale.iter().fold(0.0f32, |largest, scale| largest.max(*scale)),
+            smallest_scale: splat.scale.iter().fold(f32::INFINITY, |smallest, scale| smallest.min(*scale)),
+            has_higher_order_spherical_harmonics: splat.color[3..].iter().any(|coefficient| *coefficient != 0.0),
+        }
+    }
+}
+
+/// Counts of values in equally wide bins between `min` and `max`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    /// The bins are equally wide in log10 space
+    pub logarithmic: bool,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    fn new<I: Iterator<Item = f32> + Clone>(values: I, logarithmic: bool) -> Self {
+        let transform = |value: f32| if logarithmic { value.log10() } else { value };
+        let finite_values = values.map(transform).filter(|value| value.is_finite());
+        let (min, max) = finite_values
+            .clone()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)));
+        let mut counts = vec![0; HISTOGRAM_BIN_COUNT];
+        if min <= max {
+            let bin_width = (max - min) / HISTOGRAM_BIN_COUNT as f32;
+            for value in finite_values {
+                let bin = if bin_width > 0.0 { ((value - min) / bin_width) as usize } else { 0 };
+                counts[bin.min(HISTOGRAM_BIN_COUNT - 1)] += 1;
+            }
+        }
+        let inverse_transform = |value: f32| if logarithmic { 10.0f32.powf(value) } else { value };
+        Self {
+            min: inverse_transform(min),
+            max: inverse_transform(max),
+            logarithmic,
+            counts,
+        }
+    }
+
+    /// Lower and upper bound of a bin
+    pub fn bin_bounds(&self, bin: usize) -> [f32; 2] {
+        let transform = |value: f32| if self.logarithmic { value.log10() } else { value };
+        let inverse_transform = |value: f32| if self.logarithmic { 10.0f32.powf(value) } else { value };
+        let (min, max) = (transform(self.min), transform(self.max));
+        let bin_width = (max - min) / self.counts.len() as f32;
+        [
+            inverse_transform(min + bin as f32 * bin_width),
+            inverse_transform(min + (bin + 1) as f32 * bin_width),
+        ]
+    }
+}
+
+impl std::fmt::Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let largest_count = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (bin, count) in self.counts.iter().enumerate() {
+            let [lower, upper] = self.bin_bounds(bin);
+            writeln!(
+                f,
+                "  {:>12.5} .. {:<12.5} {:>9} {}",
+                lower,
+                upper,
+                count,
+                "#".repeat(count * 40 / largest_count)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary of the loaded splats of a [Scene]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneStatistics {
+    pub splat_count: usize,
+    /// Minimum and maximum of the splat centers
+    pub bounding_box: [[f32; 3]; 2],
+    /// 1st and 99th percentile of the splat centers along each axis, which ignores far away floaters
+    pub robust_bounds: [[f32; 3]; 2],
+    pub centroid: [f32; 3],
+    pub opacity_histogram: Histogram,
+    /// Histogram of the largest scale of each splat
+    pub scale_histogram: Histogram,
+    /// Histogram of the ratio of the largest to the smallest scale of each splat
+    pub anisotropy_histogram: Histogram,
+    /// Number of splats with any non-zero higher order spherical harmonics coefficient
+    pub higher_order_splat_count: usize,
+    /// Estimated size of the GPU buffers of the scene in bytes, without those shared through the renderer
+    pub gpu_memory: usize,
+}
+
+impl SceneStatistics {
+    pub(crate) fn new(splat_summaries: &[SplatSummary], gpu_memory: usize) -> Self {
+        let splat_count = splat_summaries.len();
+        let mut bounding_box = [[f32::INFINITY; 3], [f32::NEG_INFINITY; 3]];
+        let mut robust_bounds = [[0.0; 3]; 2];
+        let mut centroid = [0.0; 3];
+        for axis in 0..3 {
+            let mut coordinates: Vec<f32> = splat_summaries.iter().map(|summary| summary.center[axis]).collect();
+            if coordinates.is_empty() {
+                bounding_box[0][axis] = 0.0;
+                bounding_box[1][axis] = 0.0;
+                continue;
+            }
+            for coordinate in &coordinates {
+                bounding_box[0][axis] = bounding_box[0][axis].min(*coordinate);
+                bounding_box[1][axis] = bounding_box[1][axis].max(*coordinate);
+            }
+            centroid[axis] = (coordinates.iter().map(|coordinate| *coordinate as f64).sum::<f64>() / splat_count as f64) as f32;
+            for (bound, percentile) in robust_bounds.iter_mut().zip([0.01, 0.99]) {
+                let index = ((splat_count - 1) as f64 * percentile).round() as usize;
+                bound[axis] = *coordinates.select_nth_unstable_by(index, f32::total_cmp).1;
+            }
+        }
+        Self {
+            splat_count,
+            bounding_box,
+            robust_bounds,
+            centroid,
+            opacity_histogram: Histogram::new(splat_summaries.iter().map(|summary| summary.alpha), false),
+            scale_histogram: Histogram::new(splat_summaries.iter().map(|summary| summary.largest_scale), true),
+            anisotropy_histogram: Histogram::new(splat_summaries.iter().map(|summary| summary.largest_scale / summary.smallest_scale), true),
+            higher_order_splat_count: splat_summaries
+                .iter()
+                .filter(|summary| summary.has_higher_order_spherical_harmonics)
+                .count(),
+            gpu_memory,
+        }
+    }
+}
+
+impl std::fmt::Display for SceneStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "splats: {}", self.splat_count)?;
+        writeln!(f, "bounding box: {:?} .. {:?}", self.bounding_box[0], self.bounding_box[1])?;
+        writeln!(f, "1st - 99th percentile: {:?} .. {:?}", self.robust_bounds[0], self.robust_bounds[1])?;
+        writeln!(f, "centroid: {:?}", self.centroid)?;
+        writeln!(f, "splats with higher order spherical harmonics: {}", self.higher_order_splat_count)?;
+        writeln!(f, "estimated GPU memory: {:.1} MiB", self.gpu_memory as f64 / (1024.0 * 1024.0))?;
+        write!(f, "opacity:\n{}", self.opacity_histogram)?;
+        write!(f, "largest scale:\n{}", self.scale_histogram)?;
+        write!(f, "anisotropy:\n{}", self.anisotropy_histogram)
+    }
+}
+
+impl Scene {
+    /// Summarizes the loaded splats without reading them back from the GPU
+    pub fn statistics(&self) -> SceneStatistics {
+        let codebook_size = self.spherical_harmonics_codebook().map(|codebook| codebook.entries.len()).unwrap_or(1);
+        let capacity = self.capacity().max(1);
+        // Splats, two entry buffers for sorting and the radii
+        let gpu_memory = capacity * (self.splat_size() + 2 * std::mem::size_of::<(u32, u32)>() + std::mem::size_of::<f32>())
+            + codebook_size * CODEBOOK_ENTRY_SIZE * std::mem::size_of::<f32>();
+        SceneStatistics::new(&self.splat_summaries[0..self.loaded_splat_count], gpu_memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_of_splats() {
+        let splat_summaries: Vec<SplatSummary> = (0..101)
+            .map(|index| {
+                let mut splat = GpuSplat {
+                    center: [index as f32, 0.0, -(index as f32)],
+                    scale: [0.1, 0.1, 0.1 * (index + 1) as f32],
+                    alpha: index as f32 / 100.0,
+                    ..GpuSplat::default()
+                };
+                splat.color[3] = (index % 2) as f32;
+                SplatSummary::from(&splat)
+            })
+            .collect();
+        let statistics = SceneStatistics::new(&splat_summaries, 0);
+        assert_eq!(statistics.bounding_box, [[0.0, 0.0, -100.0], [100.0, 0.0, 0.0]]);
+        assert_eq!(statistics.robust_bounds, [[1.0, 0.0, -99.0], [99.0, 0.0, -1.0]]);
+        assert_eq!(statistics.centroid, [50.0, 0.0, -50.0]);
+        assert_eq!(statistics.higher_order_splat_count, 50);
+        assert_eq!(statistics.opacity_histogram.counts.iter().sum::<usize>(), 101);
+        assert_eq!(statistics.opacity_histogram.counts[0], 10);
+        assert_eq!(statistics.opacity_histogram.counts[9], 11);
+        assert!((statistics.anisotropy_histogram.max - 101.0).abs() < 1.0e-3);
+        let [lower, upper] = statistics.scale_histogram.bin_bounds(0);
+        assert!((lower - 0.1).abs() < 1.0e-5 && upper > lower);
+    }
+}
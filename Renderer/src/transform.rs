@@ -0,0 +1,327 @@
+//! Rigid and similarity transforms of splats
+//!
+//! Besides the centers, rotations and scales, the view dependent colors have to be rotated:
+//! each band of spherical harmonics is transformed by its real Wigner-D matrix.
+use crate::scene::GpuSplat;
+
+/// Real spherical harmonics basis of degree 1 to 3, in the order and normalization of `sphericalHarmonicsLookup` in the shaders
+fn spherical_harmonics_basis(direction: [f64; 3]) -> [f64; 15] {
+    let [x, y, z] = direction;
+    let (xx, yy, zz) = (x * x, y * y, z * z);
+    [
+        -0.4886025119029199 * y,
+        0.4886025119029199 * z,
+        -0.4886025119029199 * x,
+        1.0925484305920792 * x * y,
+        -1.0925484305920792 * y * z,
+        0.31539156525252005 * (2.0 * zz - xx - yy),
+        -1.0925484305920792 * x * z,
+        0.5462742152960396 * (xx - yy),
+        -0.5900435899266435 * y * (3.0 * xx - yy),
+        2.890611442640554 * x * y * z,
+        -0.4570457994644658 * y * (4.0 * zz - xx - yy),
+        0.3731763325901154 * z * (2.0 * zz - 3.0 * xx - 3.0 * yy),
+        -0.4570457994644658 * x * (4.0 * zz - xx - yy),
+        1.445305721320277 * z * (xx - yy),
+        -0.5900435899266435 * x * (xx - 3.0 * yy),
+    ]
+}
+
+/// Inverts a small symmetric positive definite matrix by Gauss-Jordan elimination
+fn invert<const N: usize>(mut matrix: [[f64; N]; N]) -> [[f64; N]; N] {
+    let mut inverse = [[0.0; N]; N];
+    for (index, row) in inverse.iter_mut().enumerate() {
+        row[index] = 1.0;
+    }
+    for pivot in 0..N {
+        let pivot_value = matrix[pivot][pivot];
+        for column in 0..N {
+            matrix[pivot][column] /= pivot_value;
+            inverse[pivot][column] /= pivot_value;
+        }
+        for row in 0..N {
+            if row != pivot {
+                let factor = matrix[row][pivot];
+                for column in 0..N {
+                    matrix[row][column] -= factor * matrix[pivot][column];
+                    inverse[row][column] -= factor * inverse[pivot][column];
+                }
+            }
+        }
+    }
+    inverse
+}
+
+/// Multiplies a column vector by a row major 3x3 matrix
+pub(crate) fn rotate(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|row| matrix[row][0] * vector[0] + matrix[row][1] * vector[1] + matrix[row][2] * vector[2])
+}
+
+/// Row major rotation matrix of a unit quaternion (w, x, y, z)
+pub(crate) fn rotation_matrix(rotation: [f32; 4]) -> [[f64; 3]; 3] {
+    rotation_matrix_f64(rotation.map(|component| component as f64))
+}
+
+/// Like [rotation_matrix], for quaternions stored in double precision such as the ones of COLMAP
+pub(crate) fn rotation_matrix_f64(rotation: [f64; 4]) -> [[f64; 3]; 3] {
+    let [w, x, y, z] = rotation;
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+    ]
+}
+
+/// Hamilton product of two quaternions (w, x, y, z)
+fn quaternion_product(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+/// Wigner-D matrices rotating the spherical harmonics of degree 1 to 3
+///
+/// As the basis of [spherical_harmonics_basis] is not the textbook one, the matrices are fitted:
+/// evaluating the basis at rotated sample directions yields the matrix of each band by least squares.
+pub struct SphericalHarmonicsRotation {
+    /// Maps the original coefficients of one color channel to the rotated ones, block diagonal by band
+    matrix: [[f32; 15]; 15],
+}
+
+impl SphericalHarmonicsRotation {
+    const SAMPLE_COUNT: usize = 64;
+
+    /// Rotation by a unit quaternion (w, x, y, z)
+    pub fn new(rotation: [f32; 4]) -> Self {
+        let rotation = rotation_matrix(rotation);
+        let mut matrix = [[0.0; 15]; 15];
+        Self::fit_band::<3>(&rotation, 0, &mut matrix);
+        Self::fit_band::<5>(&rotation, 3, &mut matrix);
+        Self::fit_band::<7>(&rotation, 8, &mut matrix);
+        Self { matrix }
+    }
+
+    /// Fits `matrix[offset..offset + N][offset..offset + N]` so that the rotated coefficients `c'` satisfy `f'(R d) = f(d)`
+    fn fit_band<const N: usize>(rotation: &[[f64; 3]; 3], offset: usize, matrix: &mut [[f32; 15]; 15]) {
+        // With B(d) = K B(R d) for all directions d, f'(R d) = c'^T B(R d) = c^T B(d) gives c' = K^T c
+        let mut gram = [[0.0; N]; N];
+        let mut cross = [[0.0; N]; N];
+        for sample_index in 0..Self::SAMPLE_COUNT {
+            // Fibonacci sphere
+            let z = 1.0 - (2 * sample_index + 1) as f64 / Self::SAMPLE_COUNT as f64;
+            let radius = (1.0 - z * z).sqrt();
+            let angle = sample_index as f64 * std::f64::consts::PI * (3.0 - 5.0f64.sqrt());
+            let direction = [radius * angle.cos(), radius * angle.sin(), z];
+            let original_basis = spherical_harmonics_basis(direction);
+            let rotated_basis = spherical_harmonics_basis(rotate(rotation, direction));
+            for row in 0..N {
+                for column in 0..N {
+                    gram[row][column] += rotated_basis[offset + row] * rotated_basis[offset + column];
+                    cross[row][column] += rotated_basis[offset + row] * original_basis[offset + column];
+                }
+            }
+        }
+        // The least squares fit is K = cross^T gram^-1, so K^T = gram^-1 cross as the gram matrix is symmetric
+        let inverse_gram = invert(gram);
+        for row in 0..N {
+            for column in 0..N {
+                matrix[offset + row][offset + column] = (0..N).map(|index| inverse_gram[row][index] * cross[index][column]).sum::<f64>() as f32;
+            }
+        }
+    }
+
+    /// Rotates the higher order coefficients of a splat, interleaved as in [GpuSplat::color] without the base color
+    pub fn apply(&self, coefficients: &mut [f32]) {
+        for channel in 0..3 {
+            let original: [f32; 15] = std::array::from_fn(|index| coefficients[index * 3 + channel]);
+            for (row, matrix_row) in self.matrix.iter().enumerate() {
+                coefficients[row * 3 + channel] = matrix_row.iter().zip(original.iter()).map(|(factor, value)| factor * value).sum();
+            }
+        }
+    }
+}
+
+/// A rotation followed by a uniform scale and a translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Similarity {
+    /// Unit quaternion (w, x, y, z)
+    pub rotation: [f32; 4],
+    pub translation: [f32; 3],
+    pub scale: f32,
+}
+
+impl Default for Similarity {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Similarity {
+    pub fn identity() -> Self {
+        Self {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            translation: [0.0; 3],
+            scale: 1.0,
+        }
+    }
+
+    /// A rotation followed by a translation
+    pub fn rigid(rotation: [f32; 4], translation: [f32; 3]) -> Self {
+        Self {
+            rotation,
+            translation,
+            scale: 1.0,
+        }
+    }
+
+    /// Decomposes a column major 4x4 matrix with a uniformly scaled rotation, as used in [crate::utils] and by COLMAP alignments
+    ///
+    /// Returns `None` if the upper 3x3 part is not a positively scaled rotation.
+    pub fn from_matrix(matrix: &[[f32; 4]; 4]) -> Option<Self> {
+        let column = |index: usize| [matrix[index][0] as f64, matrix[index][1] as f64, matrix[index][2] as f64];
+        let length = |vector: [f64; 3]| (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+        let (x, y, z) = (column(0), column(1), column(2));
+        let determinant = x[0] * (y[1] * z[2] - z[1] * y[2]) - y[0] * (x[1] * z[2] - z[1] * x[2]) + z[0] * (x[1] * y[2] - y[1] * x[2]);
+        if determinant <= 0.0 {
+            return None;
+        }
+        let scale = determinant.cbrt();
+        for axis in [x, y, z] {
+            if (length(axis) / scale - 1.0).abs() > 1.0e-3 {
+                return None;
+            }
+        }
+        // Row major rotation matrix
+        let r = [0, 1, 2].map(|row| [x[row] / scale, y[row] / scale, z[row] / scale]);
+        let trace = r[0][0] + r[1][1] + r[2][2];
+        let rotation = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            [0.25 * s, (r[2][1] - r[1][2]) / s, (r[0][2] - r[2][0]) / s, (r[1][0] - r[0][1]) / s]
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = (1.0 + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.0;
+            [(r[2][1] - r[1][2]) / s, 0.25 * s, (r[0][1] + r[1][0]) / s, (r[0][2] + r[2][0]) / s]
+        } else if r[1][1] > r[2][2] {
+            let s = (1.0 + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.0;
+            [(r[0][2] - r[2][0]) / s, (r[0][1] + r[1][0]) / s, 0.25 * s, (r[1][2] + r[2][1]) / s]
+        } else {
+            let s = (1.0 + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.0;
+            [(r[1][0] - r[0][1]) / s, (r[0][2] + r[2][0]) / s, (r[1][2] + r[2][1]) / s, 0.25 * s]
+        };
+        Some(Self {
+            rotation: rotation.map(|component| component as f32),
+            translation: [matrix[3][0], matrix[3][1], matrix[3][2]],
+            scale: scale as f32,
+        })
+    }
+
+    /// Applies `self` after `other`
+    pub fn then(&self, other: &Self) -> Self {
+        let translation = self.transform_point(other.translation);
+        Self {
+            rotation: quaternion_product(self.rotation, other.rotation),
+            translation,
+            scale: self.scale * other.scale,
+        }
+    }
+
+    pub fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let rotated = rotate(&rotation_matrix(self.rotation), point.map(|component| component as f64));
+        [0, 1, 2].map(|axis| rotated[axis] as f32 * self.scale + self.translation[axis])
+    }
+
+    /// Transforms the centers, rotations and scales of the splats and rotates their spherical harmonics
+    pub fn transform_splats(&self, splat_data: &mut [GpuSplat]) {
+        let spherical_harmonics_rotation = SphericalHarmonicsRotation::new(self.rotation);
+        for splat in splat_data {
+            splat.center = self.transform_point(splat.center);
+            let rotation = quaternion_product(self.rotation, splat.rotation);
+            let norm = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+            splat.rotation = rotation.map(|component| component / norm);
+            splat.scale = splat.scale.map(|scale| scale * self.scale);
+            spherical_harmonics_rotation.apply(&mut splat.color[3..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(coefficients: &[f32], direction: [f64; 3]) -> f64 {
+        spherical_harmonics_basis(direction)
+            .iter()
+            .enumerate()
+            .map(|(index, basis)| basis * coefficients[index * 3] as f64)
+            .sum()
+    }
+
+    #[test]
+    fn rotate_spherical_harmonics() {
+        let rotation = [0.8f32, 0.2, -0.4, 0.4];
+        let norm = rotation.iter().map(|component| component * component).sum::<f32>().sqrt();
+        let rotation = rotation.map(|component| component / norm);
+        let coefficients: Vec<f32> = (0..45).map(|index| ((index * 7 % 11) as f32 - 5.0) * 0.1).collect();
+        let mut rotated_coefficients = coefficients.clone();
+        SphericalHarmonicsRotation::new(rotation).apply(&mut rotated_coefficients);
+        let matrix = rotation_matrix(rotation);
+        for direction in [[1.0, 0.0, 0.0], [0.0, 0.6, 0.8], [-0.48, 0.6, -0.64]] {
+            let expected = evaluate(&coefficients, direction);
+            let rotated = evaluate(&rotated_coefficients, rotate(&matrix, direction));
+            assert!((expected - rotated).abs() < 1.0e-4, "{} became {}", expected, rotated);
+        }
+    }
+
+    #[test]
+    fn similarity_from_matrix() {
+        let similarity = Similarity {
+            rotation: [0.5, 0.5, -0.5, 0.5],
+            translation: [1.0, 2.0, 3.0],
+            scale: 2.0,
+        };
+        let r = rotation_matrix(similarity.rotation);
+        let mut matrix = [[0.0; 4]; 4];
+        for column in 0..3 {
+            for row in 0..3 {
+                matrix[column][row] = r[row][column] as f32 * similarity.scale;
+            }
+            matrix[3][column] = similarity.translation[column];
+        }
+        matrix[3][3] = 1.0;
+        let decomposed = Similarity::from_matrix(&matrix).unwrap();
+        for (component, expected) in decomposed.rotation.iter().zip(similarity.rotation.iter()) {
+            assert!((component - expected).abs() < 1.0e-6);
+        }
+        assert_eq!(decomposed.translation, similarity.translation);
+        assert!((decomposed.scale - similarity.scale).abs() < 1.0e-6);
+        assert_eq!(similarity.transform_point([1.0, 0.0, 0.0]), [1.0, 2.0, 5.0]);
+        // Mirroring an axis is not a rotation
+        matrix[0] = matrix[0].map(|value| -value);
+        assert!(Similarity::from_matrix(&matrix).is_none());
+    }
+
+    #[test]
+    fn transform_splats() {
+        let similarity = Similarity::rigid([0.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0]).then(&Similarity {
+            scale: 3.0,
+            ..Similarity::identity()
+        });
+        let mut splat = GpuSplat {
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            center: [1.0, 2.0, 3.0],
+            scale: [0.1, 0.2, 0.3],
+            ..GpuSplat::default()
+        };
+        // Degree 1 coefficient of y, which becomes -y under a rotation by pi around z
+        splat.color[3] = 1.0;
+        similarity.transform_splats(std::slice::from_mut(&mut splat));
+        for (value, expected) in splat.center.iter().zip([-3.0, -6.0, 10.0].iter()) {
+            assert!((value - expected).abs() < 1.0e-5);
+        }
+        assert_eq!(splat.rotation, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(splat.scale.map(|scale| (scale * 10.0).round()), [3.0, 6.0, 9.0]);
+        assert!((splat.color[3] + 1.0).abs() < 1.0e-5);
+    }
+}
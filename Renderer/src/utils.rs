@@ -12,7 +12,7 @@ pub fn read_and_print_radii_buffer(device: &wgpu::Device, queue: &wgpu::Queue, r
                 println!("AHHAHAHAHHAHAHHAHHAHHAHAHHAA");
 
                 // Conversion du buffer en un tableau de f32
-                let radii_data: &[f32] = transmute_slice::<u8, f32>(&*buffer_data);
+                let radii_data: &[f32] = transmute_slice::<u8, f32>(&buffer_data);
                 // Affichage du contenu du buffer
                 
                 println!("Contenu du buffer radii_buffer : {:?}", radii_data);
@@ -88,3 +88,160 @@ pub fn mat4_multiplication(a: &[ppga3d::Point; 4], b: &[ppga3d::Point; 4]) -> [p
 pub fn mat4_transform(a: &[ppga3d::Point; 4], b: &ppga3d::Point) -> ppga3d::Point {
     a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
 }
+
+/// Converts a f32 to the bits of the nearest f16, like `pack2x16float` in WGSL
+pub fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+    if exponent == 0xFF {
+        // Infinity or NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // Subnormal, shift the mantissa including its implicit leading one
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1FFF;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // A carry out of the mantissa correctly increments the exponent, up to infinity
+    sign | (half + round_up as u32) as u16
+}
+
+/// Converts the bits of a f16 to a f32, like `unpack2x16float` in WGSL
+pub fn f16_bits_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1F) as u32;
+    let mantissa = (bits & 0x03FF) as u32;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, normalize the mantissa
+            let shift = mantissa.leading_zeros() - 21;
+            sign | ((127 - 15 + 1 - shift) << 23) | ((mantissa << shift) & 0x03FF) << 13
+        }
+        0x1F => sign | 0x7F80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Packs two f32 into a u32 of two f16, like `pack2x16float` in WGSL
+pub fn pack2x16float(values: [f32; 2]) -> u32 {
+    f32_to_f16_bits(values[0]) as u32 | (f32_to_f16_bits(values[1]) as u32) << 16
+}
+
+/// Unpacks a u32 of two f16, like `unpack2x16float` in WGSL
+pub fn unpack2x16float(packed: u32) -> [f32; 2] {
+    [f16_bits_to_f32(packed as u16), f16_bits_to_f32((packed >> 16) as u16)]
+}
+
+/// Creates a device and a renderer for the tests which need a GPU
+///
+/// Uses the backends selected by `WGPU_BACKEND` or else the primary ones.
+/// These tests are ignored unless they are run with `cargo test -- --include-ignored`, and fail without a suitable adapter.
+#[cfg(test)]
+pub(crate) fn test_renderer(depth_sorting: crate::renderer::DepthSorting) -> (wgpu::Device, wgpu::Queue, crate::renderer::Renderer) {
+    use crate::renderer::{Background, Configuration, Renderer, SplatLayout};
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::PRIMARY),
+        ..wgpu::InstanceDescriptor::default()
+    });
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).expect("No GPU adapter found");
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        },
+        None,
+    ))
+    .expect("Unable to create a device");
+    let renderer = Renderer::new(
+        &device,
+        Configuration {
+            surface_configuration: wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                view_formats: vec![wgpu::TextureFormat::Rgba8Unorm],
+                width: 64,
+                height: 64,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            },
+            depth_sorting,
+            use_covariance_for_scale: true,
+            use_unaligned_rectangles: true,
+            spherical_harmonics_order: 3,
+            max_splat_count: 1024,
+            radix_bits_per_digit: 8,
+            frustum_culling_tolerance: 1.5,
+            ellipse_margin: 2.0,
+            splat_scale: 1.0,
+            splat_layout: SplatLayout::Full,
+            background: Background::Transparent,
+        },
+    );
+    (device, queue, renderer)
+}
+
+/// Splats told apart by their x coordinate, which is their index
+#[cfg(test)]
+pub(crate) fn numbered_splats(splat_index_range: std::ops::Range<usize>) -> Vec<crate::scene::GpuSplat> {
+    splat_index_range
+        .map(|splat_index| crate::scene::GpuSplat {
+            center: [splat_index as f32, 0.0, 0.0],
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            ..crate::scene::GpuSplat::default()
+        })
+        .collect()
+}
+
+/// The numbers of all splats of the scene, see [numbered_splats]
+#[cfg(test)]
+pub(crate) fn splat_numbers(device: &wgpu::Device, queue: &wgpu::Queue, scene: &crate::scene::Scene) -> Vec<f32> {
+    let splat_data = scene.download_splats(device, queue, 0..scene.splat_count).unwrap();
+    splat_data.iter().map(|splat| splat.center[0]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_round_trip() {
+        for value in [0.0, -0.0, 1.0, -2.5, 0.1, 65504.0, 1.0e-5, 6.0e-8, 1.0e6] {
+            let bits = f32_to_f16_bits(value);
+            let round_trip = f16_bits_to_f32(bits);
+            if value.abs() > 65504.0 {
+                assert!(round_trip.is_infinite());
+            } else {
+                assert!(
+                    (round_trip - value).abs() <= value.abs() * 1.0e-3 + 6.0e-8,
+                    "{} became {}",
+                    value,
+                    round_trip
+                );
+            }
+        }
+        assert_eq!(f32_to_f16_bits(1.0), 0x3C00);
+        assert_eq!(f32_to_f16_bits(-2.0), 0xC000);
+        assert_eq!(f16_bits_to_f32(0x0001), 2.0f32.powi(-24));
+        assert_eq!(unpack2x16float(pack2x16float([0.5, -0.25])), [0.5, -0.25]);
+    }
+}